@@ -0,0 +1,184 @@
+use crate::cartridge::Region;
+
+/// Output periods in CPU cycles selected by $4010.
+const NTSC_RATES: [u16; 16] = [428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54];
+const PAL_RATES: [u16; 16] = [398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50];
+
+/// The delta modulation channel, $4010-$4013: plays 1 bit deltas fetched
+/// from CPU memory by DMA, or levels written straight to $4011.
+pub struct Dmc {
+    rates: &'static [u16; 16],
+    rate: u16,
+    timer: u16,
+
+    irq_enabled: bool,
+    looping: bool,
+    pub irq: bool,
+
+    /// $C000 + A * 64 and L * 16 + 1, from $4012 and $4013.
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+
+    shift_register: u8,
+    bits_remaining: u8,
+    silence: bool,
+    output_level: u8,
+}
+
+impl Dmc {
+    pub fn new() -> Self {
+        Self {
+            rates: &NTSC_RATES,
+            rate: NTSC_RATES[0],
+            timer: 0,
+            irq_enabled: false,
+            looping: false,
+            irq: false,
+            sample_address: 0xC000,
+            sample_length: 1,
+            current_address: 0xC000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift_register: 0,
+            bits_remaining: 8,
+            silence: true,
+            output_level: 0,
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.rates = match region {
+            Region::Pal => &PAL_RATES,
+            _ => &NTSC_RATES,
+        };
+    }
+
+    pub fn write(&mut self, register: u16, data: u8) {
+        match register {
+            // [IL.. RRRR]
+            0 => {
+                self.irq_enabled = data & 0x80 != 0;
+                self.looping = data & 0x40 != 0;
+                self.rate = self.rates[(data & 0x0F) as usize];
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+            }
+            // [.DDD DDDD]
+            1 => self.output_level = data & 0x7F,
+            2 => self.sample_address = 0xC000 | (data as u16) << 6,
+            _ => self.sample_length = ((data as u16) << 4) + 1,
+        }
+    }
+
+    /// $4015: starts the sample over if it had finished, or stops it. Either
+    /// way the DMC IRQ is acknowledged.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    /// Whether sample bytes are left to fetch, for $4015.
+    pub fn active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    /// The address the memory reader wants fetched, once the sample buffer
+    /// has been emptied into the shift register.
+    pub fn dma_address(&self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            Some(self.current_address)
+        } else {
+            None
+        }
+    }
+
+    /// Takes the byte the DMA fetched from `dma_address`.
+    pub fn fill_sample_buffer(&mut self, data: u8) {
+        self.sample_buffer = Some(data);
+        // The address wraps round to $8000, not $0000.
+        self.current_address = self.current_address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    /// Clocked every CPU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.rate - 1;
+
+        // The level only moves by 2 at a time, and stays put rather than wrap.
+        if !self.silence {
+            if self.shift_register & 0x01 != 0 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(data) => {
+                    self.silence = false;
+                    self.shift_register = data;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    /// The level, 0 to 127.
+    pub fn output(&self) -> u8 {
+        self.output_level
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_address_wraps_to_8000() {
+        let mut dmc = Dmc::new();
+        // 65 bytes from $FFC0.
+        dmc.write(2, 0xFF);
+        dmc.write(3, 0x04);
+        dmc.set_enabled(true);
+
+        for _ in 0..0x3F {
+            dmc.fill_sample_buffer(0);
+            dmc.sample_buffer = None;
+        }
+        assert_eq!(dmc.dma_address(), Some(0xFFFF));
+        dmc.fill_sample_buffer(0);
+        dmc.sample_buffer = None;
+        assert_eq!(dmc.dma_address(), Some(0x8000));
+    }
+}
@@ -0,0 +1,44 @@
+use std::f32::consts::PI;
+
+/// A first order RC filter, like the ones between the 2A03 and the
+/// console's audio jack.
+pub struct Filter {
+    high_pass: bool,
+    alpha: f32,
+    input: f32,
+    output: f32,
+}
+
+impl Filter {
+    pub fn high_pass(cutoff: f32, sample_rate: f32) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate;
+        Self {
+            high_pass: true,
+            alpha: rc / (rc + dt),
+            input: 0.0,
+            output: 0.0,
+        }
+    }
+
+    pub fn low_pass(cutoff: f32, sample_rate: f32) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate;
+        Self {
+            high_pass: false,
+            alpha: dt / (rc + dt),
+            input: 0.0,
+            output: 0.0,
+        }
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        if self.high_pass {
+            self.output = self.alpha * (self.output + input - self.input);
+        } else {
+            self.output += self.alpha * (input - self.output);
+        }
+        self.input = input;
+        self.output
+    }
+}
@@ -0,0 +1,34 @@
+/// The 2A03's non-linear mixer, as lookup tables indexed by the summed
+/// channel levels. Louder channels add less and less, so a loud triangle
+/// quietens the noise and DMC.
+pub struct Mixer {
+    pulse_table: [f32; 31],
+    tnd_table: [f32; 203],
+}
+
+impl Mixer {
+    pub fn new() -> Self {
+        let mut pulse_table = [0.0; 31];
+        for (n, level) in pulse_table.iter_mut().enumerate().skip(1) {
+            *level = 95.52 / (8128.0 / n as f32 + 100.0);
+        }
+
+        // Indexed by 3 * triangle + 2 * noise + DMC.
+        let mut tnd_table = [0.0; 203];
+        for (n, level) in tnd_table.iter_mut().enumerate().skip(1) {
+            *level = 163.67 / (24329.0 / n as f32 + 100.0);
+        }
+
+        Self {
+            pulse_table,
+            tnd_table,
+        }
+    }
+
+    /// Mixes the levels from `APU::channel_outputs`, to about 1.0 with
+    /// every channel at full volume.
+    pub fn mix(&self, outputs: [u8; 5]) -> f32 {
+        let [pulse1, pulse2, triangle, noise, dmc] = outputs.map(|level| level as usize);
+        self.pulse_table[pulse1 + pulse2] + self.tnd_table[3 * triangle + 2 * noise + dmc]
+    }
+}
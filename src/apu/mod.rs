@@ -0,0 +1,306 @@
+mod units;
+mod pulse;
+mod triangle;
+mod noise;
+mod dmc;
+mod mixer;
+mod filter;
+mod resampler;
+pub mod output;
+
+use crate::cartridge::Region;
+use self::dmc::Dmc;
+use self::mixer::Mixer;
+use self::noise::Noise;
+use self::pulse::Pulse;
+use self::triangle::Triangle;
+
+/// CPU cycles into the frame counter's sequence at which each step happens:
+/// three quarter frames, then the cycles around the end of the sequence.
+const NTSC_FRAME_STEPS: [u32; 4] = [7457, 14913, 22371, 29829];
+const PAL_FRAME_STEPS: [u32; 4] = [8313, 16627, 24939, 33253];
+
+/// The 5 step sequence adds a step of this many more cycles, and raises no IRQ.
+const NTSC_FIFTH_STEP: u32 = 37281;
+const PAL_FIFTH_STEP: u32 = 41565;
+
+enum Status {
+    FrameInterrupt      = (1 << 6),
+    DmcInterrupt        = (1 << 7),
+}
+
+/// The audio processing unit (2A03): two pulse channels, a triangle, noise
+/// and the delta modulation channel, stepped along by the frame counter.
+pub struct APU {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    mixer: Mixer,
+
+    frame_steps: [u32; 4],
+    fifth_step: u32,
+    frame_cycle: u32,
+    five_step_mode: bool,
+    irq_inhibit: bool,
+    frame_irq: bool,
+    /// CPU cycles until a $4017 write resets the sequence.
+    frame_reset_delay: u8,
+
+    /// Pulse and noise timers run at half the CPU clock.
+    odd_cycle: bool,
+}
+
+impl APU {
+    pub fn new() -> Self {
+        Self {
+            pulse1: Pulse::new(true),
+            pulse2: Pulse::new(false),
+            triangle: Triangle::new(),
+            noise: Noise::new(),
+            dmc: Dmc::new(),
+            mixer: Mixer::new(),
+            frame_steps: NTSC_FRAME_STEPS,
+            fifth_step: NTSC_FIFTH_STEP,
+            frame_cycle: 0,
+            five_step_mode: false,
+            irq_inhibit: false,
+            frame_irq: false,
+            frame_reset_delay: 0,
+            odd_cycle: false,
+        }
+    }
+
+    /// PAL consoles have their own frame counter, noise and DMC periods,
+    /// in CPU cycles. The Dendy keeps the NTSC timings.
+    pub fn set_region(&mut self, region: Region) {
+        (self.frame_steps, self.fifth_step) = match region {
+            Region::Pal => (PAL_FRAME_STEPS, PAL_FIFTH_STEP),
+            _ => (NTSC_FRAME_STEPS, NTSC_FIFTH_STEP),
+        };
+        self.noise.set_region(region);
+        self.dmc.set_region(region);
+    }
+
+    /// $4015: which channels are still playing, and the IRQ flags. Reading
+    /// clears the frame IRQ but not the DMC's.
+    pub fn read_status(&mut self, read_only: bool) -> u8 {
+        let mut data = 0;
+        data |= self.pulse1.length.active() as u8;
+        data |= (self.pulse2.length.active() as u8) << 1;
+        data |= (self.triangle.length.active() as u8) << 2;
+        data |= (self.noise.length.active() as u8) << 3;
+        data |= (self.dmc.active() as u8) << 4;
+        if self.frame_irq {
+            data |= Status::FrameInterrupt as u8;
+        }
+        if self.dmc.irq {
+            data |= Status::DmcInterrupt as u8;
+        }
+
+        if !read_only {
+            self.frame_irq = false;
+        }
+        data
+    }
+
+    pub fn cpu_write(&mut self, address: u16, data: u8) {
+        let register = address & 0x0003;
+        match address {
+            0x4000..=0x4003 => self.pulse1.write(register, data),
+            0x4004..=0x4007 => self.pulse2.write(register, data),
+            0x4008..=0x400B => self.triangle.write(register, data),
+            0x400C..=0x400F => self.noise.write(register, data),
+            0x4010..=0x4013 => self.dmc.write(register, data),
+            0x4015 => {
+                self.pulse1.length.set_enabled(data & 0x01 != 0);
+                self.pulse2.length.set_enabled(data & 0x02 != 0);
+                self.triangle.length.set_enabled(data & 0x04 != 0);
+                self.noise.length.set_enabled(data & 0x08 != 0);
+                self.dmc.set_enabled(data & 0x10 != 0);
+            }
+            // [MI.. ....]
+            0x4017 => {
+                self.five_step_mode = data & 0x80 != 0;
+                self.irq_inhibit = data & 0x40 != 0;
+                if self.irq_inhibit {
+                    self.frame_irq = false;
+                }
+                // The sequence restarts 3 or 4 CPU cycles later, depending
+                // on where the write falls in the APU's two cycle beat.
+                self.frame_reset_delay = if self.odd_cycle { 4 } else { 3 };
+            }
+            _ => {}
+        }
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse1.clock_quarter_frame();
+        self.pulse2.clock_quarter_frame();
+        self.triangle.clock_quarter_frame();
+        self.noise.clock_quarter_frame();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse1.clock_half_frame();
+        self.pulse2.clock_half_frame();
+        self.triangle.clock_half_frame();
+        self.noise.clock_half_frame();
+    }
+
+    fn set_frame_irq(&mut self) {
+        if !self.irq_inhibit {
+            self.frame_irq = true;
+        }
+    }
+
+    fn clock_frame_counter(&mut self) {
+        if self.frame_reset_delay > 0 {
+            self.frame_reset_delay -= 1;
+            if self.frame_reset_delay == 0 {
+                self.frame_cycle = 0;
+                // Entering the 5 step mode clocks everything at once.
+                if self.five_step_mode {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+                return;
+            }
+        }
+
+        self.frame_cycle += 1;
+        let [first, second, third, last] = self.frame_steps;
+        let cycle = self.frame_cycle;
+
+        if cycle == first || cycle == third {
+            self.clock_quarter_frame();
+        } else if cycle == second {
+            self.clock_quarter_frame();
+            self.clock_half_frame();
+        } else if self.five_step_mode {
+            if cycle == self.fifth_step {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            } else if cycle == self.fifth_step + 1 {
+                self.frame_cycle = 0;
+            }
+        } else if cycle == last - 1 {
+            self.set_frame_irq();
+        } else if cycle == last {
+            self.clock_quarter_frame();
+            self.clock_half_frame();
+            self.set_frame_irq();
+        } else if cycle == last + 1 {
+            self.set_frame_irq();
+            self.frame_cycle = 0;
+        }
+    }
+
+    /// Advances by one CPU cycle.
+    pub fn clock(&mut self) {
+        self.triangle.clock_timer();
+        self.dmc.clock_timer();
+        if self.odd_cycle {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+            self.noise.clock_timer();
+        }
+        self.odd_cycle = !self.odd_cycle;
+
+        self.clock_frame_counter();
+    }
+
+    /// The frame counter's IRQ on its own, for the event viewer.
+    pub fn frame_irq_state(&self) -> bool {
+        self.frame_irq
+    }
+
+    pub fn dmc_irq_state(&self) -> bool {
+        self.dmc.irq
+    }
+
+    /// Level of the frame counter's and DMC's IRQs, wired to the CPU's /IRQ line.
+    pub fn irq_state(&self) -> bool {
+        self.frame_irq || self.dmc.irq
+    }
+
+    /// The address the DMC wants read, when its sample buffer runs empty.
+    /// The bus halts the CPU to fetch it and hands it over with `dmc_dma_complete`.
+    pub fn dmc_dma_address(&self) -> Option<u16> {
+        self.dmc.dma_address()
+    }
+
+    pub fn dmc_dma_complete(&mut self, data: u8) {
+        self.dmc.fill_sample_buffer(data);
+    }
+
+    /// Each channel's level: pulse 1, pulse 2, triangle and noise from 0 to
+    /// 15, and the DMC from 0 to 127.
+    pub fn channel_outputs(&self) -> [u8; 5] {
+        [
+            self.pulse1.output(),
+            self.pulse2.output(),
+            self.triangle.output(),
+            self.noise.output(),
+            self.dmc.output(),
+        ]
+    }
+
+    /// The channels through the 2A03's non-linear mixer, from 0.0 to about 1.0.
+    pub fn output(&self) -> f32 {
+        self.mixer.mix(self.channel_outputs())
+    }
+
+    /// The reset button silences every channel, as if $4015 were written with 0.
+    pub fn reset(&mut self) {
+        self.cpu_write(0x4015, 0x00);
+        self.frame_irq = false;
+        self.frame_cycle = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(apu: &mut APU, cycles: u32) {
+        for _ in 0..cycles {
+            apu.clock();
+        }
+    }
+
+    #[test]
+    fn four_step_irq_is_raised_for_the_last_three_cycles() {
+        let mut apu = APU::new();
+        run(&mut apu, NTSC_FRAME_STEPS[3] - 2);
+        assert!(!apu.irq_state());
+
+        // Acknowledging it doesn't help until the sequence starts over.
+        for _ in 0..3 {
+            run(&mut apu, 1);
+            assert!(apu.irq_state());
+            apu.read_status(false);
+        }
+        run(&mut apu, 1);
+        assert!(!apu.irq_state());
+    }
+
+    #[test]
+    fn five_step_sequence_clocks_half_frames_at_its_second_and_fifth_steps() {
+        let mut apu = APU::new();
+        apu.cpu_write(0x4017, 0x80);
+        // Written on an even cycle, so the sequence restarts after 3.
+        run(&mut apu, 3);
+
+        // A length of 2, which runs out on the second half frame.
+        apu.cpu_write(0x4015, 0x01);
+        apu.cpu_write(0x4003, 0x18);
+
+        run(&mut apu, NTSC_FIFTH_STEP - 1);
+        assert_eq!(apu.read_status(true) & 0x01, 0x01);
+        run(&mut apu, 1);
+        assert_eq!(apu.read_status(true) & 0x01, 0x00);
+        assert!(!apu.irq_state());
+    }
+}
@@ -0,0 +1,91 @@
+use crate::cartridge::Region;
+use super::units::{Envelope, LengthCounter};
+
+/// Timer periods in CPU cycles selected by $400E.
+const NTSC_PERIODS: [u16; 16] = [4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068];
+const PAL_PERIODS: [u16; 16] = [4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778];
+
+/// The noise channel, $400C-$400F: a 15 bit linear feedback shift register.
+pub struct Noise {
+    periods: &'static [u16; 16],
+    period: u16,
+    timer: u16,
+    /// Takes feedback from bit 6 instead of bit 1, for a short, metallic
+    /// 93 step sequence instead of 32767 steps.
+    short_mode: bool,
+    shift_register: u16,
+
+    envelope: Envelope,
+    pub length: LengthCounter,
+}
+
+impl Noise {
+    pub fn new() -> Self {
+        Self {
+            periods: &NTSC_PERIODS,
+            period: NTSC_PERIODS[0],
+            timer: 0,
+            short_mode: false,
+            shift_register: 1,
+            envelope: Envelope::new(),
+            length: LengthCounter::new(),
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.periods = match region {
+            Region::Pal => &PAL_PERIODS,
+            _ => &NTSC_PERIODS,
+        };
+    }
+
+    pub fn write(&mut self, register: u16, data: u8) {
+        match register {
+            // [..LC VVVV]
+            0 => {
+                self.length.halt = data & 0x20 != 0;
+                self.envelope.write(data);
+            }
+            1 => {}
+            // [M... PPPP]
+            2 => {
+                self.short_mode = data & 0x80 != 0;
+                // The timer counts APU cycles, two CPU cycles each.
+                self.period = self.periods[(data & 0x0F) as usize] / 2;
+            }
+            _ => {
+                self.length.load(data >> 3);
+                self.envelope.restart();
+            }
+        }
+    }
+
+    /// Clocked every other CPU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period - 1;
+
+        let tap = if self.short_mode { 6 } else { 1 };
+        let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 0x01;
+        self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length.clock();
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.length.active() || self.shift_register & 0x01 != 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use super::filter::Filter;
+use super::resampler::{Resampler, MAX_RATE_ADJUSTMENT};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channels {
+    Mono,
+    /// The same signal on both sides, interleaved left then right.
+    Stereo,
+}
+
+/// Turns the mixed signal into samples for the host: resampled to
+/// `sample_rate` (44100, 48000 or 96000, say), then through the same
+/// filters as the console's output, two high-pass and one low-pass.
+pub struct AudioOutput {
+    resampler: Resampler,
+    filters: [Filter; 3],
+    channels: Channels,
+    samples: Vec<f32>,
+}
+
+impl AudioOutput {
+    pub fn new(clock_rate: u64, sample_rate: u32, channels: Channels) -> Self {
+        let rate = sample_rate as f32;
+        Self {
+            resampler: Resampler::new(clock_rate, sample_rate),
+            filters: [
+                Filter::high_pass(90.0, rate),
+                Filter::high_pass(440.0, rate),
+                Filter::low_pass(14000.0, rate),
+            ],
+            channels,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.resampler.sample_rate()
+    }
+
+    pub fn channels(&self) -> Channels {
+        self.channels
+    }
+
+    pub fn set_clock_rate(&mut self, clock_rate: u64) {
+        self.resampler.set_clock_rate(clock_rate);
+    }
+
+    /// Takes the mixed level for one CPU cycle.
+    pub fn clock(&mut self, level: f32) {
+        self.resampler.clock(level);
+    }
+
+    /// The hook for dynamic rate control. Frontends that time frames by
+    /// the display pass how full their audio queue is against its target,
+    /// 0.0 to 1.0, after each frame; a queue running low gets slightly more
+    /// samples and a full one slightly fewer, so it never drains or overflows.
+    pub fn set_buffer_fill(&mut self, fill: f32) {
+        let fill = fill.clamp(0.0, 1.0) as f64;
+        self.resampler.set_rate_adjustment(1.0 + (1.0 - 2.0 * fill) * MAX_RATE_ADJUSTMENT);
+    }
+
+    /// Or sets the ratio of samples made to the nominal rate directly,
+    /// within half a percent either way.
+    pub fn set_rate_adjustment(&mut self, ratio: f64) {
+        self.resampler.set_rate_adjustment(ratio);
+    }
+
+    /// Resamples and filters whatever has come in since the last call,
+    /// leaving it in `self.samples`.
+    fn drain(&mut self) {
+        self.samples.clear();
+        self.resampler.read_samples(&mut self.samples);
+        for sample in self.samples.iter_mut() {
+            *sample = self.filters.iter_mut().fold(*sample, |signal, filter| filter.process(signal));
+        }
+    }
+
+    /// Appends the samples since the last call to `out`, from -1.0 to 1.0.
+    /// Call once a frame.
+    pub fn samples_f32(&mut self, out: &mut Vec<f32>) {
+        self.drain();
+        for &sample in &self.samples {
+            let sample = sample.clamp(-1.0, 1.0);
+            match self.channels {
+                Channels::Mono => out.push(sample),
+                Channels::Stereo => out.extend([sample, sample]),
+            }
+        }
+    }
+
+    pub fn samples_i16(&mut self, out: &mut Vec<i16>) {
+        self.drain();
+        for &sample in &self.samples {
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            match self.channels {
+                Channels::Mono => out.push(sample),
+                Channels::Stereo => out.extend([sample, sample]),
+            }
+        }
+    }
+}
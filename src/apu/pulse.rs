@@ -0,0 +1,152 @@
+use super::units::{Envelope, LengthCounter};
+
+const DUTY_SEQUENCES: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+/// One of the two square wave channels, $4000-$4003 and $4004-$4007.
+pub struct Pulse {
+    /// Pulse 1 negates its sweep with ones' complement, so it sweeps one
+    /// lower than pulse 2.
+    ones_complement: bool,
+
+    duty: usize,
+    sequence_step: usize,
+    period: u16,
+    timer: u16,
+
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_divider: u8,
+    sweep_reload: bool,
+
+    envelope: Envelope,
+    pub length: LengthCounter,
+}
+
+impl Pulse {
+    pub fn new(ones_complement: bool) -> Self {
+        Self {
+            ones_complement,
+            duty: 0,
+            sequence_step: 0,
+            period: 0,
+            timer: 0,
+            sweep_enabled: false,
+            sweep_period: 0,
+            sweep_negate: false,
+            sweep_shift: 0,
+            sweep_divider: 0,
+            sweep_reload: false,
+            envelope: Envelope::new(),
+            length: LengthCounter::new(),
+        }
+    }
+
+    /// `register` is the low two bits of the address.
+    pub fn write(&mut self, register: u16, data: u8) {
+        match register {
+            // [DDLC VVVV]
+            0 => {
+                self.duty = (data >> 6) as usize;
+                self.length.halt = data & 0x20 != 0;
+                self.envelope.write(data);
+            }
+            // [EPPP NSSS]
+            1 => {
+                self.sweep_enabled = data & 0x80 != 0;
+                self.sweep_period = (data >> 4) & 0x07;
+                self.sweep_negate = data & 0x08 != 0;
+                self.sweep_shift = data & 0x07;
+                self.sweep_reload = true;
+            }
+            2 => self.period = (self.period & 0x0700) | data as u16,
+            // [LLLL LTTT], which also restarts the waveform and envelope.
+            _ => {
+                self.period = (self.period & 0x00FF) | ((data & 0x07) as u16) << 8;
+                self.length.load(data >> 3);
+                self.sequence_step = 0;
+                self.envelope.restart();
+            }
+        }
+    }
+
+    /// Clocked every other CPU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            self.sequence_step = (self.sequence_step + 1) & 0x07;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length.clock();
+
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.sweep_muting() {
+            self.period = self.sweep_target();
+        }
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    fn sweep_target(&self) -> u16 {
+        let change = self.period >> self.sweep_shift;
+        if !self.sweep_negate {
+            self.period + change
+        } else if self.ones_complement {
+            self.period.saturating_sub(change + 1)
+        } else {
+            self.period.saturating_sub(change)
+        }
+    }
+
+    /// The sweep unit silences the channel whenever the period is too low,
+    /// or would overflow, even with the sweep disabled.
+    fn sweep_muting(&self) -> bool {
+        self.period < 8 || self.sweep_target() > 0x07FF
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.length.active() || self.sweep_muting() || DUTY_SEQUENCES[self.duty][self.sequence_step] == 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn swept_period(ones_complement: bool) -> u16 {
+        let mut pulse = Pulse::new(ones_complement);
+        pulse.write(2, 0x00);
+        pulse.write(3, 0x01);
+        // Enabled, negated, shift 1.
+        pulse.write(1, 0x89);
+        pulse.clock_half_frame();
+        pulse.period
+    }
+
+    #[test]
+    fn pulse_1_sweeps_down_one_further_than_pulse_2() {
+        assert_eq!(swept_period(true), 0x100 - 0x80 - 1);
+        assert_eq!(swept_period(false), 0x100 - 0x80);
+    }
+}
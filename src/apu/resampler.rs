@@ -0,0 +1,135 @@
+use std::f64::consts::PI;
+
+/// Output samples each level change is spread over, half before and half after.
+const KERNEL_WIDTH: usize = 16;
+/// Steps between output samples the kernel is precomputed for.
+const PHASES: usize = 64;
+/// Passband as a fraction of the output's Nyquist frequency.
+const CUTOFF: f64 = 0.9;
+
+/// How far `set_rate_adjustment` may stretch the output, either way.
+pub const MAX_RATE_ADJUSTMENT: f64 = 0.005;
+
+/// Band-limited synthesis: turns a signal clocked at the CPU rate into
+/// samples at the host's rate. Every change of level is added to the output
+/// as a band-limited step instead of being sampled, so the 1.79 MHz square
+/// waves don't alias.
+pub struct Resampler {
+    clock_rate: f64,
+    sample_rate: f64,
+    adjustment: f64,
+    /// Output samples per clock.
+    step: f64,
+    /// Where the current clock falls, in output samples from `buffer[0]`.
+    time: f64,
+    level: f32,
+
+    /// Differences between successive output samples, summed on the way out.
+    buffer: Vec<f32>,
+    sum: f32,
+    kernel: Vec<[f32; KERNEL_WIDTH]>,
+}
+
+impl Resampler {
+    pub fn new(clock_rate: u64, sample_rate: u32) -> Self {
+        let mut resampler = Self {
+            clock_rate: clock_rate as f64,
+            sample_rate: sample_rate as f64,
+            adjustment: 1.0,
+            step: 0.0,
+            time: 0.0,
+            level: 0.0,
+            buffer: vec![0.0; KERNEL_WIDTH],
+            sum: 0.0,
+            kernel: Self::kernel(),
+        };
+        resampler.update_step();
+        resampler
+    }
+
+    /// A windowed sinc impulse for each phase, each summing to 1.
+    fn kernel() -> Vec<[f32; KERNEL_WIDTH]> {
+        let mut kernel = vec![[0.0; KERNEL_WIDTH]; PHASES];
+        for (phase, taps) in kernel.iter_mut().enumerate() {
+            let offset = phase as f64 / PHASES as f64;
+            let mut total = 0.0;
+            for (i, tap) in taps.iter_mut().enumerate() {
+                let x = i as f64 - offset - (KERNEL_WIDTH / 2) as f64 + 1.0;
+                let sinc = if x == 0.0 { 1.0 } else { (PI * CUTOFF * x).sin() / (PI * CUTOFF * x) };
+                // Blackman window over the kernel's width.
+                let position = (x + KERNEL_WIDTH as f64 / 2.0) / KERNEL_WIDTH as f64;
+                let window = 0.42 - 0.5 * (2.0 * PI * position).cos() + 0.08 * (4.0 * PI * position).cos();
+                let value = sinc * window.max(0.0);
+                *tap = value as f32;
+                total += value;
+            }
+            for tap in taps.iter_mut() {
+                *tap = (*tap as f64 / total) as f32;
+            }
+        }
+        kernel
+    }
+
+    fn update_step(&mut self) {
+        self.step = self.sample_rate * self.adjustment / self.clock_rate;
+    }
+
+    /// The rate `clock` is called at, which changes with the region.
+    pub fn set_clock_rate(&mut self, clock_rate: u64) {
+        self.clock_rate = clock_rate as f64;
+        self.update_step();
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate as u32
+    }
+
+    /// Makes slightly more (above 1.0) or fewer samples for the same
+    /// emulated time, without a change in pitch anyone would hear.
+    pub fn set_rate_adjustment(&mut self, ratio: f64) {
+        self.adjustment = ratio.clamp(1.0 - MAX_RATE_ADJUSTMENT, 1.0 + MAX_RATE_ADJUSTMENT);
+        self.update_step();
+    }
+
+    /// Takes the signal's level for one clock.
+    pub fn clock(&mut self, level: f32) {
+        if level != self.level {
+            self.add_step(level - self.level);
+            self.level = level;
+        }
+        self.time += self.step;
+    }
+
+    fn add_step(&mut self, delta: f32) {
+        let position = self.time.floor();
+        let phase = (((self.time - position) * PHASES as f64) as usize).min(PHASES - 1);
+        let start = position as usize;
+        if self.buffer.len() < start + KERNEL_WIDTH {
+            self.buffer.resize(start + KERNEL_WIDTH, 0.0);
+        }
+
+        for (sample, tap) in self.buffer[start..].iter_mut().zip(&self.kernel[phase]) {
+            *sample += delta * tap;
+        }
+    }
+
+    /// Samples that no later step can change, which lag the input by half
+    /// the kernel's width.
+    pub fn available(&self) -> usize {
+        self.time as usize
+    }
+
+    /// Moves every finished sample to `out`.
+    pub fn read_samples(&mut self, out: &mut Vec<f32>) {
+        let count = self.available();
+        if self.buffer.len() < count + KERNEL_WIDTH {
+            self.buffer.resize(count + KERNEL_WIDTH, 0.0);
+        }
+
+        for delta in self.buffer.drain(..count) {
+            self.sum += delta;
+            out.push(self.sum);
+        }
+        self.time -= count as f64;
+    }
+}
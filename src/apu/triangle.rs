@@ -0,0 +1,89 @@
+use super::units::LengthCounter;
+
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0,
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+
+/// The triangle channel, $4008-$400B. It has no volume control, only a
+/// second, finer length counter (the linear counter).
+pub struct Triangle {
+    period: u16,
+    timer: u16,
+    sequence_step: usize,
+
+    linear_counter: u8,
+    linear_reload_value: u8,
+    linear_reload: bool,
+    /// Stops the linear counter reload flag being cleared; the same bit
+    /// halts the length counter.
+    control: bool,
+
+    pub length: LengthCounter,
+}
+
+impl Triangle {
+    pub fn new() -> Self {
+        Self {
+            period: 0,
+            timer: 0,
+            sequence_step: 0,
+            linear_counter: 0,
+            linear_reload_value: 0,
+            linear_reload: false,
+            control: false,
+            length: LengthCounter::new(),
+        }
+    }
+
+    pub fn write(&mut self, register: u16, data: u8) {
+        match register {
+            // [CRRR RRRR]
+            0 => {
+                self.control = data & 0x80 != 0;
+                self.length.halt = self.control;
+                self.linear_reload_value = data & 0x7F;
+            }
+            1 => {}
+            2 => self.period = (self.period & 0x0700) | data as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | ((data & 0x07) as u16) << 8;
+                self.length.load(data >> 3);
+                self.linear_reload = true;
+            }
+        }
+    }
+
+    /// Clocked every CPU cycle, twice as often as the pulse channels, so
+    /// the same period plays an octave lower.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.length.active() && self.linear_counter > 0 {
+                self.sequence_step = (self.sequence_step + 1) & 0x1F;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length.clock();
+    }
+
+    /// A stopped triangle holds its last step rather than going silent.
+    pub fn output(&self) -> u8 {
+        SEQUENCE[self.sequence_step]
+    }
+}
@@ -0,0 +1,73 @@
+mod inflate;
+mod zip;
+
+use std::fmt;
+
+/// File extensions the emulator can load out of an archive.
+pub const ROM_EXTENSIONS: [&str; 6] = ["nes", "unf", "unif", "fds", "nsf", "nsfe"];
+
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+const SEVEN_ZIP_MAGIC: &[u8] = b"7z\xBC\xAF\x27\x1C";
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    Corrupt(&'static str),
+    UnsupportedFormat(&'static str),
+    UnsupportedMethod(u16),
+    NoRom,
+    EntryNotFound(String),
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArchiveError::Corrupt(reason) => write!(f, "corrupt archive: {}", reason),
+            ArchiveError::UnsupportedFormat(format) => write!(f, "{} archives are not supported", format),
+            ArchiveError::UnsupportedMethod(method) => write!(f, "compression method {} is not supported", method),
+            ArchiveError::NoRom => write!(f, "archive contains no ROM"),
+            ArchiveError::EntryNotFound(name) => write!(f, "archive has no file named {}", name),
+        }
+    }
+}
+
+/// True for any archive format we recognise, supported or not, so that the
+/// caller can report a useful error instead of "invalid header".
+pub fn is_archive(bytes: &[u8]) -> bool {
+    bytes.starts_with(ZIP_MAGIC) || bytes.starts_with(SEVEN_ZIP_MAGIC)
+}
+
+fn is_rom(name: &str) -> bool {
+    name.rsplit_once('.')
+        .is_some_and(|(_, extension)| ROM_EXTENSIONS.iter().any(|rom| rom.eq_ignore_ascii_case(extension)))
+}
+
+fn zip_entries(bytes: &[u8]) -> Result<Vec<zip::Entry>, ArchiveError> {
+    if bytes.starts_with(SEVEN_ZIP_MAGIC) {
+        return Err(ArchiveError::UnsupportedFormat("7z"));
+    }
+    zip::entries(bytes)
+}
+
+/// Names of the loadable files in the archive, in archive order.
+pub fn list(bytes: &[u8]) -> Result<Vec<String>, ArchiveError> {
+    Ok(zip_entries(bytes)?
+        .into_iter()
+        .map(|entry| entry.name)
+        .filter(|name| is_rom(name))
+        .collect())
+}
+
+/// Extracts the file called `name`, or the first loadable file when no name
+/// is given. Returns the file's name along with its contents.
+pub fn extract(bytes: &[u8], name: Option<&str>) -> Result<(String, Vec<u8>), ArchiveError> {
+    let entries = zip_entries(bytes)?;
+    let entry = match name {
+        Some(name) => entries
+            .iter()
+            .find(|entry| entry.name == name)
+            .ok_or_else(|| ArchiveError::EntryNotFound(name.to_string()))?,
+        None => entries.iter().find(|entry| is_rom(&entry.name)).ok_or(ArchiveError::NoRom)?,
+    };
+
+    Ok((entry.name.clone(), zip::extract(bytes, entry)?))
+}
@@ -0,0 +1,95 @@
+use crate::checksum::crc32;
+use super::ArchiveError;
+use super::inflate::inflate;
+
+const LOCAL_HEADER: u32 = 0x04034B50;
+const CENTRAL_HEADER: u32 = 0x02014B50;
+const END_OF_DIRECTORY: u32 = 0x06054B50;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+/// A file in the archive's central directory.
+pub struct Entry {
+    pub name: String,
+    method: u16,
+    crc: u32,
+    compressed_size: usize,
+    size: usize,
+    local_header: usize,
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, ArchiveError> {
+    let bytes = data.get(offset..offset + 2).ok_or(ArchiveError::Corrupt("archive is truncated"))?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, ArchiveError> {
+    let bytes = data.get(offset..offset + 4).ok_or(ArchiveError::Corrupt("archive is truncated"))?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Reads the central directory, found through the record at the end of the
+/// file which may be followed by a comment of up to 64 KB.
+pub fn entries(data: &[u8]) -> Result<Vec<Entry>, ArchiveError> {
+    let search_start = data.len().saturating_sub(22 + 0xFFFF);
+    let end = (search_start..data.len().saturating_sub(21))
+        .rev()
+        .find(|&offset| u32_at(data, offset).ok() == Some(END_OF_DIRECTORY))
+        .ok_or(ArchiveError::Corrupt("no zip directory found"))?;
+
+    let count = u16_at(data, end + 10)? as usize;
+    let mut offset = u32_at(data, end + 16)? as usize;
+    let mut entries = Vec::with_capacity(count);
+
+    for _ in 0..count {
+        if u32_at(data, offset)? != CENTRAL_HEADER {
+            return Err(ArchiveError::Corrupt("bad zip directory entry"));
+        }
+
+        let name_length = u16_at(data, offset + 28)? as usize;
+        let extra_length = u16_at(data, offset + 30)? as usize;
+        let comment_length = u16_at(data, offset + 32)? as usize;
+        let name = data
+            .get(offset + 46..offset + 46 + name_length)
+            .ok_or(ArchiveError::Corrupt("archive is truncated"))?;
+
+        entries.push(Entry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method: u16_at(data, offset + 10)?,
+            crc: u32_at(data, offset + 16)?,
+            compressed_size: u32_at(data, offset + 20)? as usize,
+            size: u32_at(data, offset + 24)? as usize,
+            local_header: u32_at(data, offset + 42)? as usize,
+        });
+
+        offset += 46 + name_length + extra_length + comment_length;
+    }
+
+    Ok(entries)
+}
+
+pub fn extract(data: &[u8], entry: &Entry) -> Result<Vec<u8>, ArchiveError> {
+    let header = entry.local_header;
+    if u32_at(data, header)? != LOCAL_HEADER {
+        return Err(ArchiveError::Corrupt("bad zip file header"));
+    }
+
+    // The local header repeats the name and has its own extra field length.
+    let start = header + 30 + u16_at(data, header + 26)? as usize + u16_at(data, header + 28)? as usize;
+    let compressed = data
+        .get(start..start + entry.compressed_size)
+        .ok_or(ArchiveError::Corrupt("archive is truncated"))?;
+
+    let contents = match entry.method {
+        STORED => compressed.to_vec(),
+        DEFLATED => inflate(compressed, entry.size).map_err(ArchiveError::Corrupt)?,
+        method => return Err(ArchiveError::UnsupportedMethod(method)),
+    };
+
+    if contents.len() != entry.size || crc32(&contents) != entry.crc {
+        return Err(ArchiveError::Corrupt("checksum mismatch"));
+    }
+
+    Ok(contents)
+}
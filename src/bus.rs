@@ -1,23 +1,497 @@
+use std::cell::RefCell;
+use std::io;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::apu::output::AudioOutput;
+use crate::apu::APU;
+use crate::cartridge::{Cartridge, Console, Region, SaveFile};
 use crate::cpu::CPU;
+use crate::events::{EventKind, EventLog, IrqSource};
+use crate::ppu::debug::Image;
+use crate::ppu::PPU;
+use crate::vs_system::VsSystem;
+
+const PRG_RAM_START: usize = 0x6000;
+const PRG_RAM_END: usize = 0x8000;
+
+const OAM_DMA: u16 = 0x4014;
+
+/// Expansion chips at full volume come out about as loud as both pulse channels.
+const EXPANSION_VOLUME: f32 = 0.25;
+
+/// Roughly one second of CPU cycles between checks for unsaved changes.
+const SAVE_INTERVAL: u32 = 1_789_773;
 
 pub struct Bus {
     cpu: CPU,
+    ppu: PPU,
+    apu: APU,
     ram: [u8; 64 * 1024],
+    cartridge: Option<Rc<RefCell<Cartridge>>>,
+    /// Set when running a Vs. System game, which adds the cabinet's inputs.
+    vs_system: Option<VsSystem>,
+    frame_ready: bool,
+    events: EventLog,
+    audio: Option<AudioOutput>,
+
+    region: Region,
+    /// PPU dots owed, in fifths: NTSC runs 3 per CPU cycle, PAL 3.2.
+    ppu_dots: u8,
+
+    /// CPU cycles since power on; DMA has to line up with even cycles.
+    cycles: u64,
+    /// OAM DMA copies a page of CPU memory to $2004 while the CPU is halted.
+    dma_page: u8,
+    dma_address: u8,
+    dma_data: u8,
+    dma_transfer: bool,
+    dma_dummy: bool,
+
+    /// CPU cycles left of a DMC sample fetch; the byte is read on the last.
+    dmc_stall: u8,
+    dmc_address: u16,
+    /// What the CPU was last doing on the bus, which decides how long a DMC
+    /// fetch stalls it and whether the halted read is repeated. The CPU
+    /// makes all of an instruction's accesses on its first cycle, so this
+    /// is how the last instruction ended, as of `last_access_cycle`.
+    last_read: u16,
+    consecutive_writes: u8,
+    last_access_cycle: u64,
+
+    save_directory: Option<PathBuf>,
+    save_file: Option<SaveFile>,
+    save_dirty: bool,
+    save_timer: u32,
+    /// The last failed background save, until the frontend takes it.
+    save_error: Option<io::Error>,
 }
 
 impl Bus {
     pub fn new() -> Self {
         Self {
             cpu: CPU::default(),
+            ppu: PPU::new(),
+            apu: APU::new(),
             ram: [0; 64 * 1024],
+            cartridge: None,
+            vs_system: None,
+            frame_ready: false,
+            events: EventLog::new(),
+            audio: None,
+            region: Region::Ntsc,
+            ppu_dots: 0,
+            cycles: 0,
+            dma_page: 0,
+            dma_address: 0,
+            dma_data: 0,
+            dma_transfer: false,
+            dma_dummy: true,
+            dmc_stall: 0,
+            dmc_address: 0,
+            last_read: 0,
+            consecutive_writes: 0,
+            last_access_cycle: 0,
+            save_directory: None,
+            save_file: None,
+            save_dirty: false,
+            save_timer: 0,
+            save_error: None,
+        }
+    }
+
+    /// Points the CPU back at this bus. Has to be called again whenever the
+    /// bus moves, so it's best kept boxed.
+    pub fn connect_cpu(&mut self) {
+        let bus: *mut Bus = self;
+        self.cpu.connect_bus(bus);
+    }
+
+    /// The event viewer's log; turn it on with `set_enabled`.
+    pub fn events(&mut self) -> &mut EventLog {
+        &mut self.events
+    }
+
+    pub fn apu(&mut self) -> &mut APU {
+        &mut self.apu
+    }
+
+    /// Starts resampling the sound for the host, or stops with `None`.
+    pub fn set_audio_output(&mut self, audio: Option<AudioOutput>) {
+        self.audio = audio;
+        if let Some(audio) = &mut self.audio {
+            audio.set_clock_rate(self.region.cpu_clock_rate());
+        }
+    }
+
+    /// Where to collect each frame's samples once `set_audio_output` is set.
+    pub fn audio(&mut self) -> Option<&mut AudioOutput> {
+        self.audio.as_mut()
+    }
+
+    /// The APU and the cartridge's expansion audio mixed, for this cycle.
+    pub fn audio_level(&self) -> f32 {
+        let expansion = self.cartridge.as_ref().map_or(0.0, |cart| cart.borrow().audio_output());
+        self.apu.output() + expansion * EXPANSION_VOLUME
+    }
+
+    /// The last frame's events drawn over the PPU's scanlines and dots.
+    pub fn event_image(&self) -> Image {
+        self.events.image(self.ppu.scanlines_per_frame() as usize)
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// Sets the CPU to PPU clock ratio and PPU timing for `region`. Inserting
+    /// a cartridge does this from what is known about the game.
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.ppu.set_region(region);
+        self.apu.set_region(region);
+        if let Some(audio) = &mut self.audio {
+            audio.set_clock_rate(region.cpu_clock_rate());
+        }
+    }
+
+    /// Keeps `.sav` files, and the `.ips` files of disk writes, in
+    /// `directory` instead of next to the ROM. Takes effect for the next
+    /// inserted cartridge.
+    pub fn set_save_directory(&mut self, directory: Option<PathBuf>) {
+        self.save_directory = directory;
+    }
+
+    /// Saves the old cartridge and loads the new one's save. The cartridge
+    /// goes in even if either fails. When its save can't be read the game
+    /// runs without saving, rather than overwrite a file that may be fine.
+    pub fn insert_cartridge(&mut self, cartridge: Rc<RefCell<Cartridge>>) -> io::Result<()> {
+        let mut result = self.flush_save();
+        self.save_file = None;
+
+        {
+            let mut cart = cartridge.borrow_mut();
+            self.set_region(cart.region());
+            self.vs_system = match cart.console() {
+                Console::VsSystem(ppu, hardware) => Some(VsSystem::new(ppu, hardware)),
+                _ => None,
+            };
+            self.ppu.set_vs_ppu(self.vs_system.as_ref().map(|vs| vs.ppu));
+
+            if cart.has_battery() {
+                if let Some(path) = cart.path() {
+                    let directory = self.save_directory.as_deref();
+                    let mut save_file = if cart.disk_sides() > 0 {
+                        SaveFile::disk_writes(path, directory)
+                    } else {
+                        SaveFile::new(path, directory)
+                    };
+                    match save_file.load() {
+                        Ok(Some(data)) => {
+                            self.load_save(&mut cart, &data);
+                            self.save_file = Some(save_file);
+                        }
+                        Ok(None) => self.save_file = Some(save_file),
+                        Err(error) => result = Err(error),
+                    }
+                }
+            }
+        }
+
+        self.ppu.connect_cartridge(cartridge.clone());
+        self.cartridge = Some(cartridge);
+        result
+    }
+
+    pub fn ppu(&mut self) -> &mut PPU {
+        &mut self.ppu
+    }
+
+    /// Returns true once for each frame the PPU finishes, at the start of
+    /// VBlank. The picture is then in `ppu().frame()`.
+    pub fn frame_ready(&mut self) -> bool {
+        std::mem::take(&mut self.frame_ready)
+    }
+
+    pub fn vs_system(&mut self) -> Option<&mut VsSystem> {
+        self.vs_system.as_mut()
+    }
+
+    /// Splits a save back into PRG-RAM and whatever the board keeps itself.
+    fn load_save(&mut self, cartridge: &mut Cartridge, data: &[u8]) {
+        let prg_ram_length = cartridge.prg_ram_size().min(PRG_RAM_END - PRG_RAM_START).min(data.len());
+        self.ram[PRG_RAM_START..PRG_RAM_START + prg_ram_length].copy_from_slice(&data[..prg_ram_length]);
+        cartridge.load_nvram(&data[prg_ram_length..]);
+    }
+
+    /// Writes battery backed memory, or a disk's writes, to its file if it changed.
+    pub fn flush_save(&mut self) -> io::Result<()> {
+        let (Some(save_file), Some(cartridge)) = (&mut self.save_file, &self.cartridge) else {
+            return Ok(());
+        };
+
+        let cartridge = cartridge.borrow();
+        let prg_ram_length = cartridge.prg_ram_size().min(PRG_RAM_END - PRG_RAM_START);
+        let mut data = self.ram[PRG_RAM_START..PRG_RAM_START + prg_ram_length].to_vec();
+        if let Some(nvram) = cartridge.nvram() {
+            data.extend_from_slice(nvram);
         }
+
+        save_file.store(&data)?;
+        self.save_dirty = false;
+        Ok(())
+    }
+
+    /// The error from the last save made while running, if it failed. The
+    /// save is tried again a second later either way.
+    pub fn take_save_error(&mut self) -> Option<io::Error> {
+        self.save_error.take()
+    }
+
+    /// A write made by the CPU itself, rather than by DMA.
+    pub fn cpu_write(&mut self, address: u16, data: u8) {
+        self.consecutive_writes = self.consecutive_writes.saturating_add(1);
+        self.last_access_cycle = self.cycles;
+        self.write(address, data);
+    }
+
+    /// A read made by the CPU itself, rather than by DMA.
+    pub fn cpu_read(&mut self, address: u16) -> u8 {
+        self.last_read = address;
+        self.consecutive_writes = 0;
+        self.last_access_cycle = self.cycles;
+        self.read(address, false)
     }
 
     pub fn write(&mut self, address: u16, data: u8) {
+        if (0x2000..=0x3FFF).contains(&address) {
+            self.record_event(EventKind::PpuRegister, 0x2000 | (address & 0x0007), data);
+            self.ppu.cpu_write(address, data);
+            return;
+        }
+
+        if matches!(address, 0x4000..=0x4013 | 0x4015 | 0x4017) {
+            self.apu.cpu_write(address, data);
+            return;
+        }
+
+        if address == OAM_DMA {
+            self.record_event(EventKind::OamDma, address, data);
+            self.dma_page = data;
+            self.dma_address = 0;
+            self.dma_transfer = true;
+            return;
+        }
+
+        if let Some(vs_system) = &mut self.vs_system {
+            vs_system.write(address, data);
+        }
+
+        if let Some(cartridge) = &self.cartridge {
+            if cartridge.borrow_mut().cpu_write(address, data) {
+                self.record_event(EventKind::MapperRegister, address, data);
+                return;
+            }
+        }
+
+        if (PRG_RAM_START..PRG_RAM_END).contains(&(address as usize)) {
+            self.save_dirty = true;
+        }
         self.ram[address as usize] = data;
     }
 
-    pub fn read(&self, address: u16, read_only: bool) -> u8 {
+    pub fn read(&mut self, address: u16, read_only: bool) -> u8 {
+        if (0x2000..=0x3FFF).contains(&address) {
+            return self.ppu.cpu_read(address, read_only);
+        }
+
+        if address == 0x4015 {
+            return self.apu.read_status(read_only);
+        }
+
+        if let Some(cartridge) = &self.cartridge {
+            if let Some(data) = cartridge.borrow_mut().cpu_read(address) {
+                return data;
+            }
+        }
+
+        // The cabinet drives the upper bits; bit 0 is still the controller's.
+        if let Some(cabinet) = self.vs_system.as_ref().and_then(|vs| vs.read(address)) {
+            return cabinet | (self.ram[address as usize] & 0x01);
+        }
+
         self.ram[address as usize]
     }
-}
\ No newline at end of file
+
+    /// Advances the system by one CPU cycle, which is three PPU dots (3.2 on PAL).
+    pub fn clock(&mut self) {
+        self.ppu_dots += if self.region == Region::Pal { 16 } else { 15 };
+        while self.ppu_dots >= 5 {
+            self.ppu.clock();
+            self.ppu_dots -= 5;
+        }
+        if std::mem::take(&mut self.ppu.frame_complete) {
+            self.frame_ready = true;
+        }
+        self.events.update(self.ppu.scanline());
+        if self.ppu.take_nmi() {
+            self.record_event(EventKind::Nmi, 0, 0);
+            self.cpu.non_maskable_interrupt();
+        }
+
+        if self.dmc_stall == 0 {
+            if let Some(address) = self.apu.dmc_dma_address() {
+                self.start_dmc_dma(address);
+            }
+        }
+
+        if self.dmc_stall > 0 && !self.dma_last_write() {
+            self.clock_dmc_dma();
+        } else if self.dma_transfer {
+            self.clock_dma();
+        } else {
+            self.cpu.clock();
+        }
+        self.cycles += 1;
+
+        self.apu.clock();
+        let (scanline, cycle) = (self.ppu.scanline(), self.ppu.cycle());
+        self.events.irq_line(IrqSource::FrameCounter, self.apu.frame_irq_state(), scanline, cycle);
+        self.events.irq_line(IrqSource::Dmc, self.apu.dmc_irq_state(), scanline, cycle);
+        let mut irq = self.apu.irq_state();
+
+        if let Some(vs_system) = &mut self.vs_system {
+            vs_system.clock();
+        }
+
+        if let Some(cartridge) = &self.cartridge {
+            let mut cartridge = cartridge.borrow_mut();
+            cartridge.clock();
+
+            self.events.irq_line(IrqSource::Cartridge, cartridge.irq_state(), scanline, cycle);
+            irq |= cartridge.irq_state();
+        }
+
+        // /IRQ is shared, so either source holding it low interrupts.
+        if irq {
+            self.cpu.interrupt_request();
+        }
+
+        if self.audio.is_some() {
+            let level = self.audio_level();
+            if let Some(audio) = &mut self.audio {
+                audio.clock(level);
+            }
+        }
+
+        // EEPROMs are written through mapper registers, so boards with their
+        // own NVRAM are always compared against the last save.
+        self.save_timer += 1;
+        if self.save_timer >= SAVE_INTERVAL {
+            self.save_timer = 0;
+            let has_nvram = self.cartridge.as_ref().is_some_and(|cart| cart.borrow().nvram().is_some());
+            if self.save_dirty || has_nvram {
+                if let Err(error) = self.flush_save() {
+                    self.save_error = Some(error);
+                }
+            }
+        }
+    }
+
+    fn record_event(&mut self, kind: EventKind, address: u16, data: u8) {
+        self.events.record(kind, address, data, self.ppu.scanline(), self.ppu.cycle());
+    }
+
+    /// One cycle of OAM DMA: after waiting for an even cycle, alternate
+    /// between reading a byte and writing it to $2004, 513 or 514 cycles in all.
+    fn clock_dma(&mut self) {
+        if self.dma_dummy {
+            if self.cycles & 0x01 == 1 {
+                self.dma_dummy = false;
+            }
+            return;
+        }
+
+        if self.cycles & 0x01 == 0 {
+            let address = (self.dma_page as u16) << 8 | self.dma_address as u16;
+            self.dma_data = self.read(address, false);
+        } else {
+            self.ppu.cpu_write(0x2004, self.dma_data);
+            self.dma_address = self.dma_address.wrapping_add(1);
+            if self.dma_address == 0 {
+                self.dma_transfer = false;
+                self.dma_dummy = true;
+            }
+        }
+    }
+
+    /// Whether OAM DMA is about to write its last byte to $2004.
+    fn dma_last_write(&self) -> bool {
+        self.dma_transfer && !self.dma_dummy && self.dma_address == 0xFF && self.cycles & 0x01 == 1
+    }
+
+    /// Halts the CPU to fetch a DMC sample byte. The CPU only stops on a
+    /// read, so a fetch that lands on writes waits them out and takes
+    /// fewer cycles: 4 after a read, 3 after one or two writes, 2 after the
+    /// three pushes of an interrupt. OAM DMA has already halted the CPU and
+    /// only pauses for 2 cycles, or 1 if it was on its last write.
+    ///
+    /// The CPU isn't cycle accurate, so this is an approximation at the
+    /// level of instructions: a fetch in the cycle straight after an
+    /// instruction's accesses is taken to land on its last one, and a fetch
+    /// any later on one of its plain reads.
+    fn start_dmc_dma(&mut self, address: u16) {
+        self.dmc_address = address;
+        let on_last_access = self.cycles == self.last_access_cycle + 1;
+        let writes = if on_last_access { self.consecutive_writes } else { 0 };
+        self.dmc_stall = if self.dma_transfer {
+            if self.dma_last_write() { 1 } else { 2 }
+        } else {
+            match writes {
+                0 => 4,
+                1 | 2 => 3,
+                _ => 2,
+            }
+        };
+
+        // While halted the 2A03 keeps repeating the read it stopped on. That
+        // is harmless except on registers where reading has side effects:
+        // the PPU's address moves on, and a controller bit is skipped. The
+        // PAL 2A07 fixed this.
+        let halted_on_read = !self.dma_transfer && on_last_access && self.consecutive_writes == 0;
+        let side_effects = self.last_read & 0xE007 == 0x2007 || matches!(self.last_read, 0x4016 | 0x4017);
+        let glitch = side_effects && self.region != Region::Pal;
+        if halted_on_read && glitch {
+            self.read(self.last_read, false);
+        }
+    }
+
+    /// One cycle of a DMC fetch, which hands the byte over on the last.
+    fn clock_dmc_dma(&mut self) {
+        self.dmc_stall -= 1;
+        if self.dmc_stall == 0 {
+            let data = self.read(self.dmc_address, false);
+            self.apu.dmc_dma_complete(data);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        if let Some(cartridge) = &self.cartridge {
+            cartridge.borrow_mut().reset();
+        }
+
+        self.dma_transfer = false;
+        self.dma_dummy = true;
+        self.dmc_stall = 0;
+        self.ppu.reset();
+        self.apu.reset();
+        self.cpu.reset();
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        // There's no one left to report a failure to.
+        let _ = self.flush_save();
+    }
+}
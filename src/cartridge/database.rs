@@ -0,0 +1,209 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::checksum::{self, Sha1};
+use super::{Mirroring, Region, RomImage};
+
+const DATABASE: &str = include_str!("gamedb.txt");
+
+/// A fuller database in the same format, loaded from a file, which is
+/// searched before the embedded one.
+static EXTRA_DATABASE: OnceLock<String> = OnceLock::new();
+
+/// Loads a database file in the format of `gamedb.txt`, such as one
+/// converted from a NesCartDB export. Fails with `AlreadyExists` once one
+/// has been loaded.
+pub fn load(path: &Path) -> io::Result<()> {
+    let text = fs::read_to_string(path)?;
+    EXTRA_DATABASE
+        .set(text)
+        .map_err(|_| io::Error::new(io::ErrorKind::AlreadyExists, "a game database is already loaded"))
+}
+
+/// A header field that was replaced with the value from the database.
+pub struct Correction {
+    pub field: &'static str,
+    pub header: String,
+    pub database: String,
+}
+
+impl fmt::Display for Correction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} -> {}", self.field, self.header, self.database)
+    }
+}
+
+/// Known good values for one cartridge; `None` keeps the header's value.
+struct Entry<'a> {
+    mapper_id: Option<u16>,
+    submapper: Option<u8>,
+    mirroring: Option<Mirroring>,
+    prg_ram_size: Option<usize>,
+    chr_ram_size: Option<usize>,
+    region: Option<Region>,
+    battery: Option<bool>,
+    title: &'a str,
+}
+
+fn field<T>(value: &str, parse: impl Fn(&str) -> Option<T>) -> Option<Option<T>> {
+    if value == "-" {
+        Some(None)
+    } else {
+        parse(value).map(Some)
+    }
+}
+
+/// Parses a database line, returning its hash and entry. Malformed lines are skipped.
+fn parse_line(line: &str) -> Option<(&str, Entry<'_>)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let mut fields = line.splitn(9, char::is_whitespace).filter(|f| !f.is_empty());
+    let hash = fields.next()?;
+    let entry = Entry {
+        mapper_id: field(fields.next()?, |v| v.parse().ok())?,
+        submapper: field(fields.next()?, |v| v.parse().ok())?,
+        mirroring: match fields.next()? {
+            "H" => Some(Mirroring::Horizontal),
+            "V" => Some(Mirroring::Vertical),
+            "4" => Some(Mirroring::FourScreen),
+            // The mapper picks the arrangement, so the header bit doesn't matter.
+            "M" | "-" => None,
+            _ => return None,
+        },
+        prg_ram_size: field(fields.next()?, |v| v.parse().ok())?,
+        chr_ram_size: field(fields.next()?, |v| v.parse().ok())?,
+        region: field(fields.next()?, |v| match v {
+            "NTSC" => Some(Region::Ntsc),
+            "PAL" => Some(Region::Pal),
+            "Dendy" => Some(Region::Dendy),
+            "Multi" => Some(Region::Multiple),
+            _ => None,
+        })?,
+        battery: field(fields.next()?, |v| match v {
+            "0" => Some(false),
+            "1" => Some(true),
+            _ => None,
+        })?,
+        title: fields.next().unwrap_or("").trim(),
+    };
+
+    Some((hash, entry))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+fn lookup<'a>(database: &'a str, image: &RomImage) -> Option<Entry<'a>> {
+    let chr_rom: &[u8] = if image.chr_ram { &[] } else { &image.chr };
+
+    let mut crc = checksum::Crc32::new();
+    crc.update(&image.prg_rom);
+    crc.update(chr_rom);
+    let crc = format!("{:08X}", crc.finish());
+
+    let mut sha1 = Sha1::new();
+    sha1.update(&image.prg_rom);
+    sha1.update(chr_rom);
+    let sha1 = hex(&sha1.finish());
+
+    database
+        .lines()
+        .filter_map(parse_line)
+        .find(|(hash, _)| hash.eq_ignore_ascii_case(&crc) || hash.eq_ignore_ascii_case(&sha1))
+        .map(|(_, entry)| entry)
+}
+
+/// Looks the ROM up in the database and replaces whatever the header got
+/// wrong. Returns the title and the corrections made, or `None` for unknown ROMs.
+pub fn apply(image: &mut RomImage) -> Option<(&'static str, Vec<Correction>)> {
+    let entry = EXTRA_DATABASE
+        .get()
+        .and_then(|database| lookup(database, image))
+        .or_else(|| lookup(DATABASE, image))?;
+    let mut corrections = Vec::new();
+
+    fn correct<T: PartialEq + fmt::Debug>(
+        corrections: &mut Vec<Correction>,
+        field: &'static str,
+        value: &mut T,
+        database: Option<T>,
+    ) {
+        if let Some(database) = database {
+            if *value != database {
+                corrections.push(Correction {
+                    field,
+                    header: format!("{:?}", value),
+                    database: format!("{:?}", database),
+                });
+                *value = database;
+            }
+        }
+    }
+
+    correct(&mut corrections, "mapper", &mut image.mapper_id, entry.mapper_id);
+    correct(&mut corrections, "submapper", &mut image.submapper, entry.submapper);
+    correct(&mut corrections, "mirroring", &mut image.mirroring, entry.mirroring);
+    correct(&mut corrections, "PRG-RAM size", &mut image.prg_ram_size, entry.prg_ram_size);
+    correct(&mut corrections, "region", &mut image.region, entry.region);
+    image.region_known |= entry.region.is_some();
+    correct(&mut corrections, "battery", &mut image.battery, entry.battery);
+
+    if image.chr_ram {
+        let mut chr_ram_size = image.chr.len();
+        correct(&mut corrections, "CHR-RAM size", &mut chr_ram_size, entry.chr_ram_size.filter(|&size| size > 0));
+        image.chr.resize(chr_ram_size, 0);
+    }
+
+    Some((entry.title, corrections))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Console;
+
+    fn image() -> RomImage {
+        RomImage {
+            mapper_id: 0,
+            submapper: 0,
+            prg_rom: (0..0x4000).map(|i| i as u8).collect(),
+            chr: vec![0x55; 0x2000],
+            chr_ram: false,
+            mirroring: Mirroring::Horizontal,
+            battery: false,
+            prg_ram_size: 0,
+            region: Region::Ntsc,
+            region_known: false,
+            console: Console::Nes,
+        }
+    }
+
+    #[test]
+    fn lookup_by_crc_and_sha1() {
+        let image = image();
+        let mut crc = checksum::Crc32::new();
+        crc.update(&image.prg_rom);
+        crc.update(&image.chr);
+        let mut sha1 = Sha1::new();
+        sha1.update(&image.prg_rom);
+        sha1.update(&image.chr);
+
+        let by_crc = format!("{:08x} 2 - V 8192 - PAL 1 Test Cartridge", crc.finish());
+        let entry = lookup(&by_crc, &image).unwrap();
+        assert_eq!(entry.mapper_id, Some(2));
+        assert_eq!(entry.mirroring, Some(Mirroring::Vertical));
+        assert_eq!(entry.chr_ram_size, None);
+        assert_eq!(entry.title, "Test Cartridge");
+
+        let by_sha1 = format!("# comment\n{} - - M - - - - Other", hex(&sha1.finish()));
+        assert_eq!(lookup(&by_sha1, &image).unwrap().title, "Other");
+        assert!(lookup("00000000 2 - V - - - - Wrong", &image).is_none());
+    }
+}
@@ -0,0 +1,38 @@
+use super::CartridgeError;
+
+/// Size of one disk side in a .fds image, with gaps and CRCs stripped.
+pub const SIDE_SIZE: usize = 65500;
+
+const HEADER_SIZE: usize = 16;
+
+/// The verification string at the start of every disk side's first block.
+const DISK_MAGIC: &[u8] = b"\x01*NINTENDO-HVC*";
+
+/// A .fds image starts with either the fwNES header or straight away with the first side.
+pub fn is_disk(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"FDS\x1A") || bytes.starts_with(DISK_MAGIC)
+}
+
+/// Strips the fwNES header if there is one and returns the disk sides
+/// back to back, padded to a whole number of sides.
+pub fn parse(bytes: &[u8]) -> Result<Vec<u8>, CartridgeError> {
+    let data = if bytes.starts_with(b"FDS\x1A") {
+        if bytes.len() < HEADER_SIZE {
+            return Err(CartridgeError::Truncated);
+        }
+
+        // The side count in the header is often wrong, so trust the file size.
+        &bytes[HEADER_SIZE..]
+    } else {
+        bytes
+    };
+
+    if !data.starts_with(DISK_MAGIC) {
+        return Err(CartridgeError::InvalidHeader);
+    }
+
+    let sides = data.len().div_ceil(SIDE_SIZE);
+    let mut disk = data.to_vec();
+    disk.resize(sides * SIDE_SIZE, 0);
+    Ok(disk)
+}
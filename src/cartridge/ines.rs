@@ -0,0 +1,191 @@
+use super::{CartridgeError, Console, Mirroring, Region, RomImage, VsHardware, VsPpu};
+
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+
+/// The 16 byte header shared by iNES and NES 2.0 files.
+pub struct Header {
+    pub mapper_id: u16,
+    pub submapper: u8,
+    pub prg_rom_size: usize,
+    pub chr_rom_size: usize,
+    pub prg_ram_size: usize,
+    pub chr_ram_size: usize,
+    pub mirroring: Mirroring,
+    pub battery: bool,
+    pub trainer: bool,
+    pub nes2: bool,
+    pub region: Region,
+    /// iNES 1.0 headers only mark PAL games, and rarely even that.
+    pub region_known: bool,
+    pub console: Console,
+}
+
+impl Header {
+    pub fn parse(bytes: &[u8]) -> Result<Self, CartridgeError> {
+        if bytes.len() < HEADER_SIZE || &bytes[0..4] != b"NES\x1A" {
+            return Err(CartridgeError::InvalidHeader);
+        }
+
+        let flags6 = bytes[6];
+        let flags7 = bytes[7];
+        let nes2 = (flags7 & 0x0C) == 0x08;
+
+        let mirroring = if flags6 & 0x08 != 0 {
+            Mirroring::FourScreen
+        } else if flags6 & 0x01 != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+
+        let mut mapper_id = (flags6 >> 4) as u16;
+        let mut submapper = 0;
+        let prg_rom_size;
+        let chr_rom_size;
+        let prg_ram_size;
+        let chr_ram_size;
+        let region;
+        let region_known;
+        let console;
+
+        if nes2 {
+            mapper_id |= (flags7 & 0xF0) as u16;
+            mapper_id |= ((bytes[8] & 0x0F) as u16) << 8;
+            submapper = bytes[8] >> 4;
+
+            prg_rom_size = rom_size(bytes[4], bytes[9] & 0x0F, 16 * 1024);
+            chr_rom_size = rom_size(bytes[5], bytes[9] >> 4, 8 * 1024);
+            prg_ram_size = ram_size(bytes[10] & 0x0F) + ram_size(bytes[10] >> 4);
+            chr_ram_size = ram_size(bytes[11] & 0x0F) + ram_size(bytes[11] >> 4);
+            region = match bytes[12] & 0x03 {
+                0 => Region::Ntsc,
+                1 => Region::Pal,
+                2 => Region::Multiple,
+                _ => Region::Dendy,
+            };
+            region_known = true;
+            console = match flags7 & 0x03 {
+                1 => Console::VsSystem(vs_ppu(bytes[13] & 0x0F), vs_hardware(bytes[13] >> 4)),
+                2 => Console::PlayChoice10,
+                // Extended console types (clones, VT0x) play like a regular NES here.
+                _ => Console::Nes,
+            };
+        } else {
+            // Headers written by old tools have garbage ("DiskDude!") in the
+            // padding, which also makes the upper mapper nibble unreliable.
+            if bytes[12..16].iter().all(|&b| b == 0) {
+                mapper_id |= (flags7 & 0xF0) as u16;
+            }
+
+            prg_rom_size = bytes[4] as usize * 16 * 1024;
+            chr_rom_size = bytes[5] as usize * 8 * 1024;
+            prg_ram_size = 8 * 1024;
+            chr_ram_size = if chr_rom_size == 0 { 8 * 1024 } else { 0 };
+            region = if bytes[9] & 0x01 != 0 { Region::Pal } else { Region::Ntsc };
+            region_known = region == Region::Pal;
+            // iNES doesn't say which PPU a Vs. game needs. Assume the 2C03, whose
+            // palette is closest to the NES's; only NES 2.0 headers say otherwise.
+            console = if flags7 & 0x01 != 0 {
+                Console::VsSystem(VsPpu::Rp2C03, VsHardware::Unisystem)
+            } else if flags7 & 0x02 != 0 {
+                Console::PlayChoice10
+            } else {
+                Console::Nes
+            };
+        }
+
+        Ok(Self {
+            mapper_id,
+            submapper,
+            prg_rom_size,
+            chr_rom_size,
+            prg_ram_size,
+            chr_ram_size,
+            mirroring,
+            battery: flags6 & 0x02 != 0,
+            trainer: flags6 & 0x04 != 0,
+            nes2,
+            region,
+            region_known,
+            console,
+        })
+    }
+}
+
+/// NES 2.0 sizes are either a plain 12 bit count of units, or when the
+/// upper nibble is $F an exponent-multiplier pair giving the size in bytes.
+fn rom_size(lsb: u8, msb: u8, unit: usize) -> usize {
+    if msb == 0x0F {
+        let exponent = (lsb >> 2) as u32;
+        let multiplier = ((lsb & 0x03) * 2 + 1) as usize;
+        multiplier << exponent
+    } else {
+        (((msb as usize) << 8) | lsb as usize) * unit
+    }
+}
+
+fn vs_ppu(value: u8) -> VsPpu {
+    match value {
+        2..=5 => VsPpu::Rp2C04(value - 1),
+        8..=12 => VsPpu::Rc2C05(value - 7),
+        _ => VsPpu::Rp2C03,
+    }
+}
+
+fn vs_hardware(value: u8) -> VsHardware {
+    match value {
+        1 => VsHardware::RbiBaseball,
+        2 => VsHardware::TkoBoxing,
+        3 => VsHardware::SuperXevious,
+        4 => VsHardware::IceClimberJapan,
+        5 => VsHardware::DualSystem,
+        6 => VsHardware::DualSystemBungeling,
+        _ => VsHardware::Unisystem,
+    }
+}
+
+fn ram_size(shift: u8) -> usize {
+    if shift == 0 {
+        0
+    } else {
+        64 << shift
+    }
+}
+
+pub fn parse(bytes: &[u8]) -> Result<RomImage, CartridgeError> {
+    let header = Header::parse(bytes)?;
+
+    let mut offset = HEADER_SIZE;
+    if header.trainer {
+        offset += TRAINER_SIZE;
+    }
+
+    let prg_end = offset + header.prg_rom_size;
+    let chr_end = prg_end + header.chr_rom_size;
+    if bytes.len() < chr_end || header.prg_rom_size == 0 {
+        return Err(CartridgeError::Truncated);
+    }
+
+    let prg_rom = bytes[offset..prg_end].to_vec();
+    let chr_ram = header.chr_rom_size == 0;
+    let chr = if chr_ram {
+        vec![0; header.chr_ram_size.max(8 * 1024)]
+    } else {
+        bytes[prg_end..chr_end].to_vec()
+    };
+
+    Ok(RomImage {
+        mapper_id: header.mapper_id,
+        submapper: header.submapper,
+        prg_rom,
+        chr,
+        chr_ram,
+        mirroring: header.mirroring,
+        battery: header.battery,
+        prg_ram_size: header.prg_ram_size,
+        region: header.region,
+        region_known: header.region_known,
+        console: header.console,
+    })
+}
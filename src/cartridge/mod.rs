@@ -0,0 +1,501 @@
+mod ines;
+mod unif;
+mod save;
+mod database;
+mod patch;
+pub(crate) mod fds;
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::archive::{self, ArchiveError};
+use crate::mappers::{self, Mapper};
+use crate::nsf::{Nsf, NsfError};
+
+pub use self::database::Correction;
+pub(crate) use self::patch::{apply as apply_patch, ips_diff};
+pub use self::save::SaveFile;
+
+/// Looked for next to disk images.
+const DISK_SYSTEM_BIOS: &str = "disksys.rom";
+
+/// A fuller game database, looked for next to ROMs.
+const GAME_DATABASE: &str = "gamedb.txt";
+
+/// The iNES mapper number reserved for the Disk System.
+const DISK_SYSTEM_MAPPER: u16 = 20;
+
+/// How the two physical nametables are arranged in the PPU address space.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    SingleScreenLow,
+    SingleScreenHigh,
+    FourScreen,
+}
+
+/// The TV system a game was made for.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Region {
+    Ntsc,
+    Pal,
+    /// Runs on either, so the NTSC timing is used.
+    Multiple,
+    Dendy,
+}
+
+impl Region {
+    /// CPU cycles per second: the master clock divided by 12 (NTSC), 16 (PAL) or 15 (Dendy).
+    pub fn cpu_clock_rate(&self) -> u64 {
+        match self {
+            Region::Ntsc | Region::Multiple => 1_789_773,
+            Region::Pal => 1_662_607,
+            Region::Dendy => 1_773_448,
+        }
+    }
+
+    /// Guesses the region from the tags in a file name, as in
+    /// "Game (Europe).nes" or "Game (U) [!].nes".
+    fn from_name(name: &str) -> Option<Region> {
+        let (mut ntsc, mut pal, mut dendy) = (false, false, false);
+
+        let tags = name.split('(').skip(1).filter_map(|tag| tag.split(')').next());
+        for country in tags.flat_map(|tag| tag.split(',')) {
+            match country.trim().to_ascii_lowercase().as_str() {
+                "u" | "usa" | "j" | "japan" | "ju" | "k" | "korea" | "ntsc" => ntsc = true,
+                "e" | "europe" | "a" | "australia" | "g" | "germany" | "f" | "france" | "s" | "spain"
+                | "i" | "italy" | "sw" | "sweden" | "uk" | "pal" => pal = true,
+                "r" | "russia" | "dendy" => dendy = true,
+                "w" | "world" => {
+                    ntsc = true;
+                    pal = true;
+                }
+                _ => {}
+            }
+        }
+
+        match (ntsc, pal, dendy) {
+            (true, true, _) => Some(Region::Multiple),
+            (_, true, _) => Some(Region::Pal),
+            (_, _, true) => Some(Region::Dendy),
+            (true, _, _) => Some(Region::Ntsc),
+            _ => None,
+        }
+    }
+}
+
+/// The system a ROM was dumped from.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Console {
+    Nes,
+    VsSystem(VsPpu, VsHardware),
+    PlayChoice10,
+}
+
+/// The RGB PPUs used in Vs. System cabinets. They output RGB instead of
+/// composite video, so their palettes differ from the 2C02's.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum VsPpu {
+    /// RP2C03B/G and RC2C03B/C, with the standard palette order.
+    Rp2C03,
+    /// RP2C04-0001 to -0004, each with its own scrambled palette order.
+    Rp2C04(u8),
+    /// RC2C05-01 to -05: standard palette order, but with $2000 and $2001
+    /// swapped and an ID in the low bits of $2002.
+    Rc2C05(u8),
+}
+
+impl VsPpu {
+    /// Bits 0-4 returned in $2002 by the 2C05 variants that are known.
+    pub fn status_id(&self) -> Option<u8> {
+        match self {
+            VsPpu::Rc2C05(1) | VsPpu::Rc2C05(4) => Some(0x1B),
+            VsPpu::Rc2C05(2) => Some(0x3D),
+            VsPpu::Rc2C05(3) => Some(0x1C),
+            _ => None,
+        }
+    }
+
+    pub fn swaps_control_registers(&self) -> bool {
+        matches!(self, VsPpu::Rc2C05(_))
+    }
+}
+
+/// Vs. System board variants, from byte 13 of an NES 2.0 header.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum VsHardware {
+    Unisystem,
+    RbiBaseball,
+    TkoBoxing,
+    SuperXevious,
+    IceClimberJapan,
+    /// Two CPUs and PPUs sharing the cabinet, like Vs. Tennis.
+    DualSystem,
+    DualSystemBungeling,
+}
+
+impl VsHardware {
+    pub fn is_dual(&self) -> bool {
+        matches!(self, VsHardware::DualSystem | VsHardware::DualSystemBungeling)
+    }
+}
+
+#[derive(Debug)]
+pub enum CartridgeError {
+    Io(io::Error),
+    InvalidHeader,
+    Truncated,
+    UnsupportedMapper(u16, u8),
+    UnknownBoard(String),
+    InvalidPatch(&'static str),
+    Archive(ArchiveError),
+    MissingBios(PathBuf),
+    Nsf(NsfError),
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CartridgeError::Io(error) => write!(f, "could not read ROM: {}", error),
+            CartridgeError::InvalidHeader => write!(f, "unrecognised ROM header"),
+            CartridgeError::Truncated => write!(f, "ROM is shorter than its header claims"),
+            CartridgeError::UnsupportedMapper(id, sub) => write!(f, "mapper {}.{} is not supported", id, sub),
+            CartridgeError::UnknownBoard(name) => write!(f, "UNIF board {} is not known", name),
+            CartridgeError::InvalidPatch(reason) => write!(f, "could not apply patch: {}", reason),
+            CartridgeError::Archive(error) => write!(f, "{}", error),
+            CartridgeError::MissingBios(path) => write!(f, "disk system BIOS not found at {}", path.display()),
+            CartridgeError::Nsf(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl From<io::Error> for CartridgeError {
+    fn from(error: io::Error) -> Self {
+        CartridgeError::Io(error)
+    }
+}
+
+impl From<ArchiveError> for CartridgeError {
+    fn from(error: ArchiveError) -> Self {
+        CartridgeError::Archive(error)
+    }
+}
+
+impl From<NsfError> for CartridgeError {
+    fn from(error: NsfError) -> Self {
+        CartridgeError::Nsf(error)
+    }
+}
+
+/// Everything read out of a ROM file, before a mapper is chosen for it.
+pub struct RomImage {
+    pub mapper_id: u16,
+    pub submapper: u8,
+    pub prg_rom: Vec<u8>,
+    pub chr: Vec<u8>,
+    pub chr_ram: bool,
+    pub mirroring: Mirroring,
+    pub battery: bool,
+    pub prg_ram_size: usize,
+    pub region: Region,
+    /// False when the header left the region to a guess.
+    pub region_known: bool,
+    pub console: Console,
+}
+
+impl RomImage {
+    /// Reads PRG-ROM through a bank of `bank_size` bytes, wrapping the bank number
+    /// around the size of the ROM like the unconnected address lines would.
+    pub fn read_prg(&self, bank: usize, bank_size: usize, address: u16) -> u8 {
+        let banks = (self.prg_rom.len() / bank_size).max(1);
+        let offset = (bank % banks) * bank_size + (address as usize & (bank_size - 1));
+        self.prg_rom[offset % self.prg_rom.len()]
+    }
+
+    pub fn read_chr(&self, bank: usize, bank_size: usize, address: u16) -> u8 {
+        let banks = (self.chr.len() / bank_size).max(1);
+        let offset = (bank % banks) * bank_size + (address as usize & (bank_size - 1));
+        self.chr[offset % self.chr.len()]
+    }
+
+    /// Writes only land when the board has CHR-RAM instead of CHR-ROM.
+    pub fn write_chr(&mut self, bank: usize, bank_size: usize, address: u16, data: u8) {
+        if self.chr_ram {
+            let banks = (self.chr.len() / bank_size).max(1);
+            let offset = (bank % banks) * bank_size + (address as usize & (bank_size - 1));
+            let length = self.chr.len();
+            self.chr[offset % length] = data;
+        }
+    }
+
+    pub fn prg_banks(&self, bank_size: usize) -> usize {
+        (self.prg_rom.len() / bank_size).max(1)
+    }
+}
+
+pub struct Cartridge {
+    mapper: Box<dyn Mapper>,
+    mapper_id: u16,
+    submapper: u8,
+    battery: bool,
+    prg_ram_size: usize,
+    region: Region,
+    region_known: bool,
+    console: Console,
+    path: Option<PathBuf>,
+    patch: Option<PathBuf>,
+    title: Option<&'static str>,
+    corrections: Vec<Correction>,
+}
+
+impl Cartridge {
+    /// Loads a ROM, applying a same-named `.ips`, `.ups` or `.bps` patch
+    /// found next to it. Zip archives load the first ROM they contain.
+    /// NSF and NSFe tunes load into the player board.
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, CartridgeError> {
+        let patch = patch::find(path.as_ref());
+        Self::open(path, None, patch)
+    }
+
+    /// Loads a ROM and applies `patch` to it in memory; the files on disk
+    /// are never modified. For archives, `entry` names the file to load
+    /// instead of the first ROM found.
+    pub fn open<P: AsRef<Path>>(path: P, entry: Option<&str>, patch: Option<PathBuf>) -> Result<Self, CartridgeError> {
+        // The first database found is kept for the rest of the session.
+        let database_path = path.as_ref().with_file_name(GAME_DATABASE);
+        if database_path.is_file() {
+            match database::load(&database_path) {
+                Err(error) if error.kind() != io::ErrorKind::AlreadyExists => return Err(error.into()),
+                _ => {}
+            }
+        }
+
+        let mut bytes = fs::read(path.as_ref())?;
+        let mut name = path.as_ref().file_name().map(|name| name.to_string_lossy().into_owned());
+        if archive::is_archive(&bytes) {
+            let (entry_name, entry_bytes) = archive::extract(&bytes, entry)?;
+            name = Some(entry_name);
+            bytes = entry_bytes;
+        }
+        if let Some(patch) = &patch {
+            bytes = patch::load(&bytes, patch)?;
+        }
+
+        let mut cartridge = if fds::is_disk(&bytes) {
+            let bios_path = path.as_ref().with_file_name(DISK_SYSTEM_BIOS);
+            let bios = fs::read(&bios_path).map_err(|_| CartridgeError::MissingBios(bios_path))?;
+            Self::from_disk(&bytes, bios)?
+        } else if Nsf::is_nsf(&bytes) {
+            Self::from_nsf(&Nsf::parse(&bytes)?)
+        } else {
+            Self::from_bytes(&bytes)?
+        };
+        cartridge.path = Some(path.as_ref().to_path_buf());
+        cartridge.patch = patch;
+
+        // Most iNES headers don't say, but the file name usually does.
+        if !cartridge.region_known {
+            if let Some(region) = name.as_deref().and_then(Region::from_name) {
+                cartridge.region = region;
+                cartridge.region_known = true;
+            }
+        }
+        Ok(cartridge)
+    }
+
+    /// Parses a ROM and fixes its header from the game database before
+    /// choosing a mapper.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CartridgeError> {
+        let mut image = if bytes.starts_with(b"UNIF") {
+            unif::parse(bytes)?
+        } else {
+            ines::parse(bytes)?
+        };
+
+        let known = database::apply(&mut image);
+        let mut cartridge = Self::from_image(image)?;
+        if let Some((title, corrections)) = known {
+            cartridge.title = Some(title).filter(|title| !title.is_empty());
+            cartridge.corrections = corrections;
+        }
+        Ok(cartridge)
+    }
+
+    /// Loads a Famicom Disk System image, with or without the fwNES header.
+    /// The RAM adapter needs the 8 KB BIOS from the console, which isn't
+    /// distributed with the emulator.
+    pub fn from_disk(bytes: &[u8], bios: Vec<u8>) -> Result<Self, CartridgeError> {
+        if bios.len() != 8 * 1024 {
+            return Err(CartridgeError::InvalidHeader);
+        }
+
+        let disk = fds::parse(bytes)?;
+        let mapper = mappers::create_disk_system(bios, disk);
+
+        // Disk writes are saved to their own .ips file as a diff against
+        // the image; the RAM adapter's RAM isn't saved.
+        Ok(Self::with_mapper(mapper, DISK_SYSTEM_MAPPER, 0, true, 0))
+    }
+
+    /// Wraps an NSF tune in a cartridge that plays it.
+    pub fn from_nsf(nsf: &Nsf) -> Self {
+        // NSF tunes have no real board and so no mapper number.
+        let mut cartridge = Self::with_mapper(mappers::create_nsf_board(nsf), 0, 0, false, 0);
+        cartridge.region = if nsf.is_pal() { Region::Pal } else { Region::Ntsc };
+        cartridge
+    }
+
+    pub fn from_image(image: RomImage) -> Result<Self, CartridgeError> {
+        let mapper_id = image.mapper_id;
+        let submapper = image.submapper;
+        let battery = image.battery;
+        let prg_ram_size = image.prg_ram_size;
+        let region = image.region;
+        let region_known = image.region_known;
+        let console = image.console;
+
+        let mapper = mappers::create(image)
+            .ok_or(CartridgeError::UnsupportedMapper(mapper_id, submapper))?;
+
+        let mut cartridge = Self::with_mapper(mapper, mapper_id, submapper, battery, prg_ram_size);
+        cartridge.region = region;
+        cartridge.region_known = region_known;
+        cartridge.console = console;
+        Ok(cartridge)
+    }
+
+    fn with_mapper(mapper: Box<dyn Mapper>, mapper_id: u16, submapper: u8, battery: bool, prg_ram_size: usize) -> Self {
+        Self {
+            mapper,
+            mapper_id,
+            submapper,
+            battery,
+            prg_ram_size,
+            region: Region::Ntsc,
+            region_known: true,
+            console: Console::Nes,
+            path: None,
+            patch: None,
+            title: None,
+            corrections: Vec::new(),
+        }
+    }
+
+    pub fn mapper_id(&self) -> u16 {
+        self.mapper_id
+    }
+
+    pub fn submapper(&self) -> u8 {
+        self.submapper
+    }
+
+    pub fn has_battery(&self) -> bool {
+        self.battery
+    }
+
+    pub fn prg_ram_size(&self) -> usize {
+        self.prg_ram_size
+    }
+
+    /// The TV system the game needs; when the header didn't say, this
+    /// comes from the file name or defaults to NTSC.
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn console(&self) -> Console {
+        self.console
+    }
+
+    /// The patch applied when loading, if any.
+    pub fn patch(&self) -> Option<&Path> {
+        self.patch.as_deref()
+    }
+
+    /// The game's name, if the ROM was found in the database.
+    pub fn title(&self) -> Option<&'static str> {
+        self.title
+    }
+
+    /// Header fields that the database disagreed with and replaced, for the
+    /// frontend to report.
+    pub fn corrections(&self) -> &[Correction] {
+        &self.corrections
+    }
+
+    /// Where the ROM was loaded from, if it came from a file.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Board memory that survives power off besides PRG-RAM (EEPROM and the like).
+    pub fn nvram(&self) -> Option<&[u8]> {
+        self.mapper.nvram()
+    }
+
+    pub fn load_nvram(&mut self, data: &[u8]) {
+        self.mapper.load_nvram(data);
+    }
+
+    /// Number of disk sides, zero for anything but the Disk System.
+    pub fn disk_sides(&self) -> usize {
+        self.mapper.disk_sides()
+    }
+
+    pub fn disk_side(&self) -> Option<usize> {
+        self.mapper.disk_side()
+    }
+
+    /// Switches to another disk side, or ejects the disk with `None`.
+    pub fn insert_disk(&mut self, side: Option<usize>) {
+        self.mapper.insert_disk(side);
+    }
+
+    pub fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        self.mapper.cpu_read(address)
+    }
+
+    pub fn cpu_write(&mut self, address: u16, data: u8) -> bool {
+        self.mapper.cpu_write(address, data)
+    }
+
+    pub fn ppu_read(&mut self, address: u16) -> Option<u8> {
+        let data = self.mapper.ppu_read(address);
+        self.mapper.ppu_fetch(address);
+        data
+    }
+
+    /// Reads CHR without the board seeing the fetch, for reads the real
+    /// PPU would never make.
+    pub fn ppu_peek(&mut self, address: u16) -> Option<u8> {
+        self.mapper.ppu_read(address)
+    }
+
+    pub fn ppu_write(&mut self, address: u16, data: u8) -> bool {
+        self.mapper.ppu_write(address, data)
+    }
+
+    pub fn mirroring(&self) -> Mirroring {
+        self.mapper.mirroring()
+    }
+
+    pub fn irq_state(&self) -> bool {
+        self.mapper.irq_state()
+    }
+
+    /// Advances the board by one CPU cycle (IRQ counters, expansion audio).
+    pub fn clock(&mut self) {
+        self.mapper.clock();
+    }
+
+    pub fn audio_output(&self) -> f32 {
+        self.mapper.audio_output()
+    }
+
+    pub fn reset(&mut self) {
+        self.mapper.reset();
+    }
+}
@@ -0,0 +1,273 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::checksum::crc32;
+use super::CartridgeError;
+
+/// Extensions of patches picked up automatically from next to the ROM, in order of preference.
+const EXTENSIONS: [&str; 3] = ["ips", "ups", "bps"];
+
+/// "EOF", which ends the records of an IPS patch.
+const IPS_EOF: usize = 0x454F46;
+
+/// Finds a patch with the same name as the ROM, if there is one.
+pub fn find(rom_path: &Path) -> Option<PathBuf> {
+    EXTENSIONS
+        .iter()
+        .map(|extension| rom_path.with_extension(extension))
+        .find(|path| path.is_file())
+}
+
+pub fn load(rom: &[u8], patch_path: &Path) -> Result<Vec<u8>, CartridgeError> {
+    let patch = fs::read(patch_path)?;
+    apply(rom, &patch)
+}
+
+/// Applies an IPS, UPS or BPS patch, picking the format from its magic number.
+/// The ROM itself is left untouched.
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, CartridgeError> {
+    if patch.starts_with(b"PATCH") {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(b"UPS1") {
+        apply_ups(rom, patch)
+    } else if patch.starts_with(b"BPS1") {
+        apply_bps(rom, patch)
+    } else {
+        Err(CartridgeError::InvalidPatch("unknown patch format"))
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], offset: usize) -> Self {
+        Self { data, offset }
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], CartridgeError> {
+        let end = self.offset.checked_add(count).filter(|&end| end <= self.data.len());
+        let end = end.ok_or(CartridgeError::InvalidPatch("patch is truncated"))?;
+        let bytes = &self.data[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, CartridgeError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn big_endian(&mut self, count: usize) -> Result<usize, CartridgeError> {
+        Ok(self.bytes(count)?.iter().fold(0, |value, &b| (value << 8) | b as usize))
+    }
+
+    /// The variable length integers of UPS and BPS: seven bits per byte,
+    /// least significant first, with the high bit marking the last byte and
+    /// an implicit +1 per continuation so every value has one encoding.
+    fn number(&mut self) -> Result<usize, CartridgeError> {
+        let mut value: usize = 0;
+        let mut shift: usize = 1;
+        loop {
+            let byte = self.byte()?;
+            value = (byte as usize & 0x7F)
+                .checked_mul(shift)
+                .and_then(|add| value.checked_add(add))
+                .ok_or(CartridgeError::InvalidPatch("number out of range"))?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift
+                .checked_mul(0x80)
+                .ok_or(CartridgeError::InvalidPatch("number out of range"))?;
+            value = value
+                .checked_add(shift)
+                .ok_or(CartridgeError::InvalidPatch("number out of range"))?;
+        }
+    }
+}
+
+/// IPS: records of (24 bit offset, 16 bit size, data) until "EOF". A size of
+/// zero is a run of one repeated byte. The common extension stores a 24 bit
+/// length after "EOF" to truncate the output to.
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, CartridgeError> {
+    let mut output = rom.to_vec();
+    let mut reader = Reader::new(patch, 5);
+
+    loop {
+        let offset = reader.big_endian(3)?;
+        if offset == IPS_EOF {
+            if reader.offset + 3 <= patch.len() {
+                output.truncate(reader.big_endian(3)?);
+            }
+            break;
+        }
+
+        let size = reader.big_endian(2)?;
+        let (length, run) = if size == 0 {
+            (reader.big_endian(2)?, Some(reader.byte()?))
+        } else {
+            (size, None)
+        };
+
+        if output.len() < offset + length {
+            output.resize(offset + length, 0);
+        }
+
+        match run {
+            Some(value) => output[offset..offset + length].fill(value),
+            None => output[offset..offset + length].copy_from_slice(reader.bytes(length)?),
+        }
+    }
+
+    Ok(output)
+}
+
+/// Reads the source, target and patch CRC-32s that end both UPS and BPS
+/// files, and checks the patch itself and the ROM it's applied to.
+fn check_footer(rom: &[u8], patch: &[u8]) -> Result<u32, CartridgeError> {
+    if patch.len() < 12 {
+        return Err(CartridgeError::InvalidPatch("patch is truncated"));
+    }
+
+    let footer = &patch[patch.len() - 12..];
+    let word = |i: usize| u32::from_le_bytes([footer[i], footer[i + 1], footer[i + 2], footer[i + 3]]);
+
+    if crc32(&patch[..patch.len() - 4]) != word(8) {
+        return Err(CartridgeError::InvalidPatch("patch checksum mismatch"));
+    }
+    if crc32(rom) != word(0) {
+        return Err(CartridgeError::InvalidPatch("patch is for a different ROM"));
+    }
+
+    Ok(word(4))
+}
+
+fn check_output(output: Vec<u8>, target_crc: u32) -> Result<Vec<u8>, CartridgeError> {
+    if crc32(&output) != target_crc {
+        return Err(CartridgeError::InvalidPatch("patched ROM checksum mismatch"));
+    }
+    Ok(output)
+}
+
+/// UPS: XOR runs at relative offsets, each ended by a zero byte.
+fn apply_ups(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, CartridgeError> {
+    let target_crc = check_footer(rom, patch)?;
+    let body = &patch[..patch.len() - 12];
+    let mut reader = Reader::new(body, 4);
+
+    let _source_size = reader.number()?;
+    let target_size = reader.number()?;
+
+    let mut output = rom.to_vec();
+    output.resize(target_size, 0);
+
+    let mut offset = 0;
+    while reader.offset < body.len() {
+        offset += reader.number()?;
+        loop {
+            let byte = reader.byte()?;
+            if offset < output.len() {
+                output[offset] ^= byte;
+            }
+            offset += 1;
+            if byte == 0 {
+                break;
+            }
+        }
+    }
+
+    check_output(output, target_crc)
+}
+
+/// BPS: the output is built from copies out of the source, the patch and
+/// the output so far.
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, CartridgeError> {
+    let target_crc = check_footer(rom, patch)?;
+    let body = &patch[..patch.len() - 12];
+    let mut reader = Reader::new(body, 4);
+
+    let _source_size = reader.number()?;
+    let target_size = reader.number()?;
+    let metadata_size = reader.number()?;
+    reader.bytes(metadata_size)?;
+
+    let out_of_range = || CartridgeError::InvalidPatch("copy out of range");
+    let mut output: Vec<u8> = Vec::with_capacity(target_size);
+    let mut source_offset: usize = 0;
+    let mut target_offset: usize = 0;
+
+    while reader.offset < body.len() {
+        let action = reader.number()?;
+        let length = (action >> 2) + 1;
+
+        match action & 0x03 {
+            0 => {
+                let start = output.len();
+                let data = rom.get(start..start + length).ok_or_else(out_of_range)?;
+                output.extend_from_slice(data);
+            }
+            1 => output.extend_from_slice(reader.bytes(length)?),
+            2 => {
+                source_offset = relative(source_offset, reader.number()?).ok_or_else(out_of_range)?;
+                let data = rom.get(source_offset..source_offset + length).ok_or_else(out_of_range)?;
+                output.extend_from_slice(data);
+                source_offset += length;
+            }
+            _ => {
+                target_offset = relative(target_offset, reader.number()?).ok_or_else(out_of_range)?;
+                // Copied one byte at a time, since the run may overlap what it's writing.
+                for _ in 0..length {
+                    let byte = *output.get(target_offset).ok_or_else(out_of_range)?;
+                    output.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+
+    if output.len() != target_size {
+        return Err(CartridgeError::InvalidPatch("patched ROM has the wrong size"));
+    }
+    check_output(output, target_crc)
+}
+
+/// BPS copy offsets are signed deltas with the sign in the lowest bit.
+fn relative(offset: usize, delta: usize) -> Option<usize> {
+    if delta & 0x01 != 0 {
+        offset.checked_sub(delta >> 1)
+    } else {
+        offset.checked_add(delta >> 1)
+    }
+}
+
+/// Builds an IPS patch that turns `original` into `modified`, which must be
+/// the same length and no larger than IPS can address.
+pub fn ips_diff(original: &[u8], modified: &[u8]) -> Vec<u8> {
+    let mut patch = b"PATCH".to_vec();
+
+    let mut position = 0;
+    while position < modified.len() {
+        if original.get(position) == Some(&modified[position]) {
+            position += 1;
+            continue;
+        }
+
+        // A record at offset "EOF" would end the patch early, so start one byte sooner.
+        let start = if position == IPS_EOF { position - 1 } else { position };
+        while position < modified.len()
+            && position - start < 0xFFFF
+            && original.get(position) != Some(&modified[position])
+        {
+            position += 1;
+        }
+
+        patch.extend_from_slice(&(start as u32).to_be_bytes()[1..]);
+        patch.extend_from_slice(&((position - start) as u16).to_be_bytes());
+        patch.extend_from_slice(&modified[start..position]);
+    }
+
+    patch.extend_from_slice(b"EOF");
+    patch
+}
@@ -0,0 +1,92 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The `.sav` file holding a cartridge's battery backed memory.
+///
+/// The file is PRG-RAM (when the board has any) followed by whatever
+/// non-volatile memory the board keeps itself, like an EEPROM. Writes are
+/// skipped when the contents haven't changed since the last save.
+pub struct SaveFile {
+    path: PathBuf,
+    last_saved: Vec<u8>,
+}
+
+impl SaveFile {
+    /// Places the save next to the ROM, or in `directory` when one is given.
+    pub fn new(rom_path: &Path, directory: Option<&Path>) -> Self {
+        let path = match (directory, rom_path.file_stem()) {
+            (Some(directory), Some(stem)) => directory.join(stem).with_extension("sav"),
+            _ => rom_path.with_extension("sav"),
+        };
+
+        Self {
+            path,
+            last_saved: Vec::new(),
+        }
+    }
+
+    /// The file for a disk image's writes, an IPS diff against the image:
+    /// `<game>.ips` in `directory`. Without a save directory it goes next
+    /// to the image as `<game>.sav.ips`, so it isn't mistaken for a patch
+    /// to apply when the game is loaded.
+    pub fn disk_writes(rom_path: &Path, directory: Option<&Path>) -> Self {
+        let path = match (directory, rom_path.file_stem()) {
+            (Some(directory), Some(stem)) => directory.join(stem).with_extension("ips"),
+            _ => rom_path.with_extension("sav.ips"),
+        };
+
+        Self {
+            path,
+            last_saved: Vec::new(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Puts the file's path in front of the message, since the bare
+    /// `io::Error` doesn't say which file it was about.
+    fn with_path(&self, error: io::Error) -> io::Error {
+        io::Error::new(error.kind(), format!("{}: {}", self.path.display(), error))
+    }
+
+    /// Returns the saved contents, or `None` if there is no save yet.
+    pub fn load(&mut self) -> io::Result<Option<Vec<u8>>> {
+        match fs::read(&self.path) {
+            Ok(data) => {
+                self.last_saved = data.clone();
+                Ok(Some(data))
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(self.with_path(error)),
+        }
+    }
+
+    pub fn store(&mut self, data: &[u8]) -> io::Result<()> {
+        self.write(data).map_err(|error| self.with_path(error))
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        if data == self.last_saved.as_slice() {
+            return Ok(());
+        }
+
+        if let Some(directory) = self.path.parent() {
+            if !directory.as_os_str().is_empty() {
+                fs::create_dir_all(directory)?;
+            }
+        }
+
+        // Write to a temporary file first so a crash mid-write can't
+        // destroy the previous save.
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        fs::write(&temporary, data)?;
+        fs::rename(&temporary, &self.path)?;
+
+        self.last_saved = data.to_vec();
+        Ok(())
+    }
+}
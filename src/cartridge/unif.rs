@@ -0,0 +1,172 @@
+use super::{CartridgeError, Console, Mirroring, Region, RomImage};
+
+const HEADER_SIZE: usize = 32;
+
+/// UNIF board names, without their "NES-"/"UNL-"/... prefix, and the
+/// iNES mapper and submapper that implement the same board.
+const BOARDS: &[(&str, u16, u8)] = &[
+    ("NROM", 0, 0),
+    ("NROM-128", 0, 0),
+    ("NROM-256", 0, 0),
+    ("HROM", 0, 0),
+    ("RROM", 0, 0),
+    ("RROM-128", 0, 0),
+    ("SROM", 0, 0),
+    ("STROM", 0, 0),
+    ("SAROM", 1, 0),
+    ("SBROM", 1, 0),
+    ("SCROM", 1, 0),
+    ("SEROM", 1, 5),
+    ("SFROM", 1, 0),
+    ("SGROM", 1, 0),
+    ("SHROM", 1, 5),
+    ("SJROM", 1, 0),
+    ("SKROM", 1, 0),
+    ("SLROM", 1, 0),
+    ("SL1ROM", 1, 0),
+    ("SL2ROM", 1, 0),
+    ("SL3ROM", 1, 0),
+    ("SLRROM", 1, 0),
+    ("SNROM", 1, 0),
+    ("SUROM", 1, 0),
+    ("UNROM", 2, 2),
+    ("UOROM", 2, 2),
+    ("CNROM", 3, 2),
+    ("AMROM", 7, 2),
+    ("ANROM", 7, 1),
+    ("AOROM", 7, 1),
+    ("PEEOROM", 9, 0),
+    ("PNROM", 9, 0),
+    ("FJROM", 10, 0),
+    ("FKROM", 10, 0),
+    ("CPROM", 13, 0),
+    ("BNROM", 34, 2),
+    ("NINA-001", 34, 1),
+    ("NINA-01", 34, 1),
+    ("GNROM", 66, 0),
+    ("MHROM", 66, 0),
+    ("NINA-03", 79, 0),
+    ("NINA-06", 79, 0),
+    ("SA-016-1M", 79, 0),
+    ("GK-192", 58, 0),
+    ("ACTION52", 228, 0),
+];
+
+/// Board names carry a prefix for who made them (NES-, HVC-, UNL-, BMC-, ...).
+/// Some boards, like AVE's NINA-001, keep a meaningful dash in the name, so
+/// try the full name first and then without the prefix.
+fn board_mapper(name: &str) -> Option<(u16, u8)> {
+    let name = name.trim().to_ascii_uppercase();
+    let stripped = name.split_once('-').map(|(_, rest)| rest.to_string());
+
+    [Some(name), stripped]
+        .iter()
+        .flatten()
+        .find_map(|candidate| {
+            BOARDS
+                .iter()
+                .find(|(board, _, _)| board == candidate)
+                .map(|&(_, mapper, submapper)| (mapper, submapper))
+        })
+}
+
+/// Parses a UNIF file: a 32 byte header followed by tagged chunks
+/// of (4 byte ID, little endian u32 length, data).
+pub fn parse(bytes: &[u8]) -> Result<RomImage, CartridgeError> {
+    if bytes.len() < HEADER_SIZE || &bytes[0..4] != b"UNIF" {
+        return Err(CartridgeError::InvalidHeader);
+    }
+
+    let mut board = None;
+    let mut prg_chunks: [Option<&[u8]>; 16] = [None; 16];
+    let mut chr_chunks: [Option<&[u8]>; 16] = [None; 16];
+    let mut mirroring = Mirroring::Horizontal;
+    let mut battery = false;
+    let mut region = None;
+
+    let mut offset = HEADER_SIZE;
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let length = u32::from_le_bytes([
+            bytes[offset + 4], bytes[offset + 5], bytes[offset + 6], bytes[offset + 7],
+        ]) as usize;
+
+        let start = offset + 8;
+        let end = start.checked_add(length).ok_or(CartridgeError::Truncated)?;
+        if end > bytes.len() {
+            return Err(CartridgeError::Truncated);
+        }
+        let data = &bytes[start..end];
+
+        match id {
+            b"MAPR" => {
+                let name = data.split(|&b| b == 0).next().unwrap_or(&[]);
+                board = Some(String::from_utf8_lossy(name).into_owned());
+            }
+            b"MIRR" if !data.is_empty() => {
+                mirroring = match data[0] {
+                    0 => Mirroring::Horizontal,
+                    1 => Mirroring::Vertical,
+                    2 => Mirroring::SingleScreenLow,
+                    3 => Mirroring::SingleScreenHigh,
+                    4 => Mirroring::FourScreen,
+                    // 5: controlled by the mapper, which will override this anyway.
+                    _ => Mirroring::Horizontal,
+                };
+            }
+            b"BATR" => battery = true,
+            b"TVCI" if !data.is_empty() => {
+                region = Some(match data[0] {
+                    0 => Region::Ntsc,
+                    1 => Region::Pal,
+                    _ => Region::Multiple,
+                });
+            }
+            _ => {
+                if let Some(index) = chunk_index(id, b"PRG") {
+                    prg_chunks[index] = Some(data);
+                } else if let Some(index) = chunk_index(id, b"CHR") {
+                    chr_chunks[index] = Some(data);
+                }
+            }
+        }
+
+        offset = end;
+    }
+
+    let board = board.ok_or(CartridgeError::InvalidHeader)?;
+    let (mapper_id, submapper) = board_mapper(&board)
+        .ok_or_else(|| CartridgeError::UnknownBoard(board.clone()))?;
+
+    let prg_rom: Vec<u8> = prg_chunks.iter().flatten().flat_map(|chunk| chunk.iter().copied()).collect();
+    let chr: Vec<u8> = chr_chunks.iter().flatten().flat_map(|chunk| chunk.iter().copied()).collect();
+    if prg_rom.is_empty() {
+        return Err(CartridgeError::Truncated);
+    }
+
+    let chr_ram = chr.is_empty();
+    let chr = if chr_ram { vec![0; 8 * 1024] } else { chr };
+
+    Ok(RomImage {
+        mapper_id,
+        submapper,
+        prg_rom,
+        chr,
+        chr_ram,
+        mirroring,
+        battery,
+        prg_ram_size: 8 * 1024,
+        region: region.unwrap_or(Region::Ntsc),
+        region_known: region.is_some(),
+        console: Console::Nes,
+    })
+}
+
+/// Matches chunk IDs like "PRG0".."PRGF" and returns the hex digit's value.
+fn chunk_index(id: &[u8], prefix: &[u8; 3]) -> Option<usize> {
+    if &id[0..3] != prefix {
+        return None;
+    }
+
+    (id[3] as char).to_digit(16).map(|digit| digit as usize)
+}
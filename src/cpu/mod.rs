@@ -42,13 +42,13 @@ impl CPU
 
     pub fn read(&self, address: u16) -> u8 {
         unsafe {
-            (*self.bus).read(address, false)
+            (*self.bus).cpu_read(address)
         }
     }
 
     pub fn write(&mut self, address: u16, data: u8) {
         unsafe {
-            (*self.bus).write(address, data);
+            (*self.bus).cpu_write(address, data);
         }
     }
 
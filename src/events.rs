@@ -0,0 +1,157 @@
+//! A per-frame log of what the CPU did to the PPU and the board, and when,
+//! for finding where a game changes scroll, banks or raises interrupts.
+
+use crate::ppu::debug::Image;
+use crate::ppu::DOTS_PER_SCANLINE;
+
+const BACKGROUND: [u8; 3] = [0x20, 0x20, 0x20];
+/// The visible picture, lines 0 to 239 and dots 1 to 256.
+const PICTURE: [u8; 3] = [0x40, 0x40, 0x40];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventKind {
+    /// A write to $2000-$2007.
+    PpuRegister,
+    /// A write to $4014 starting OAM DMA.
+    OamDma,
+    /// A write the board took, banking and IRQ registers mostly.
+    MapperRegister,
+    /// One of the IRQ sources pulling /IRQ low.
+    Irq,
+    /// The PPU signalling NMI.
+    Nmi,
+}
+
+impl EventKind {
+    fn color(&self) -> [u8; 3] {
+        match self {
+            EventKind::PpuRegister => [0x40, 0xA0, 0xFF],
+            EventKind::OamDma => [0xFF, 0xFF, 0x40],
+            EventKind::MapperRegister => [0x40, 0xFF, 0x40],
+            EventKind::Irq => [0xFF, 0x40, 0x40],
+            EventKind::Nmi => [0xFF, 0x80, 0xFF],
+        }
+    }
+}
+
+/// What can pull /IRQ low. Each is followed separately, so one asserting
+/// while another already holds the line is still logged.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IrqSource {
+    Cartridge,
+    FrameCounter,
+    Dmc,
+}
+
+impl IrqSource {
+    /// The register that controls the source, logged as the event's address.
+    fn address(&self) -> u16 {
+        match self {
+            IrqSource::Cartridge => 0,
+            IrqSource::FrameCounter => 0x4017,
+            IrqSource::Dmc => 0x4010,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Event {
+    pub kind: EventKind,
+    /// The address and value written. IRQs have the address of their
+    /// source's register (zero for the board), NMIs zero.
+    pub address: u16,
+    pub data: u8,
+    pub scanline: i16,
+    pub cycle: u16,
+}
+
+/// Collects events while enabled. Frames start on the pre-render line.
+pub struct EventLog {
+    enabled: bool,
+    current: Vec<Event>,
+    frame: Vec<Event>,
+    last_scanline: i16,
+    /// The level of each `IrqSource`, as bits.
+    irq_lines: u8,
+}
+
+impl EventLog {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            current: Vec::new(),
+            frame: Vec::new(),
+            last_scanline: -1,
+            irq_lines: 0,
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.current.clear();
+            self.frame.clear();
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Everything from the last complete frame, in order.
+    pub fn events(&self) -> &[Event] {
+        &self.frame
+    }
+
+    pub fn record(&mut self, kind: EventKind, address: u16, data: u8, scanline: i16, cycle: u16) {
+        if self.enabled {
+            self.current.push(Event { kind, address, data, scanline, cycle });
+        }
+    }
+
+    /// Follows an IRQ source's output, logging when it's asserted.
+    pub fn irq_line(&mut self, source: IrqSource, level: bool, scanline: i16, cycle: u16) {
+        let bit = 1 << source as u8;
+        if level && self.irq_lines & bit == 0 {
+            self.record(EventKind::Irq, source.address(), 0, scanline, cycle);
+        }
+        if level {
+            self.irq_lines |= bit;
+        } else {
+            self.irq_lines &= !bit;
+        }
+    }
+
+    /// Called with the PPU's position after it runs; starts a new frame
+    /// when the PPU wraps around to the pre-render line.
+    pub fn update(&mut self, scanline: i16) {
+        if scanline < self.last_scanline {
+            std::mem::swap(&mut self.current, &mut self.frame);
+            self.current.clear();
+        }
+        self.last_scanline = scanline;
+    }
+
+    /// The last frame's events as dots on a 341 dot wide grid with one row
+    /// per scanline, the pre-render line at the top. `scanlines` is 262 for
+    /// NTSC and 312 for PAL and Dendy.
+    pub fn image(&self, scanlines: usize) -> Image {
+        let width = DOTS_PER_SCANLINE as usize;
+        let mut image = Image::new(width, scanlines);
+
+        for y in 0..scanlines {
+            for x in 0..width {
+                let visible = (1..=240).contains(&y) && (1..=256).contains(&x);
+                image.set(x, y, if visible { PICTURE } else { BACKGROUND });
+            }
+        }
+
+        for event in &self.frame {
+            let y = (event.scanline + 1) as usize;
+            if y < scanlines {
+                image.set(event.cycle as usize, y, event.kind.color());
+            }
+        }
+        image
+    }
+}
@@ -1,5 +1,15 @@
 mod cpu;
+mod ppu;
+mod apu;
 mod bus;
+mod cartridge;
+mod archive;
+mod checksum;
+mod png;
+mod mappers;
+mod nsf;
+mod vs_system;
+mod events;
 
 fn main()
 {
@@ -0,0 +1,110 @@
+use crate::cartridge::{Mirroring, RomImage};
+use super::Mapper;
+
+/// 16 KB banks in each 512 KB PRG chip.
+const BANKS_PER_CHIP: usize = 32;
+
+/// Mapper 228: Active Enterprises (Action 52, Cheetahmen II).
+///
+/// Banking is decoded from the address of the write, with only the low two
+/// CHR bits taken from the data:
+///
+/// ```text
+/// A~[..MH HPPP PPO. CCCC]  D~[.... ..cc]
+/// M: mirroring (0: vertical, 1: horizontal)
+/// H: PRG chip (0, 1 or 3; there is no chip 2)
+/// P: 16 KB PRG page, O: 0 for 32 KB mode, 1 for 16 KB mode
+/// C, c: 8 KB CHR bank
+/// ```
+///
+/// There are also four nibbles of RAM at $4020-$5FFF.
+pub struct Action52 {
+    rom: RomImage,
+    prg_bank: usize,
+    prg_16k_mode: bool,
+    chr_bank: usize,
+    mirroring: Mirroring,
+    nibble_ram: [u8; 4],
+}
+
+impl Action52 {
+    pub fn new(rom: RomImage) -> Self {
+        Self {
+            rom,
+            prg_bank: 0,
+            prg_16k_mode: false,
+            chr_bank: 0,
+            mirroring: Mirroring::Vertical,
+            nibble_ram: [0; 4],
+        }
+    }
+}
+
+impl Mapper for Action52 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x4020..=0x5FFF => Some(self.nibble_ram[(address & 0x03) as usize] & 0x0F),
+            0x8000..=0xFFFF => {
+                let bank = if self.prg_16k_mode {
+                    self.prg_bank
+                } else {
+                    (self.prg_bank & !0x01) | ((address >> 14) & 0x01) as usize
+                };
+                Some(self.rom.read_prg(bank, 0x4000, address))
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> bool {
+        match address {
+            0x4020..=0x5FFF => self.nibble_ram[(address & 0x03) as usize] = data & 0x0F,
+            0x8000..=0xFFFF => {
+                let chip = match (address >> 11) & 0x03 {
+                    3 => 2,
+                    chip => chip as usize,
+                };
+                let page = ((address >> 6) & 0x1F) as usize;
+
+                self.prg_bank = chip * BANKS_PER_CHIP + page;
+                self.prg_16k_mode = address & 0x0020 != 0;
+                self.chr_bank = (((address & 0x0F) as usize) << 2) | (data & 0x03) as usize;
+                self.mirroring = if address & 0x2000 != 0 {
+                    Mirroring::Horizontal
+                } else {
+                    Mirroring::Vertical
+                };
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8> {
+        if address < 0x2000 {
+            Some(self.rom.read_chr(self.chr_bank, 0x2000, address))
+        } else {
+            None
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x2000 {
+            self.rom.write_chr(self.chr_bank, 0x2000, address, data);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn reset(&mut self) {
+        self.prg_bank = 0;
+        self.prg_16k_mode = false;
+        self.chr_bank = 0;
+    }
+}
@@ -0,0 +1,73 @@
+use crate::cartridge::{Mirroring, RomImage};
+use super::Mapper;
+
+/// Mapper 7: AxROM (AMROM, ANROM, AOROM).
+/// A latch at $8000-$FFFF selects a 32 KB PRG bank (bits 0-2) and which
+/// nametable fills the screen (bit 4). CHR is 8 KB of unbanked RAM.
+/// AMROM has bus conflicts (submapper 2); the others don't.
+pub struct AxRom {
+    rom: RomImage,
+    prg_bank: usize,
+    mirroring: Mirroring,
+}
+
+impl AxRom {
+    pub fn new(rom: RomImage) -> Self {
+        Self {
+            rom,
+            prg_bank: 0,
+            mirroring: Mirroring::SingleScreenLow,
+        }
+    }
+}
+
+impl Mapper for AxRom {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        if address >= 0x8000 {
+            Some(self.rom.read_prg(self.prg_bank, 0x8000, address))
+        } else {
+            None
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x8000 {
+            return false;
+        }
+
+        let data = if self.rom.submapper == 2 {
+            data & self.rom.read_prg(self.prg_bank, 0x8000, address)
+        } else {
+            data
+        };
+        self.prg_bank = (data & 0x07) as usize;
+        self.mirroring = if data & 0x10 != 0 {
+            Mirroring::SingleScreenHigh
+        } else {
+            Mirroring::SingleScreenLow
+        };
+
+        true
+    }
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8> {
+        if address < 0x2000 {
+            Some(self.rom.read_chr(0, 0x2000, address))
+        } else {
+            None
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x2000 {
+            self.rom.write_chr(0, 0x2000, address, data);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
@@ -0,0 +1,187 @@
+use crate::cartridge::{Mirroring, RomImage};
+use super::Mapper;
+use super::eeprom::{Eeprom, EepromKind};
+
+/// Mappers 16, 153 and 159: Bandai FCG-1/FCG-2 and LZ93D50.
+///
+/// * 16 submapper 4: FCG-1/2, registers at $6000-$7FFF, IRQ counts the counter directly.
+/// * 16 submapper 5: LZ93D50 with a 24C02, registers at $8000-$FFFF, IRQ reloads from a latch.
+/// * 16 submapper 0: unknown, registers decoded at both ranges.
+/// * 153: LZ93D50 with 8 KB of PRG-RAM; bit 0 of the CHR registers selects a 256 KB PRG half.
+/// * 159: LZ93D50 with a 24C01.
+pub struct BandaiFcg {
+    rom: RomImage,
+    low_registers: bool,
+    high_registers: bool,
+    latched_irq: bool,
+
+    chr_registers: [u8; 8],
+    prg_bank: usize,
+    mirroring: Mirroring,
+
+    irq_enabled: bool,
+    irq_counter: u16,
+    irq_latch: u16,
+    irq_pending: bool,
+
+    eeprom: Option<Eeprom>,
+}
+
+impl BandaiFcg {
+    pub fn new(rom: RomImage) -> Self {
+        let (low_registers, high_registers) = match (rom.mapper_id, rom.submapper) {
+            (16, 4) => (true, false),
+            (16, 5) | (153, _) | (159, _) => (false, true),
+            _ => (true, true),
+        };
+
+        let eeprom = match (rom.mapper_id, rom.submapper) {
+            (159, _) => Some(Eeprom::new(EepromKind::X24C01)),
+            (16, 4) | (153, _) => None,
+            _ => Some(Eeprom::new(EepromKind::X24C02)),
+        };
+
+        Self {
+            latched_irq: high_registers,
+            low_registers,
+            high_registers,
+            rom,
+            chr_registers: [0; 8],
+            prg_bank: 0,
+            mirroring: Mirroring::Vertical,
+            irq_enabled: false,
+            irq_counter: 0,
+            irq_latch: 0,
+            irq_pending: false,
+            eeprom,
+        }
+    }
+
+    /// Mapper 153 uses bit 0 of the CHR registers as PRG A18 and has CHR-RAM.
+    fn prg_outer_bank(&self) -> usize {
+        if self.rom.mapper_id == 153 {
+            let bit = self.chr_registers.iter().fold(0, |acc, r| acc | r) & 0x01;
+            bit as usize * 16
+        } else {
+            0
+        }
+    }
+
+    fn write_register(&mut self, register: u16, data: u8) {
+        match register & 0x000F {
+            0x0..=0x7 => self.chr_registers[(register & 0x07) as usize] = data,
+            0x8 => self.prg_bank = (data & 0x0F) as usize,
+            0x9 => {
+                self.mirroring = match data & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLow,
+                    _ => Mirroring::SingleScreenHigh,
+                };
+            }
+            0xA => {
+                self.irq_enabled = data & 0x01 != 0;
+                if self.latched_irq {
+                    self.irq_counter = self.irq_latch;
+                }
+                self.irq_pending = false;
+            }
+            0xB => {
+                if self.latched_irq {
+                    self.irq_latch = (self.irq_latch & 0xFF00) | data as u16;
+                } else {
+                    self.irq_counter = (self.irq_counter & 0xFF00) | data as u16;
+                }
+            }
+            0xC => {
+                if self.latched_irq {
+                    self.irq_latch = (self.irq_latch & 0x00FF) | ((data as u16) << 8);
+                } else {
+                    self.irq_counter = (self.irq_counter & 0x00FF) | ((data as u16) << 8);
+                }
+            }
+            _ => {
+                if let Some(eeprom) = &mut self.eeprom {
+                    eeprom.write_lines(data & 0x20 != 0, data & 0x40 != 0);
+                }
+            }
+        }
+    }
+}
+
+impl Mapper for BandaiFcg {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x6000..=0x7FFF => {
+                // SDA of the EEPROM is readable on D4; other boards fall through to PRG-RAM.
+                self.eeprom.as_ref().map(|eeprom| (eeprom.output() as u8) << 4)
+            }
+            0x8000..=0xBFFF => {
+                let bank = self.prg_outer_bank() | self.prg_bank;
+                Some(self.rom.read_prg(bank, 0x4000, address))
+            }
+            0xC000..=0xFFFF => {
+                let bank = self.prg_outer_bank() | 0x0F;
+                Some(self.rom.read_prg(bank, 0x4000, address))
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> bool {
+        match address {
+            0x6000..=0x7FFF if self.low_registers => self.write_register(address, data),
+            0x8000..=0xFFFF if self.high_registers => self.write_register(address, data),
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8> {
+        if address >= 0x2000 {
+            None
+        } else if self.rom.chr_ram {
+            Some(self.rom.read_chr(0, 0x2000, address))
+        } else {
+            let bank = self.chr_registers[(address >> 10) as usize] as usize;
+            Some(self.rom.read_chr(bank, 0x0400, address))
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x2000 {
+            self.rom.write_chr(0, 0x2000, address, data);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn irq_state(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn clock(&mut self) {
+        if self.irq_enabled {
+            if self.irq_counter == 0 {
+                self.irq_pending = true;
+            }
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+        }
+    }
+
+    fn nvram(&self) -> Option<&[u8]> {
+        self.eeprom.as_ref().map(|eeprom| eeprom.data())
+    }
+
+    fn load_nvram(&mut self, data: &[u8]) {
+        if let Some(eeprom) = &mut self.eeprom {
+            eeprom.load(data);
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use crate::cartridge::{Mirroring, RomImage};
+use super::Mapper;
+
+/// Mapper 34: two unrelated boards sharing a number.
+///
+/// * BNROM (submapper 2): a latch at $8000-$FFFF selects a 32 KB PRG bank,
+///   with 8 KB of unbanked CHR-RAM and bus conflicts.
+/// * NINA-001 (submapper 1): registers at $7FFD-$7FFF, overlaid on PRG-RAM,
+///   select a 32 KB PRG bank and two 4 KB CHR-ROM banks.
+///
+/// Without a submapper, more than 8 KB of CHR means NINA-001.
+pub struct Bnrom {
+    rom: RomImage,
+    nina: bool,
+    prg_bank: usize,
+    chr_banks: [usize; 2],
+}
+
+impl Bnrom {
+    pub fn new(rom: RomImage) -> Self {
+        let nina = match rom.submapper {
+            1 => true,
+            2 => false,
+            _ => rom.chr.len() > 0x2000,
+        };
+
+        Self {
+            rom,
+            nina,
+            prg_bank: 0,
+            chr_banks: [0, 1],
+        }
+    }
+}
+
+impl Mapper for Bnrom {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        if address >= 0x8000 {
+            Some(self.rom.read_prg(self.prg_bank, 0x8000, address))
+        } else {
+            None
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> bool {
+        if self.nina {
+            // The registers don't claim the write, so PRG-RAM still stores it.
+            match address {
+                0x7FFD => self.prg_bank = (data & 0x01) as usize,
+                0x7FFE => self.chr_banks[0] = (data & 0x0F) as usize,
+                0x7FFF => self.chr_banks[1] = (data & 0x0F) as usize,
+                _ => {}
+            }
+            false
+        } else if address >= 0x8000 {
+            let data = data & self.rom.read_prg(self.prg_bank, 0x8000, address);
+            self.prg_bank = data as usize;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8> {
+        if address < 0x2000 {
+            let bank = self.chr_banks[(address >> 12) as usize];
+            Some(self.rom.read_chr(bank, 0x1000, address))
+        } else {
+            None
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x2000 {
+            let bank = self.chr_banks[(address >> 12) as usize];
+            self.rom.write_chr(bank, 0x1000, address, data);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.rom.mirroring
+    }
+}
@@ -0,0 +1,109 @@
+use crate::cartridge::{Mirroring, RomImage};
+use super::Mapper;
+
+/// Mappers 71 and 232: Camerica/Codemasters BF909x boards.
+///
+/// These carts defeat the CIC lockout with a charge pump instead of a
+/// lockout chip, which needs nothing from the emulator, so only the banking
+/// is modelled.
+///
+/// * 71: a 16 KB PRG bank at $8000 selected through $C000-$FFFF, the last bank
+///   fixed at $C000. The BF9097 (submapper 1, Fire Hawk) also has a one-screen
+///   mirroring select in bit 4 of $8000-$9FFF. Without a submapper the
+///   mirroring control is switched on by the first write to $9000-$9FFF,
+///   which Fire Hawk makes and the other mapper 71 games never do; they
+///   can write to $8000 without meaning to change the mirroring.
+/// * 232: the BF9096 Quattro multicarts, with a 64 KB outer bank at
+///   $8000-$BFFF and the 16 KB inner bank at $C000-$FFFF. Submapper 1 (the
+///   Aladdin Deck Enhancer) swaps the two outer bank bits.
+pub struct Camerica {
+    rom: RomImage,
+    quattro: bool,
+
+    prg_bank: usize,
+    outer_bank: usize,
+    mirroring_control: bool,
+    mirroring: Mirroring,
+}
+
+impl Camerica {
+    pub fn new(rom: RomImage) -> Self {
+        Self {
+            quattro: rom.mapper_id == 232,
+            mirroring_control: rom.mapper_id == 71 && rom.submapper == 1,
+            mirroring: rom.mirroring,
+            rom,
+            prg_bank: 0,
+            outer_bank: 0,
+        }
+    }
+}
+
+impl Mapper for Camerica {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        if address < 0x8000 {
+            return None;
+        }
+
+        let bank = match (self.quattro, address < 0xC000) {
+            (false, true) => self.prg_bank,
+            (false, false) => self.rom.prg_banks(0x4000) - 1,
+            (true, true) => (self.outer_bank << 2) | self.prg_bank,
+            (true, false) => (self.outer_bank << 2) | 0x03,
+        };
+
+        Some(self.rom.read_prg(bank, 0x4000, address))
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> bool {
+        match address {
+            0x8000..=0xBFFF if self.quattro => {
+                self.outer_bank = if self.rom.submapper == 1 {
+                    ((data >> 4) & 0x01 | (data >> 2) & 0x02) as usize
+                } else {
+                    ((data >> 3) & 0x03) as usize
+                };
+            }
+            0x8000..=0x9FFF => {
+                // Only Fire Hawk writes here, and it uses $9000.
+                if self.rom.submapper == 0 && address >= 0x9000 {
+                    self.mirroring_control = true;
+                }
+
+                if self.mirroring_control {
+                    self.mirroring = if data & 0x10 != 0 {
+                        Mirroring::SingleScreenHigh
+                    } else {
+                        Mirroring::SingleScreenLow
+                    };
+                }
+            }
+            0xC000..=0xFFFF if self.quattro => self.prg_bank = (data & 0x03) as usize,
+            0xC000..=0xFFFF => self.prg_bank = (data & 0x0F) as usize,
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8> {
+        if address < 0x2000 {
+            Some(self.rom.read_chr(0, 0x2000, address))
+        } else {
+            None
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x2000 {
+            self.rom.write_chr(0, 0x2000, address, data);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
@@ -0,0 +1,62 @@
+use crate::cartridge::{Mirroring, RomImage};
+use super::Mapper;
+
+/// Mapper 3: CNROM.
+/// Up to 32 KB of unbanked PRG-ROM, with a latch at $8000-$FFFF selecting
+/// the 8 KB CHR bank. Submapper 1 has no bus conflicts.
+pub struct Cnrom {
+    rom: RomImage,
+    chr_bank: usize,
+}
+
+impl Cnrom {
+    pub fn new(rom: RomImage) -> Self {
+        Self { rom, chr_bank: 0 }
+    }
+}
+
+impl Mapper for Cnrom {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        if address >= 0x8000 {
+            Some(self.rom.read_prg(0, 0x8000, address))
+        } else {
+            None
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x8000 {
+            return false;
+        }
+
+        let data = if self.rom.submapper == 1 {
+            data
+        } else {
+            data & self.rom.read_prg(0, 0x8000, address)
+        };
+        self.chr_bank = data as usize;
+
+        true
+    }
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8> {
+        if address < 0x2000 {
+            Some(self.rom.read_chr(self.chr_bank, 0x2000, address))
+        } else {
+            None
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x2000 {
+            self.rom.write_chr(self.chr_bank, 0x2000, address, data);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.rom.mirroring
+    }
+}
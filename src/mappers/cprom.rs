@@ -0,0 +1,68 @@
+use crate::cartridge::{Mirroring, RomImage};
+use super::Mapper;
+
+/// Mapper 13: CPROM, used only by Videomation.
+/// 32 KB of unbanked PRG-ROM and 16 KB of CHR-RAM: the first 4 KB is fixed
+/// at $0000 and a latch at $8000-$FFFF (bits 0-1) picks the 4 KB at $1000.
+/// The board has bus conflicts.
+pub struct Cprom {
+    rom: RomImage,
+    chr_bank: usize,
+}
+
+impl Cprom {
+    pub fn new(mut rom: RomImage) -> Self {
+        // Headers often only ask for the usual 8 KB.
+        if rom.chr_ram && rom.chr.len() < 0x4000 {
+            rom.chr.resize(0x4000, 0);
+        }
+
+        Self { rom, chr_bank: 0 }
+    }
+
+    fn chr_bank(&self, address: u16) -> usize {
+        if address < 0x1000 { 0 } else { self.chr_bank }
+    }
+}
+
+impl Mapper for Cprom {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        if address >= 0x8000 {
+            Some(self.rom.read_prg(0, 0x8000, address))
+        } else {
+            None
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x8000 {
+            return false;
+        }
+
+        let data = data & self.rom.read_prg(0, 0x8000, address);
+        self.chr_bank = (data & 0x03) as usize;
+
+        true
+    }
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8> {
+        if address < 0x2000 {
+            Some(self.rom.read_chr(self.chr_bank(address), 0x1000, address))
+        } else {
+            None
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x2000 {
+            self.rom.write_chr(self.chr_bank(address), 0x1000, address, data);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.rom.mirroring
+    }
+}
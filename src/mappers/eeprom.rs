@@ -0,0 +1,189 @@
+/// Serial EEPROM chips driven by bit-banging SCL/SDA through a mapper register.
+#[derive(Copy, Clone, PartialEq)]
+pub enum EepromKind {
+    /// 128 bytes, the first byte after a start condition is the address and R/W bit.
+    X24C01,
+    /// 256 bytes, standard I2C with a device select byte before the address.
+    X24C02,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum State {
+    Idle,
+    DeviceSelect,
+    Address,
+    Write,
+    Read,
+}
+
+/// Acknowledge handshake after a byte has been received:
+/// drive SDA low on the next falling edge, hold it through the ninth
+/// clock, and release it on the falling edge after that.
+#[derive(Copy, Clone, PartialEq)]
+enum Ack {
+    None,
+    Pending,
+    Driving,
+    Sampled,
+}
+
+pub struct Eeprom {
+    kind: EepromKind,
+    data: Vec<u8>,
+    state: State,
+    ack: Ack,
+    bit: u8,
+    shift: u8,
+    address: u8,
+    scl: bool,
+    sda: bool,
+    output: bool,
+}
+
+impl Eeprom {
+    pub fn new(kind: EepromKind) -> Self {
+        let size = match kind {
+            EepromKind::X24C01 => 128,
+            EepromKind::X24C02 => 256,
+        };
+
+        Self {
+            kind,
+            data: vec![0xFF; size],
+            state: State::Idle,
+            ack: Ack::None,
+            bit: 0,
+            shift: 0,
+            address: 0,
+            scl: false,
+            sda: false,
+            output: true,
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn load(&mut self, data: &[u8]) {
+        let length = data.len().min(self.data.len());
+        self.data[..length].copy_from_slice(&data[..length]);
+    }
+
+    /// The level the chip drives on SDA (open collector, so true when released).
+    pub fn output(&self) -> bool {
+        self.output
+    }
+
+    pub fn write_lines(&mut self, scl: bool, sda: bool) {
+        if self.scl && scl && self.sda && !sda {
+            self.start();
+        } else if self.scl && scl && !self.sda && sda {
+            self.stop();
+        } else if !self.scl && scl {
+            self.rising_edge(sda);
+        } else if self.scl && !scl {
+            self.falling_edge();
+        }
+
+        self.scl = scl;
+        self.sda = sda;
+    }
+
+    fn start(&mut self) {
+        self.state = State::DeviceSelect;
+        self.ack = Ack::None;
+        self.bit = 0;
+        self.shift = 0;
+        self.output = true;
+    }
+
+    fn stop(&mut self) {
+        self.state = State::Idle;
+        self.ack = Ack::None;
+        self.output = true;
+    }
+
+    fn rising_edge(&mut self, sda: bool) {
+        if self.ack == Ack::Driving {
+            self.ack = Ack::Sampled;
+            return;
+        }
+
+        match self.state {
+            State::Idle => {}
+            State::Read => {
+                if self.bit < 8 {
+                    self.bit += 1;
+                } else if !sda {
+                    // The master acknowledged, so it wants the next byte.
+                    self.address = self.address.wrapping_add(1);
+                    self.bit = 0;
+                } else {
+                    self.state = State::Idle;
+                }
+            }
+            _ => {
+                self.shift = (self.shift << 1) | sda as u8;
+                self.bit += 1;
+                if self.bit == 8 {
+                    self.bit = 0;
+                    self.receive(self.shift);
+                }
+            }
+        }
+    }
+
+    fn falling_edge(&mut self) {
+        match self.ack {
+            Ack::Pending => {
+                self.output = false;
+                self.ack = Ack::Driving;
+                return;
+            }
+            Ack::Sampled => {
+                self.output = true;
+                self.ack = Ack::None;
+            }
+            _ => {}
+        }
+
+        if self.state == State::Read {
+            self.output = if self.bit < 8 {
+                let byte = self.data[self.address as usize % self.data.len()];
+                (byte >> (7 - self.bit)) & 0x01 != 0
+            } else {
+                true
+            };
+        }
+    }
+
+    fn receive(&mut self, byte: u8) {
+        let size = self.data.len();
+
+        match (self.state, self.kind) {
+            (State::DeviceSelect, EepromKind::X24C01) => {
+                self.address = byte >> 1;
+                self.state = if byte & 0x01 != 0 { State::Read } else { State::Write };
+            }
+            (State::DeviceSelect, EepromKind::X24C02) => {
+                if byte & 0xF0 != 0xA0 {
+                    self.state = State::Idle;
+                    return;
+                }
+                self.state = if byte & 0x01 != 0 { State::Read } else { State::Address };
+            }
+            (State::Address, _) => {
+                self.address = byte;
+                self.state = State::Write;
+            }
+            (State::Write, _) => {
+                self.data[self.address as usize % size] = byte;
+                self.address = self.address.wrapping_add(1);
+            }
+            _ => {}
+        }
+
+        self.ack = Ack::Pending;
+    }
+}
@@ -0,0 +1,527 @@
+use crate::cartridge::{self, Mirroring};
+use crate::cartridge::fds::SIDE_SIZE;
+use super::Mapper;
+use super::fds_audio::FdsAudio;
+
+/// The drive writes gaps of zero bits between blocks: a long one before the
+/// first block and a short one after each block.
+const LEAD_IN_GAP: usize = 28300 / 8;
+const BLOCK_GAP: usize = 976 / 8;
+
+/// Roughly how long one byte takes to pass under the head, in CPU cycles.
+const BYTE_CYCLES: u32 = 149;
+
+/// Time for the head to return to the start of the disk, in CPU cycles.
+const REWIND_CYCLES: u32 = 50000;
+
+/// How long a disk stays out of the drive when switching sides, in CPU
+/// cycles. The BIOS has to see the drive empty to notice the change.
+const SWAP_CYCLES: u32 = 1_789_773;
+
+/// Famicom Disk System: the RAM adapter with 32 KB of PRG-RAM, 8 KB of
+/// CHR-RAM, the BIOS, a timer IRQ, a wavetable sound channel and the disk
+/// drive interface.
+///
+/// Disk sides are kept as the byte stream the drive would see, gaps and
+/// CRCs included, and converted back to .fds form for saving. Writes are
+/// kept as an IPS diff against the original image, which is saved as the
+/// board's NVRAM to the game's `.ips` file; the image itself is never
+/// modified.
+pub struct Fds {
+    bios: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr_ram: Vec<u8>,
+
+    original: Vec<u8>,
+    sides: Vec<Vec<u8>>,
+    diff: Vec<u8>,
+    modified: bool,
+
+    side: Option<usize>,
+    next_side: Option<usize>,
+    swap_delay: u32,
+
+    timer_reload: u16,
+    timer_counter: u16,
+    timer_repeat: bool,
+    timer_enabled: bool,
+    timer_irq: bool,
+
+    disk_registers_enabled: bool,
+    sound_registers_enabled: bool,
+
+    write_data: u8,
+    read_data: u8,
+    motor_on: bool,
+    reset_transfer: bool,
+    read_mode: bool,
+    mirroring: Mirroring,
+    crc_control: bool,
+    previous_crc_control: bool,
+    disk_ready: bool,
+    disk_irq_enabled: bool,
+    disk_irq: bool,
+
+    transfer_complete: bool,
+    end_of_head: bool,
+    scanning: bool,
+    gap_ended: bool,
+    position: usize,
+    delay: u32,
+    crc: u16,
+
+    audio: FdsAudio,
+}
+
+impl Fds {
+    /// `disk` holds the sides back to back in .fds form, without the fwNES header.
+    pub fn new(bios: Vec<u8>, disk: Vec<u8>) -> Self {
+        let sides = disk.chunks(SIDE_SIZE).map(raw_side).collect();
+
+        Self {
+            bios,
+            prg_ram: vec![0; 32 * 1024],
+            chr_ram: vec![0; 8 * 1024],
+            original: disk,
+            sides,
+            diff: Vec::new(),
+            modified: false,
+            side: Some(0),
+            next_side: None,
+            swap_delay: 0,
+            timer_reload: 0,
+            timer_counter: 0,
+            timer_repeat: false,
+            timer_enabled: false,
+            timer_irq: false,
+            disk_registers_enabled: true,
+            sound_registers_enabled: true,
+            write_data: 0,
+            read_data: 0,
+            motor_on: false,
+            reset_transfer: false,
+            read_mode: true,
+            mirroring: Mirroring::Horizontal,
+            crc_control: false,
+            previous_crc_control: false,
+            disk_ready: false,
+            disk_irq_enabled: false,
+            disk_irq: false,
+            transfer_complete: false,
+            end_of_head: true,
+            scanning: false,
+            gap_ended: false,
+            position: 0,
+            delay: 0,
+            crc: 0,
+            audio: FdsAudio::new(),
+        }
+    }
+
+    /// The whole disk in .fds form, with whatever has been written to it.
+    fn disk_image(&self) -> Vec<u8> {
+        self.sides.iter().flat_map(|raw| fds_side(raw)).collect()
+    }
+
+    fn update_diff(&mut self) {
+        if self.modified {
+            self.diff = cartridge::ips_diff(&self.original, &self.disk_image());
+            self.modified = false;
+        }
+    }
+
+    fn update_crc(&mut self, data: u8) {
+        self.crc = crc_step(self.crc, data);
+    }
+
+    fn clock_timer(&mut self) {
+        if !self.timer_enabled || !self.disk_registers_enabled {
+            return;
+        }
+
+        if self.timer_counter == 0 {
+            self.timer_irq = true;
+            self.timer_counter = self.timer_reload;
+            if !self.timer_repeat {
+                self.timer_enabled = false;
+            }
+        } else {
+            self.timer_counter -= 1;
+        }
+    }
+
+    fn clock_drive(&mut self) {
+        let Some(side) = self.side else {
+            self.end_of_head = true;
+            self.scanning = false;
+            return;
+        };
+
+        if !self.motor_on {
+            self.end_of_head = true;
+            self.scanning = false;
+            return;
+        }
+
+        if self.reset_transfer && !self.scanning {
+            return;
+        }
+
+        if self.end_of_head {
+            self.delay = REWIND_CYCLES;
+            self.end_of_head = false;
+            self.position = 0;
+            self.gap_ended = false;
+            return;
+        }
+
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        self.scanning = true;
+        let mut irq = self.disk_irq_enabled;
+
+        if self.read_mode {
+            let data = self.sides[side].get(self.position).copied().unwrap_or(0);
+
+            if !self.previous_crc_control {
+                self.update_crc(data);
+            }
+
+            if !self.disk_ready {
+                self.gap_ended = false;
+                self.crc = 0;
+            } else if data != 0 && !self.gap_ended {
+                // The start mark ending the gap doesn't raise an IRQ.
+                self.gap_ended = true;
+                irq = false;
+            }
+
+            if self.gap_ended {
+                self.transfer_complete = true;
+                self.read_data = data;
+                self.disk_irq |= irq;
+            }
+        } else {
+            let mut data = 0;
+            if !self.crc_control {
+                self.transfer_complete = true;
+                data = self.write_data;
+                self.disk_irq |= irq;
+            }
+
+            if !self.disk_ready {
+                data = 0;
+            }
+
+            if !self.crc_control {
+                self.update_crc(data);
+            } else {
+                if !self.previous_crc_control {
+                    self.update_crc(0);
+                    self.update_crc(0);
+                }
+                data = self.crc as u8;
+                self.crc >>= 8;
+            }
+
+            if let Some(byte) = self.sides[side].get_mut(self.position) {
+                *byte = data;
+                self.modified = true;
+            }
+            self.gap_ended = false;
+        }
+
+        self.previous_crc_control = self.crc_control;
+
+        self.position += 1;
+        if self.position >= self.sides[side].len() {
+            self.motor_on = false;
+            self.end_of_head = true;
+            self.update_diff();
+        } else {
+            self.delay = BYTE_CYCLES;
+        }
+    }
+}
+
+impl Mapper for Fds {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x4030 if self.disk_registers_enabled => {
+                let data = (self.timer_irq as u8)
+                    | (self.transfer_complete as u8) << 1
+                    | (self.end_of_head as u8) << 6
+                    | (self.disk_registers_enabled as u8) << 7;
+
+                self.transfer_complete = false;
+                self.timer_irq = false;
+                self.disk_irq = false;
+                Some(data)
+            }
+            0x4031 if self.disk_registers_enabled => {
+                self.transfer_complete = false;
+                self.disk_irq = false;
+                Some(self.read_data)
+            }
+            0x4032 if self.disk_registers_enabled => {
+                let inserted = self.side.is_some();
+                Some(
+                    0x40 | (!inserted as u8)
+                        | ((!inserted || !self.scanning) as u8) << 1
+                        | (!inserted as u8) << 2,
+                )
+            }
+            // Bit 7 reads the battery as good.
+            0x4033 if self.disk_registers_enabled => Some(0x80),
+            0x4040..=0x4097 if self.sound_registers_enabled => self.audio.read(address),
+            0x6000..=0xDFFF => Some(self.prg_ram[(address - 0x6000) as usize]),
+            0xE000..=0xFFFF => Some(self.bios[(address & 0x1FFF) as usize % self.bios.len()]),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> bool {
+        match address {
+            0x4020 if self.disk_registers_enabled => {
+                self.timer_reload = (self.timer_reload & 0xFF00) | data as u16;
+            }
+            0x4021 if self.disk_registers_enabled => {
+                self.timer_reload = (self.timer_reload & 0x00FF) | (data as u16) << 8;
+            }
+            0x4022 if self.disk_registers_enabled => {
+                self.timer_repeat = data & 0x01 != 0;
+                self.timer_enabled = data & 0x02 != 0;
+                if self.timer_enabled {
+                    self.timer_counter = self.timer_reload;
+                } else {
+                    self.timer_irq = false;
+                }
+            }
+            0x4023 => {
+                self.disk_registers_enabled = data & 0x01 != 0;
+                self.sound_registers_enabled = data & 0x02 != 0;
+                if !self.disk_registers_enabled {
+                    self.timer_enabled = false;
+                    self.timer_irq = false;
+                    self.disk_irq = false;
+                }
+            }
+            0x4024 if self.disk_registers_enabled => {
+                self.write_data = data;
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+            0x4025 if self.disk_registers_enabled => {
+                let motor_was_on = self.motor_on;
+                self.motor_on = data & 0x01 != 0;
+                self.reset_transfer = data & 0x02 != 0;
+                self.read_mode = data & 0x04 != 0;
+                self.mirroring = if data & 0x08 != 0 {
+                    Mirroring::Horizontal
+                } else {
+                    Mirroring::Vertical
+                };
+                self.crc_control = data & 0x10 != 0;
+                self.disk_ready = data & 0x40 != 0;
+                self.disk_irq_enabled = data & 0x80 != 0;
+                self.disk_irq = false;
+
+                if motor_was_on && !self.motor_on {
+                    self.update_diff();
+                }
+            }
+            // External connector output, unused.
+            0x4026 if self.disk_registers_enabled => {}
+            0x4040..=0x4097 if self.sound_registers_enabled => self.audio.write(address, data),
+            0x6000..=0xDFFF => self.prg_ram[(address - 0x6000) as usize] = data,
+            0xE000..=0xFFFF => {}
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8> {
+        if address < 0x2000 {
+            Some(self.chr_ram[address as usize])
+        } else {
+            None
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x2000 {
+            self.chr_ram[address as usize] = data;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn irq_state(&self) -> bool {
+        self.timer_irq || self.disk_irq
+    }
+
+    fn clock(&mut self) {
+        self.clock_timer();
+        self.clock_drive();
+        self.audio.clock();
+
+        if self.swap_delay > 0 {
+            self.swap_delay -= 1;
+            if self.swap_delay == 0 {
+                self.side = self.next_side.take();
+            }
+        }
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn nvram(&self) -> Option<&[u8]> {
+        if self.diff.is_empty() {
+            None
+        } else {
+            Some(&self.diff)
+        }
+    }
+
+    /// Replays the saved diff on top of the original image.
+    fn load_nvram(&mut self, data: &[u8]) {
+        if let Ok(disk) = cartridge::apply_patch(&self.original, data) {
+            if disk.len() == self.original.len() {
+                self.sides = disk.chunks(SIDE_SIZE).map(raw_side).collect();
+                self.diff = data.to_vec();
+            }
+        }
+    }
+
+    fn disk_sides(&self) -> usize {
+        self.sides.len()
+    }
+
+    fn disk_side(&self) -> Option<usize> {
+        self.side
+    }
+
+    /// Ejects the disk, then inserts `side` after a delay long enough for
+    /// the BIOS to notice the drive was empty.
+    fn insert_disk(&mut self, side: Option<usize>) {
+        self.update_diff();
+        self.side = None;
+        self.next_side = side.filter(|&side| side < self.sides.len());
+        self.swap_delay = if self.next_side.is_some() { SWAP_CYCLES } else { 0 };
+    }
+
+    fn reset(&mut self) {
+        self.timer_enabled = false;
+        self.timer_irq = false;
+        self.disk_irq = false;
+        self.motor_on = false;
+        self.disk_registers_enabled = true;
+        self.sound_registers_enabled = true;
+    }
+}
+
+/// The drive's CRC-16 (polynomial $8408, reflected), fed one byte at a time
+/// starting with the block's start mark.
+fn crc_step(crc: u16, data: u8) -> u16 {
+    let mut crc = crc;
+    for bit in 0..8 {
+        let carry = crc & 0x01 != 0;
+        crc >>= 1;
+        if carry {
+            crc ^= 0x8408;
+        }
+        if data & (1 << bit) != 0 {
+            crc ^= 0x8000;
+        }
+    }
+    crc
+}
+
+/// Length of the block starting at `position` of a side in .fds form, or
+/// `None` at the end of the data. Blocks are the disk info (1), file count
+/// (2), and then a header (3) and data (4) block for each file.
+fn block_length(side: &[u8], position: usize, file_size: usize) -> Option<usize> {
+    let length = match side.get(position)? {
+        1 => 56,
+        2 => 2,
+        3 => 16,
+        4 => 1 + file_size,
+        _ => return None,
+    };
+
+    (position + length <= side.len()).then_some(length)
+}
+
+/// File data blocks take their size from the header block before them.
+fn file_size(header: &[u8]) -> usize {
+    header[13] as usize | (header[14] as usize) << 8
+}
+
+/// Expands a side from .fds form into what the drive reads: gaps, a start
+/// mark before each block and a CRC after it.
+fn raw_side(side: &[u8]) -> Vec<u8> {
+    let mut raw = vec![0; LEAD_IN_GAP];
+    let mut position = 0;
+    let mut size = 0;
+
+    while let Some(length) = block_length(side, position, size) {
+        let block = &side[position..position + length];
+        if block[0] == 3 {
+            size = file_size(block);
+        }
+
+        let crc = [0x80].iter().chain(block).chain(&[0, 0]).fold(0, |crc, &data| crc_step(crc, data));
+
+        raw.push(0x80);
+        raw.extend_from_slice(block);
+        raw.extend_from_slice(&crc.to_le_bytes());
+        raw.resize(raw.len() + BLOCK_GAP, 0);
+        position += length;
+    }
+
+    // Leave the rest of the side blank for games that add files.
+    raw.resize(raw.len().max(SIDE_SIZE + LEAD_IN_GAP + 256 * BLOCK_GAP), 0);
+    raw
+}
+
+/// The reverse of `raw_side`: drops the gaps and CRCs again.
+fn fds_side(raw: &[u8]) -> Vec<u8> {
+    let mut side = Vec::with_capacity(SIDE_SIZE);
+    let mut position = 0;
+    let mut size = 0;
+
+    loop {
+        while raw.get(position) == Some(&0) {
+            position += 1;
+        }
+        if raw.get(position) != Some(&0x80) {
+            break;
+        }
+        position += 1;
+
+        let Some(length) = block_length(raw, position, size) else {
+            break;
+        };
+        let block = &raw[position..position + length];
+        if block[0] == 3 {
+            size = file_size(block);
+        }
+
+        side.extend_from_slice(block);
+        position += length + 2;
+    }
+
+    side.resize(SIDE_SIZE, 0);
+    side
+}
@@ -0,0 +1,238 @@
+/// Output levels for the four settings of the master volume in $4089.
+const MASTER_VOLUME: [f32; 4] = [1.0, 2.0 / 3.0, 2.0 / 4.0, 2.0 / 5.0];
+
+/// Modulation table entries: how much each step moves the modulation
+/// counter, with 4 resetting it to zero instead.
+const MODULATION_STEP: [i8; 8] = [0, 1, 2, 4, 0, -4, -2, -1];
+
+/// The volume and modulation envelopes. When enabled, the gain moves one
+/// step towards 0 or 32 every 8 * (speed + 1) * master speed CPU cycles.
+struct Envelope {
+    speed: u8,
+    gain: u8,
+    increase: bool,
+    disabled: bool,
+    timer: u32,
+}
+
+impl Envelope {
+    fn new() -> Self {
+        Self {
+            speed: 0,
+            gain: 0,
+            increase: false,
+            disabled: true,
+            timer: 0,
+        }
+    }
+
+    /// $4080 / $4084: `[MDSS SSSS]`, M disables the envelope with S as the
+    /// gain, D set to increase.
+    fn write(&mut self, data: u8) {
+        self.speed = data & 0x3F;
+        self.increase = data & 0x40 != 0;
+        self.disabled = data & 0x80 != 0;
+        if self.disabled {
+            self.gain = data & 0x3F;
+        }
+        self.timer = 0;
+    }
+
+    fn clock(&mut self, master_speed: u8) {
+        if self.disabled {
+            return;
+        }
+
+        self.timer += 1;
+        if self.timer < 8 * (self.speed as u32 + 1) * master_speed as u32 {
+            return;
+        }
+        self.timer = 0;
+
+        if self.increase && self.gain < 32 {
+            self.gain += 1;
+        } else if !self.increase && self.gain > 0 {
+            self.gain -= 1;
+        }
+    }
+}
+
+/// The RAM adapter's sound channel: a 64 step, 6 bit wavetable whose pitch
+/// is bent by a second table through the modulation unit.
+pub struct FdsAudio {
+    wave_table: [u8; 64],
+    wave_write_enabled: bool,
+    wave_frequency: u16,
+    wave_halted: bool,
+    wave_accumulator: u32,
+    wave_output: u8,
+
+    envelopes_disabled: bool,
+    master_envelope_speed: u8,
+    master_volume: usize,
+    volume: Envelope,
+
+    modulation: Envelope,
+    modulation_table: [u8; 64],
+    modulation_position: usize,
+    modulation_frequency: u16,
+    modulation_halted: bool,
+    modulation_accumulator: u32,
+    modulation_counter: i8,
+}
+
+impl FdsAudio {
+    pub fn new() -> Self {
+        Self {
+            wave_table: [0; 64],
+            wave_write_enabled: false,
+            wave_frequency: 0,
+            wave_halted: true,
+            wave_accumulator: 0,
+            wave_output: 0,
+            envelopes_disabled: false,
+            master_envelope_speed: 0xE8,
+            master_volume: 0,
+            volume: Envelope::new(),
+            modulation: Envelope::new(),
+            modulation_table: [0; 64],
+            modulation_position: 0,
+            modulation_frequency: 0,
+            modulation_halted: true,
+            modulation_accumulator: 0,
+            modulation_counter: 0,
+        }
+    }
+
+    pub fn read(&self, address: u16) -> Option<u8> {
+        match address {
+            0x4040..=0x407F => {
+                let index = (address - 0x4040) as usize;
+                let data = if self.wave_write_enabled {
+                    self.wave_table[index]
+                } else {
+                    self.wave_table[self.wave_position()]
+                };
+                Some(data | 0x40)
+            }
+            0x4090 => Some(self.volume.gain | 0x40),
+            0x4092 => Some(self.modulation.gain | 0x40),
+            _ => None,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, data: u8) {
+        match address {
+            0x4040..=0x407F if self.wave_write_enabled => {
+                self.wave_table[(address - 0x4040) as usize] = data & 0x3F;
+            }
+            0x4080 => self.volume.write(data),
+            0x4082 => self.wave_frequency = (self.wave_frequency & 0x0F00) | data as u16,
+            0x4083 => {
+                self.wave_frequency = (self.wave_frequency & 0x00FF) | ((data & 0x0F) as u16) << 8;
+                self.envelopes_disabled = data & 0x40 != 0;
+                self.wave_halted = data & 0x80 != 0;
+                if self.wave_halted {
+                    self.wave_accumulator = 0;
+                }
+            }
+            0x4084 => self.modulation.write(data),
+            0x4085 => {
+                // Seven bit signed.
+                self.modulation_counter = ((data & 0x7F) << 1) as i8 >> 1;
+            }
+            0x4086 => self.modulation_frequency = (self.modulation_frequency & 0x0F00) | data as u16,
+            0x4087 => {
+                self.modulation_frequency =
+                    (self.modulation_frequency & 0x00FF) | ((data & 0x0F) as u16) << 8;
+                self.modulation_halted = data & 0x80 != 0;
+                if self.modulation_halted {
+                    self.modulation_accumulator = 0;
+                }
+            }
+            // Each write fills two entries, and only while modulation is halted.
+            0x4088 if self.modulation_halted => {
+                for _ in 0..2 {
+                    self.modulation_table[self.modulation_position] = data & 0x07;
+                    self.modulation_position = (self.modulation_position + 1) & 0x3F;
+                }
+            }
+            0x4089 => {
+                self.master_volume = (data & 0x03) as usize;
+                self.wave_write_enabled = data & 0x80 != 0;
+            }
+            0x408A => self.master_envelope_speed = data,
+            _ => {}
+        }
+    }
+
+    fn wave_position(&self) -> usize {
+        ((self.wave_accumulator >> 16) & 0x3F) as usize
+    }
+
+    /// The wave frequency bent by the modulation counter and gain, with the
+    /// rounding the hardware does.
+    fn modulated_frequency(&self) -> i32 {
+        let pitch = self.wave_frequency as i32;
+
+        let mut temp = self.modulation_counter as i32 * self.modulation.gain as i32;
+        let remainder = temp & 0x0F;
+        temp >>= 4;
+        if remainder > 0 && temp & 0x80 == 0 {
+            temp += if self.modulation_counter < 0 { -1 } else { 2 };
+        }
+
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+
+        temp *= pitch;
+        let remainder = temp & 0x3F;
+        temp >>= 6;
+        if remainder >= 32 {
+            temp += 1;
+        }
+
+        pitch + temp
+    }
+
+    pub fn clock(&mut self) {
+        if !self.envelopes_disabled && !self.wave_halted && self.master_envelope_speed != 0 {
+            self.volume.clock(self.master_envelope_speed);
+            self.modulation.clock(self.master_envelope_speed);
+        }
+
+        if !self.modulation_halted && self.modulation_frequency != 0 {
+            self.modulation_accumulator += self.modulation_frequency as u32;
+            if self.modulation_accumulator >= 0x10000 {
+                self.modulation_accumulator -= 0x10000;
+
+                let step = self.modulation_table[self.modulation_position];
+                self.modulation_counter = if step == 4 {
+                    0
+                } else {
+                    // Wraps around within seven bits.
+                    ((self.modulation_counter.wrapping_add(MODULATION_STEP[step as usize]) as u8) << 1) as i8 >> 1
+                };
+                self.modulation_position = (self.modulation_position + 1) & 0x3F;
+            }
+        }
+
+        if self.wave_halted || self.wave_write_enabled {
+            return;
+        }
+
+        let frequency = self.modulated_frequency();
+        if frequency > 0 {
+            self.wave_accumulator = (self.wave_accumulator + frequency as u32) & 0x3F_FFFF;
+        }
+        self.wave_output = self.wave_table[self.wave_position()];
+    }
+
+    pub fn output(&self) -> f32 {
+        let gain = self.volume.gain.min(32) as f32;
+        self.wave_output as f32 * gain * MASTER_VOLUME[self.master_volume] / (63.0 * 32.0)
+    }
+}
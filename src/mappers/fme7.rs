@@ -0,0 +1,313 @@
+use crate::cartridge::{Mirroring, RomImage};
+use super::Mapper;
+
+/// Mapper 69: Sunsoft FME-7, and the Sunsoft 5B which adds audio to it.
+///
+/// All banking goes through a command register at $8000 and a parameter
+/// register at $A000. The IRQ is a 16 bit counter decremented every CPU
+/// cycle that fires when it wraps from $0000 to $FFFF.
+pub struct Fme7 {
+    rom: RomImage,
+
+    command: u8,
+    chr_banks: [usize; 8],
+    prg_banks: [usize; 3],
+    low_bank: usize,
+    low_bank_is_ram: bool,
+    mirroring: Mirroring,
+
+    irq_counter: u16,
+    irq_enabled: bool,
+    irq_counter_enabled: bool,
+    irq_pending: bool,
+
+    audio: Sunsoft5bAudio,
+}
+
+impl Fme7 {
+    pub fn new(rom: RomImage) -> Self {
+        Self {
+            rom,
+            command: 0,
+            chr_banks: [0; 8],
+            prg_banks: [0, 1, 2],
+            low_bank: 0,
+            low_bank_is_ram: false,
+            mirroring: Mirroring::Vertical,
+            irq_counter: 0,
+            irq_enabled: false,
+            irq_counter_enabled: false,
+            irq_pending: false,
+            audio: Sunsoft5bAudio::new(),
+        }
+    }
+
+    fn write_parameter(&mut self, data: u8) {
+        match self.command {
+            0x0..=0x7 => self.chr_banks[self.command as usize] = data as usize,
+            0x8 => {
+                self.low_bank = (data & 0x3F) as usize;
+                self.low_bank_is_ram = data & 0x40 != 0;
+            }
+            0x9..=0xB => self.prg_banks[(self.command - 0x9) as usize] = (data & 0x3F) as usize,
+            0xC => {
+                self.mirroring = match data & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLow,
+                    _ => Mirroring::SingleScreenHigh,
+                };
+            }
+            0xD => {
+                self.irq_enabled = data & 0x01 != 0;
+                self.irq_counter_enabled = data & 0x80 != 0;
+                self.irq_pending = false;
+            }
+            0xE => self.irq_counter = (self.irq_counter & 0xFF00) | data as u16,
+            _ => self.irq_counter = (self.irq_counter & 0x00FF) | ((data as u16) << 8),
+        }
+    }
+}
+
+impl Mapper for Fme7 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            // With RAM selected, $6000-$7FFF falls through to the PRG-RAM on the bus.
+            0x6000..=0x7FFF if !self.low_bank_is_ram => {
+                Some(self.rom.read_prg(self.low_bank, 0x2000, address))
+            }
+            0x8000..=0x9FFF => Some(self.rom.read_prg(self.prg_banks[0], 0x2000, address)),
+            0xA000..=0xBFFF => Some(self.rom.read_prg(self.prg_banks[1], 0x2000, address)),
+            0xC000..=0xDFFF => Some(self.rom.read_prg(self.prg_banks[2], 0x2000, address)),
+            0xE000..=0xFFFF => {
+                let last = self.rom.prg_banks(0x2000) - 1;
+                Some(self.rom.read_prg(last, 0x2000, address))
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> bool {
+        match address {
+            0x6000..=0x7FFF => return !self.low_bank_is_ram,
+            0x8000..=0x9FFF => self.command = data & 0x0F,
+            0xA000..=0xBFFF => self.write_parameter(data),
+            0xC000..=0xDFFF => self.audio.write_address(data),
+            0xE000..=0xFFFF => self.audio.write_data(data),
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8> {
+        if address < 0x2000 {
+            let bank = self.chr_banks[(address >> 10) as usize];
+            Some(self.rom.read_chr(bank, 0x0400, address))
+        } else {
+            None
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x2000 {
+            let bank = self.chr_banks[(address >> 10) as usize];
+            self.rom.write_chr(bank, 0x0400, address, data);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn irq_state(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn clock(&mut self) {
+        if self.irq_counter_enabled {
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+            if self.irq_counter == 0xFFFF && self.irq_enabled {
+                self.irq_pending = true;
+            }
+        }
+
+        self.audio.clock();
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+}
+
+/// Envelope shapes are described by the four bits of register $0D.
+const ENVELOPE_CONTINUE: u8 = 0x08;
+const ENVELOPE_ATTACK: u8 = 0x04;
+const ENVELOPE_ALTERNATE: u8 = 0x02;
+const ENVELOPE_HOLD: u8 = 0x01;
+
+/// The YM2149F (AY-3-8910 compatible) core of the Sunsoft 5B: three square
+/// channels with a shared noise generator and envelope, 3 dB volume steps.
+pub struct Sunsoft5bAudio {
+    address: u8,
+    registers: [u8; 16],
+
+    divider: u8,
+    tone_counters: [u16; 3],
+    tone_outputs: [bool; 3],
+    noise_counter: u16,
+    noise_shift: u32,
+    envelope_counter: u16,
+    envelope_step: u8,
+    envelope_holding: bool,
+    volumes: [f32; 16],
+}
+
+impl Sunsoft5bAudio {
+    pub fn new() -> Self {
+        let mut volumes = [0.0; 16];
+        for (level, volume) in volumes.iter_mut().enumerate().skip(1) {
+            *volume = 10f32.powf((level as f32 - 15.0) * 3.0 / 20.0);
+        }
+
+        Self {
+            address: 0,
+            registers: [0; 16],
+            divider: 0,
+            tone_counters: [0; 3],
+            tone_outputs: [false; 3],
+            noise_counter: 0,
+            noise_shift: 1,
+            envelope_counter: 0,
+            envelope_step: 0,
+            envelope_holding: false,
+            volumes,
+        }
+    }
+
+    pub fn write_address(&mut self, data: u8) {
+        self.address = data & 0x0F;
+    }
+
+    pub fn write_data(&mut self, data: u8) {
+        self.registers[self.address as usize] = data;
+
+        if self.address == 0x0D {
+            self.envelope_step = 0;
+            self.envelope_counter = 0;
+            self.envelope_holding = false;
+        }
+    }
+
+    fn tone_period(&self, channel: usize) -> u16 {
+        let lo = self.registers[channel * 2] as u16;
+        let hi = (self.registers[channel * 2 + 1] & 0x0F) as u16;
+        ((hi << 8) | lo).max(1)
+    }
+
+    fn envelope_period(&self) -> u16 {
+        ((self.registers[0x0C] as u16) << 8 | self.registers[0x0B] as u16).max(1)
+    }
+
+    /// The generators tick once every 16 CPU cycles.
+    pub fn clock(&mut self) {
+        self.divider += 1;
+        if self.divider < 16 {
+            return;
+        }
+        self.divider = 0;
+
+        for channel in 0..3 {
+            self.tone_counters[channel] += 1;
+            if self.tone_counters[channel] >= self.tone_period(channel) {
+                self.tone_counters[channel] = 0;
+                self.tone_outputs[channel] = !self.tone_outputs[channel];
+            }
+        }
+
+        // Noise runs at half the tone rate through a 17 bit LFSR.
+        self.noise_counter += 1;
+        let noise_period = ((self.registers[0x06] & 0x1F) as u16).max(1) * 2;
+        if self.noise_counter >= noise_period {
+            self.noise_counter = 0;
+            let feedback = (self.noise_shift ^ (self.noise_shift >> 3)) & 0x01;
+            self.noise_shift = (self.noise_shift >> 1) | (feedback << 16);
+        }
+
+        self.envelope_counter += 1;
+        if self.envelope_counter >= self.envelope_period() {
+            self.envelope_counter = 0;
+            self.step_envelope();
+        }
+    }
+
+    fn step_envelope(&mut self) {
+        if self.envelope_holding {
+            return;
+        }
+
+        self.envelope_step += 1;
+
+        // One-shot and holding shapes stop after their first ramp.
+        let shape = self.registers[0x0D];
+        if self.envelope_step == 16 && (shape & ENVELOPE_CONTINUE == 0 || shape & ENVELOPE_HOLD != 0) {
+            self.envelope_holding = true;
+        } else if self.envelope_step == 32 {
+            self.envelope_step = 0;
+        }
+    }
+
+    /// Current envelope level, 0-15, following the attack/alternate/hold bits.
+    fn envelope_level(&self) -> usize {
+        let shape = self.registers[0x0D];
+        let cycle = self.envelope_step >> 4;
+        let position = self.envelope_step & 0x0F;
+
+        let mut rising = shape & ENVELOPE_ATTACK != 0;
+        if self.envelope_holding {
+            if shape & ENVELOPE_CONTINUE == 0 {
+                return 0;
+            }
+            if shape & ENVELOPE_ALTERNATE != 0 {
+                rising = !rising;
+            }
+            return if rising { 15 } else { 0 };
+        }
+        if shape & ENVELOPE_ALTERNATE != 0 && cycle == 1 {
+            rising = !rising;
+        }
+
+        if rising {
+            position as usize
+        } else {
+            15 - position as usize
+        }
+    }
+
+    pub fn output(&self) -> f32 {
+        let mixer = self.registers[0x07];
+        let noise = self.noise_shift & 0x01 != 0;
+        let mut sum = 0.0;
+
+        for channel in 0..3 {
+            let tone_disabled = mixer & (0x01 << channel) != 0;
+            let noise_disabled = mixer & (0x08 << channel) != 0;
+            let on = (self.tone_outputs[channel] || tone_disabled) && (noise || noise_disabled);
+
+            if on {
+                let volume = self.registers[0x08 + channel];
+                let level = if volume & 0x10 != 0 {
+                    self.envelope_level()
+                } else {
+                    (volume & 0x0F) as usize
+                };
+                sum += self.volumes[level];
+            }
+        }
+
+        sum / 3.0
+    }
+}
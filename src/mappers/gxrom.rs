@@ -0,0 +1,64 @@
+use crate::cartridge::{Mirroring, RomImage};
+use super::Mapper;
+
+/// Mapper 66: GxROM (GNROM, MHROM).
+/// A single latch at $8000-$FFFF selects a 32 KB PRG bank (bits 4-5)
+/// and an 8 KB CHR bank (bits 0-1). The board has bus conflicts.
+pub struct GxRom {
+    rom: RomImage,
+    prg_bank: usize,
+    chr_bank: usize,
+}
+
+impl GxRom {
+    pub fn new(rom: RomImage) -> Self {
+        Self {
+            rom,
+            prg_bank: 0,
+            chr_bank: 0,
+        }
+    }
+}
+
+impl Mapper for GxRom {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        if address >= 0x8000 {
+            Some(self.rom.read_prg(self.prg_bank, 0x8000, address))
+        } else {
+            None
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x8000 {
+            return false;
+        }
+
+        let data = data & self.rom.read_prg(self.prg_bank, 0x8000, address);
+        self.prg_bank = ((data >> 4) & 0x03) as usize;
+        self.chr_bank = (data & 0x03) as usize;
+
+        true
+    }
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8> {
+        if address < 0x2000 {
+            Some(self.rom.read_chr(self.chr_bank, 0x2000, address))
+        } else {
+            None
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x2000 {
+            self.rom.write_chr(self.chr_bank, 0x2000, address, data);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.rom.mirroring
+    }
+}
@@ -0,0 +1,149 @@
+use crate::cartridge::{Mirroring, RomImage};
+use super::Mapper;
+
+/// Mapper 18: Jaleco SS88006.
+///
+/// Every bank number is written a nibble at a time. The IRQ counter
+/// decrements every CPU cycle, but only its low 4, 8, 12 or 16 bits take
+/// part, and the IRQ fires when that part underflows.
+pub struct JalecoSs88006 {
+    rom: RomImage,
+
+    prg_banks: [usize; 3],
+    chr_banks: [usize; 8],
+    mirroring: Mirroring,
+
+    irq_reload: u16,
+    irq_counter: u16,
+    irq_mask: u16,
+    irq_enabled: bool,
+    irq_pending: bool,
+}
+
+impl JalecoSs88006 {
+    pub fn new(rom: RomImage) -> Self {
+        Self {
+            rom,
+            prg_banks: [0, 1, 2],
+            chr_banks: [0; 8],
+            mirroring: Mirroring::Horizontal,
+            irq_reload: 0,
+            irq_counter: 0,
+            irq_mask: 0xFFFF,
+            irq_enabled: false,
+            irq_pending: false,
+        }
+    }
+}
+
+/// Replaces the low or high nibble of `value` depending on bit 0 of the register.
+fn set_nibble(value: usize, register: u16, data: u8) -> usize {
+    if register & 0x01 == 0 {
+        (value & !0x0F) | (data & 0x0F) as usize
+    } else {
+        (value & 0x0F) | (((data & 0x0F) as usize) << 4)
+    }
+}
+
+impl Mapper for JalecoSs88006 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x8000..=0x9FFF => Some(self.rom.read_prg(self.prg_banks[0], 0x2000, address)),
+            0xA000..=0xBFFF => Some(self.rom.read_prg(self.prg_banks[1], 0x2000, address)),
+            0xC000..=0xDFFF => Some(self.rom.read_prg(self.prg_banks[2], 0x2000, address)),
+            0xE000..=0xFFFF => {
+                let last = self.rom.prg_banks(0x2000) - 1;
+                Some(self.rom.read_prg(last, 0x2000, address))
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x8000 {
+            return false;
+        }
+
+        let register = address & 0xF003;
+        match register {
+            0x8000 | 0x8001 => self.prg_banks[0] = set_nibble(self.prg_banks[0], register, data),
+            0x8002 | 0x8003 => self.prg_banks[1] = set_nibble(self.prg_banks[1], register, data),
+            0x9000 | 0x9001 => self.prg_banks[2] = set_nibble(self.prg_banks[2], register, data),
+            0xA000..=0xD003 => {
+                let index = ((((register >> 12) - 0xA) << 1) | ((register >> 1) & 0x01)) as usize;
+                self.chr_banks[index] = set_nibble(self.chr_banks[index], register, data);
+            }
+            0xE000..=0xE003 => {
+                let shift = (register & 0x03) * 4;
+                self.irq_reload = (self.irq_reload & !(0x0F << shift)) | (((data & 0x0F) as u16) << shift);
+            }
+            0xF000 => {
+                self.irq_counter = self.irq_reload;
+                self.irq_pending = false;
+            }
+            0xF001 => {
+                self.irq_enabled = data & 0x01 != 0;
+                self.irq_mask = if data & 0x08 != 0 {
+                    0x000F
+                } else if data & 0x04 != 0 {
+                    0x00FF
+                } else if data & 0x02 != 0 {
+                    0x0FFF
+                } else {
+                    0xFFFF
+                };
+                self.irq_pending = false;
+            }
+            0xF002 => {
+                self.mirroring = match data & 0x03 {
+                    0 => Mirroring::Horizontal,
+                    1 => Mirroring::Vertical,
+                    2 => Mirroring::SingleScreenLow,
+                    _ => Mirroring::SingleScreenHigh,
+                };
+            }
+            _ => {}
+        }
+
+        true
+    }
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8> {
+        if address < 0x2000 {
+            let bank = self.chr_banks[(address >> 10) as usize];
+            Some(self.rom.read_chr(bank, 0x0400, address))
+        } else {
+            None
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x2000 {
+            let bank = self.chr_banks[(address >> 10) as usize];
+            self.rom.write_chr(bank, 0x0400, address, data);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn irq_state(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn clock(&mut self) {
+        if !self.irq_enabled {
+            return;
+        }
+
+        let counter = self.irq_counter & self.irq_mask;
+        if counter == 0 {
+            self.irq_pending = true;
+        }
+        self.irq_counter = (self.irq_counter & !self.irq_mask) | (counter.wrapping_sub(1) & self.irq_mask);
+    }
+}
@@ -0,0 +1,149 @@
+use crate::cartridge::{Mirroring, RomImage};
+use super::Mapper;
+
+/// Mapper 1: Nintendo MMC1 (SxROM).
+///
+/// Registers are loaded one bit at a time through a shift register at
+/// $8000-$FFFF; the fifth write picks the register by address bits 13-14:
+/// control, two CHR banks and the PRG bank. Writing with bit 7 set resets
+/// the shift register and locks the last PRG bank at $C000.
+///
+/// SUROM uses bit 4 of the CHR bank register to select a 256 KB half of
+/// its 512 KB PRG-ROM. Submapper 5 (SEROM, SHROM) has 32 KB of PRG-ROM
+/// that isn't banked at all.
+pub struct Mmc1 {
+    rom: RomImage,
+    shift: u8,
+    shift_count: u8,
+    /// The MMC1 ignores a write on the cycle straight after another, which
+    /// the dummy write of read-modify-write instructions relies on.
+    written_this_cycle: bool,
+
+    /// `[...C PPMM]`: CHR mode, PRG mode and mirroring.
+    control: u8,
+    chr_banks: [usize; 2],
+    prg_bank: usize,
+}
+
+impl Mmc1 {
+    pub fn new(rom: RomImage) -> Self {
+        Self {
+            rom,
+            shift: 0,
+            shift_count: 0,
+            written_this_cycle: false,
+            control: 0x0C,
+            chr_banks: [0, 0],
+            prg_bank: 0,
+        }
+    }
+
+    fn write_register(&mut self, address: u16, data: u8) {
+        match address & 0xE000 {
+            0x8000 => self.control = data,
+            0xA000 => self.chr_banks[0] = data as usize,
+            0xC000 => self.chr_banks[1] = data as usize,
+            _ => self.prg_bank = (data & 0x0F) as usize,
+        }
+    }
+
+    fn prg_bank(&self, address: u16) -> usize {
+        // SUROM's outer bank, which only matters above 256 KB.
+        let outer = self.chr_banks[0] & 0x10;
+        let upper = address >= 0xC000;
+
+        match (self.control >> 2) & 0x03 {
+            0 | 1 => outer | (self.prg_bank & 0x0E) | upper as usize,
+            2 if upper => outer | self.prg_bank,
+            2 => outer,
+            _ if upper => outer | 0x0F,
+            _ => outer | self.prg_bank,
+        }
+    }
+
+    /// The 4 KB CHR bank for a pattern table address.
+    fn chr_bank(&self, address: u16) -> usize {
+        let right = (address >> 12) as usize;
+        if self.control & 0x10 != 0 {
+            self.chr_banks[right]
+        } else {
+            (self.chr_banks[0] & 0x1E) | right
+        }
+    }
+}
+
+impl Mapper for Mmc1 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        if address < 0x8000 {
+            return None;
+        }
+
+        if self.rom.submapper == 5 {
+            return Some(self.rom.read_prg(0, 0x8000, address));
+        }
+        Some(self.rom.read_prg(self.prg_bank(address), 0x4000, address))
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x8000 {
+            return false;
+        }
+
+        if std::mem::replace(&mut self.written_this_cycle, true) {
+            return true;
+        }
+
+        if data & 0x80 != 0 {
+            self.shift = 0;
+            self.shift_count = 0;
+            self.control |= 0x0C;
+            return true;
+        }
+
+        self.shift |= (data & 0x01) << self.shift_count;
+        self.shift_count += 1;
+        if self.shift_count == 5 {
+            self.write_register(address, self.shift);
+            self.shift = 0;
+            self.shift_count = 0;
+        }
+
+        true
+    }
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8> {
+        if address < 0x2000 {
+            Some(self.rom.read_chr(self.chr_bank(address), 0x1000, address))
+        } else {
+            None
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x2000 {
+            self.rom.write_chr(self.chr_bank(address), 0x1000, address, data);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & 0x03 {
+            0 => Mirroring::SingleScreenLow,
+            1 => Mirroring::SingleScreenHigh,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
+    fn clock(&mut self) {
+        self.written_this_cycle = false;
+    }
+
+    fn reset(&mut self) {
+        self.shift = 0;
+        self.shift_count = 0;
+        self.control |= 0x0C;
+    }
+}
@@ -0,0 +1,122 @@
+use crate::cartridge::{Mirroring, RomImage};
+use super::Mapper;
+
+/// Mappers 9 and 10: Nintendo MMC2 (PxROM) and MMC4 (FxROM).
+///
+/// Each pattern table has two CHR banks and a latch choosing between them.
+/// The latch flips when the PPU fetches the pattern of tile $FD or $FE, after
+/// that fetch has been served, so a game can switch banks mid-scanline simply
+/// by placing those tiles in the nametable.
+pub struct Mmc2 {
+    rom: RomImage,
+    mmc4: bool,
+
+    prg_bank: usize,
+    chr_banks: [[usize; 2]; 2],
+    latches: [usize; 2],
+    mirroring: Mirroring,
+}
+
+/// Index into `chr_banks` for each latch value.
+const LATCH_FD: usize = 0;
+const LATCH_FE: usize = 1;
+
+impl Mmc2 {
+    pub fn new(rom: RomImage) -> Self {
+        Self {
+            mmc4: rom.mapper_id == 10,
+            rom,
+            prg_bank: 0,
+            chr_banks: [[0; 2]; 2],
+            latches: [LATCH_FE, LATCH_FE],
+            mirroring: Mirroring::Vertical,
+        }
+    }
+
+    fn chr_bank(&self, address: u16) -> usize {
+        let table = (address >> 12) as usize & 0x01;
+        self.chr_banks[table][self.latches[table]]
+    }
+}
+
+impl Mapper for Mmc2 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        if address < 0x8000 {
+            return None;
+        }
+
+        if self.mmc4 {
+            let bank = if address < 0xC000 {
+                self.prg_bank
+            } else {
+                self.rom.prg_banks(0x4000) - 1
+            };
+            Some(self.rom.read_prg(bank, 0x4000, address))
+        } else {
+            // The upper three 8 KB banks are fixed to the end of PRG-ROM.
+            let bank = if address < 0xA000 {
+                self.prg_bank
+            } else {
+                // Saturates so undersized PRG-ROM mirrors instead of underflowing.
+                self.rom.prg_banks(0x2000).saturating_sub(4) + ((address - 0x8000) >> 13) as usize
+            };
+            Some(self.rom.read_prg(bank, 0x2000, address))
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> bool {
+        match address & 0xF000 {
+            0xA000 => self.prg_bank = (data & 0x0F) as usize,
+            0xB000 => self.chr_banks[0][LATCH_FD] = (data & 0x1F) as usize,
+            0xC000 => self.chr_banks[0][LATCH_FE] = (data & 0x1F) as usize,
+            0xD000 => self.chr_banks[1][LATCH_FD] = (data & 0x1F) as usize,
+            0xE000 => self.chr_banks[1][LATCH_FE] = (data & 0x1F) as usize,
+            0xF000 => {
+                self.mirroring = if data & 0x01 != 0 {
+                    Mirroring::Horizontal
+                } else {
+                    Mirroring::Vertical
+                };
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8> {
+        if address < 0x2000 {
+            Some(self.rom.read_chr(self.chr_bank(address), 0x1000, address))
+        } else {
+            None
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x2000 {
+            let bank = self.chr_bank(address);
+            self.rom.write_chr(bank, 0x1000, address, data);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn ppu_fetch(&mut self, address: u16) {
+        // MMC2 only watches a single address for the left pattern table,
+        // while MMC4 and the MMC2 right table react to the whole tile row range.
+        match address {
+            0x0FD8 => self.latches[0] = LATCH_FD,
+            0x0FE8 => self.latches[0] = LATCH_FE,
+            0x0FD9..=0x0FDF if self.mmc4 => self.latches[0] = LATCH_FD,
+            0x0FE9..=0x0FEF if self.mmc4 => self.latches[0] = LATCH_FE,
+            0x1FD8..=0x1FDF => self.latches[1] = LATCH_FD,
+            0x1FE8..=0x1FEF => self.latches[1] = LATCH_FE,
+            _ => {}
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
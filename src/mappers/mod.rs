@@ -0,0 +1,129 @@
+mod nrom;
+mod mmc1;
+mod uxrom;
+mod cnrom;
+mod axrom;
+mod cprom;
+mod vrc_irq;
+mod vrc1;
+mod vrc2_4;
+mod vrc6;
+mod vrc7;
+mod opll;
+mod mmc2;
+mod namco163;
+mod fme7;
+mod eeprom;
+mod bandai_fcg;
+mod jaleco_ss88006;
+mod color_dreams;
+mod gxrom;
+mod nina_03_06;
+mod bnrom;
+mod camerica;
+mod action52;
+mod multicart;
+mod fds;
+mod fds_audio;
+mod nsf;
+
+use crate::cartridge::{Mirroring, RomImage};
+use crate::nsf::Nsf;
+
+pub use self::nsf::{REGION_REGISTER, TRACK_REGISTER};
+
+/// A cartridge board. The CPU side covers $4020-$FFFF and the PPU side
+/// covers the pattern tables at $0000-$1FFF.
+pub trait Mapper {
+    /// Returns the data if the board drives the bus at this address.
+    fn cpu_read(&mut self, address: u16) -> Option<u8>;
+
+    /// Returns true if the board consumed the write.
+    fn cpu_write(&mut self, address: u16, data: u8) -> bool;
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8>;
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> bool;
+
+    /// Called after every PPU read has been served, for boards that watch
+    /// the PPU address bus to switch banks on specific tile fetches.
+    fn ppu_fetch(&mut self, _address: u16) {}
+
+    fn mirroring(&self) -> Mirroring;
+
+    /// Level of the board's IRQ output, wired to the CPU's /IRQ line.
+    fn irq_state(&self) -> bool {
+        false
+    }
+
+    /// Called once per CPU cycle.
+    fn clock(&mut self) {}
+
+    /// Expansion audio output, scaled so that the chip at full volume is about 1.0.
+    fn audio_output(&self) -> f32 {
+        0.0
+    }
+
+    /// Non-volatile memory kept on the board itself, like a serial EEPROM,
+    /// which is persisted together with PRG-RAM.
+    fn nvram(&self) -> Option<&[u8]> {
+        None
+    }
+
+    fn load_nvram(&mut self, _data: &[u8]) {}
+
+    /// Number of disk sides, for boards with a disk drive.
+    fn disk_sides(&self) -> usize {
+        0
+    }
+
+    /// The side in the drive, or `None` when it's empty.
+    fn disk_side(&self) -> Option<usize> {
+        None
+    }
+
+    /// Ejects the disk and inserts `side`, or leaves the drive empty for `None`.
+    fn insert_disk(&mut self, _side: Option<usize>) {}
+
+    fn reset(&mut self) {}
+}
+
+pub fn create(image: RomImage) -> Option<Box<dyn Mapper>> {
+    let mapper: Box<dyn Mapper> = match image.mapper_id {
+        0 => Box::new(nrom::Nrom::new(image)),
+        1 => Box::new(mmc1::Mmc1::new(image)),
+        2 => Box::new(uxrom::UxRom::new(image)),
+        3 => Box::new(cnrom::Cnrom::new(image)),
+        7 => Box::new(axrom::AxRom::new(image)),
+        9 | 10 => Box::new(mmc2::Mmc2::new(image)),
+        11 => Box::new(color_dreams::ColorDreams::new(image)),
+        13 => Box::new(cprom::Cprom::new(image)),
+        16 | 153 | 159 => Box::new(bandai_fcg::BandaiFcg::new(image)),
+        18 => Box::new(jaleco_ss88006::JalecoSs88006::new(image)),
+        19 => Box::new(namco163::Namco163::new(image)),
+        21 | 22 | 23 | 25 => Box::new(vrc2_4::Vrc2And4::new(image)),
+        24 | 26 => Box::new(vrc6::Vrc6::new(image)),
+        34 => Box::new(bnrom::Bnrom::new(image)),
+        58 | 113 | 200 | 201 | 203 | 212 | 225 => Box::new(multicart::Multicart::new(image)),
+        66 => Box::new(gxrom::GxRom::new(image)),
+        69 => Box::new(fme7::Fme7::new(image)),
+        71 | 232 => Box::new(camerica::Camerica::new(image)),
+        75 => Box::new(vrc1::Vrc1::new(image)),
+        79 => Box::new(nina_03_06::Nina0306::new(image)),
+        85 => Box::new(vrc7::Vrc7::new(image)),
+        228 => Box::new(action52::Action52::new(image)),
+        _ => return None,
+    };
+
+    Some(mapper)
+}
+
+/// The Famicom Disk System's RAM adapter, running `bios` with the sides of `disk` in its drive.
+pub fn create_disk_system(bios: Vec<u8>, disk: Vec<u8>) -> Box<dyn Mapper> {
+    Box::new(fds::Fds::new(bios, disk))
+}
+
+/// The board NSF tunes play on.
+pub fn create_nsf_board(nsf: &Nsf) -> Box<dyn Mapper> {
+    Box::new(nsf::NsfBoard::new(nsf))
+}
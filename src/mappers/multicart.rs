@@ -0,0 +1,151 @@
+use crate::cartridge::{Mirroring, RomImage};
+use super::Mapper;
+
+/// Discrete-logic multicarts that latch their whole configuration in one
+/// write, usually from the address rather than the data:
+///
+/// * 58: `A~[.... .... MOCC CPPP]`, O set for 16 KB PRG mode, M set for horizontal.
+/// * 113: NINA-03/06 based HES boards, `D~[MCPP PCCC]` written to $4100-$5FFF,
+///   M set for vertical, C the high CHR bit.
+/// * 200: `A~[.... .... .... MBBB]`, one 16 KB PRG bank mirrored twice plus an 8 KB CHR bank.
+/// * 201: `A~[.... .... BBBB BBBB]`, one 32 KB PRG bank and one 8 KB CHR bank.
+/// * 203: `D~[PPPP PPCC]`, a 16 KB PRG bank mirrored twice and an 8 KB CHR bank.
+/// * 212: `A~[.O.. .... .... MBBB]`, O set for 32 KB PRG mode.
+/// * 225: `A~[.HMO PPPP PPCC CCCC]`, H the high bit of both banks,
+///   O set for 16 KB PRG mode, with four nibbles of RAM at $5800-$5803.
+pub struct Multicart {
+    rom: RomImage,
+
+    /// Always in 16 KB units; in 32 KB mode the low bit is ignored.
+    prg_bank: usize,
+    prg_32k_mode: bool,
+    chr_bank: usize,
+    mirroring: Mirroring,
+    nibble_ram: [u8; 4],
+}
+
+impl Multicart {
+    pub fn new(rom: RomImage) -> Self {
+        let mut multicart = Self {
+            mirroring: rom.mirroring,
+            rom,
+            prg_bank: 0,
+            prg_32k_mode: true,
+            chr_bank: 0,
+            nibble_ram: [0; 4],
+        };
+        multicart.reset();
+        multicart
+    }
+
+    fn set_mirroring(&mut self, horizontal: bool) {
+        self.mirroring = if horizontal {
+            Mirroring::Horizontal
+        } else {
+            Mirroring::Vertical
+        };
+    }
+
+    fn latch(&mut self, address: u16, data: u8) {
+        let address = address as usize;
+
+        match self.rom.mapper_id {
+            58 => {
+                self.prg_bank = address & 0x07;
+                self.prg_32k_mode = address & 0x40 == 0;
+                self.chr_bank = (address >> 3) & 0x07;
+                self.set_mirroring(address & 0x80 != 0);
+            }
+            113 => {
+                self.prg_bank = ((data as usize >> 3) & 0x07) << 1;
+                self.chr_bank = (data as usize & 0x07) | ((data as usize >> 3) & 0x08);
+                self.set_mirroring(data & 0x80 == 0);
+            }
+            200 => {
+                self.prg_bank = address & 0x07;
+                self.chr_bank = address & 0x07;
+                self.set_mirroring(address & 0x08 != 0);
+            }
+            201 => {
+                self.prg_bank = (address & 0xFF) << 1;
+                self.chr_bank = address & 0xFF;
+            }
+            203 => {
+                self.prg_bank = data as usize >> 2;
+                self.chr_bank = data as usize & 0x03;
+            }
+            212 => {
+                self.prg_bank = address & 0x07;
+                self.prg_32k_mode = address & 0x4000 != 0;
+                self.chr_bank = address & 0x07;
+                self.set_mirroring(address & 0x08 != 0);
+            }
+            _ => {
+                let high = (address >> 14) & 0x01;
+                self.prg_bank = (high << 6) | ((address >> 6) & 0x3F);
+                self.prg_32k_mode = address & 0x1000 == 0;
+                self.chr_bank = (high << 6) | (address & 0x3F);
+                self.set_mirroring(address & 0x2000 != 0);
+            }
+        }
+    }
+}
+
+impl Mapper for Multicart {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x5800..=0x5FFF if self.rom.mapper_id == 225 => {
+                Some(self.nibble_ram[(address & 0x03) as usize] & 0x0F)
+            }
+            0x8000..=0xFFFF => {
+                let bank = if self.prg_32k_mode {
+                    (self.prg_bank & !0x01) | ((address >> 14) & 0x01) as usize
+                } else {
+                    self.prg_bank
+                };
+                Some(self.rom.read_prg(bank, 0x4000, address))
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> bool {
+        match (self.rom.mapper_id, address) {
+            (113, 0x4100..=0x5FFF) if address & 0x0100 != 0 => self.latch(address, data),
+            (225, 0x5800..=0x5FFF) => self.nibble_ram[(address & 0x03) as usize] = data & 0x0F,
+            (113, 0x8000..=0xFFFF) => {}
+            (_, 0x8000..=0xFFFF) => self.latch(address, data),
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8> {
+        if address < 0x2000 {
+            Some(self.rom.read_chr(self.chr_bank, 0x2000, address))
+        } else {
+            None
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x2000 {
+            self.rom.write_chr(self.chr_bank, 0x2000, address, data);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    /// Multicarts boot into their menu, which lives in the first bank.
+    fn reset(&mut self) {
+        self.prg_bank = 0;
+        self.chr_bank = 0;
+        self.prg_32k_mode = !matches!(self.rom.mapper_id, 200 | 203);
+    }
+}
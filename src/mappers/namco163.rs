@@ -0,0 +1,276 @@
+use crate::cartridge::{Mirroring, RomImage};
+use super::Mapper;
+
+/// CPU cycles spent updating each wavetable channel.
+const CHANNEL_UPDATE_CYCLES: u8 = 15;
+
+/// Mapper 19: Namco 163.
+///
+/// Besides PRG/CHR banking it has a 15 bit cycle counter IRQ and 128 bytes of
+/// internal RAM that hold both the wavetables and the registers of up to
+/// eight sound channels.
+pub struct Namco163 {
+    rom: RomImage,
+
+    prg_banks: [usize; 3],
+    chr_banks: [usize; 8],
+    nametable_banks: [usize; 4],
+    irq_counter: u16,
+    irq_enabled: bool,
+    irq_pending: bool,
+    audio: Namco163Audio,
+}
+
+impl Namco163 {
+    pub fn new(rom: RomImage) -> Self {
+        Self {
+            rom,
+            prg_banks: [0, 1, 2],
+            chr_banks: [0; 8],
+            nametable_banks: [0xE0, 0xE1, 0xE0, 0xE1],
+            irq_counter: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            audio: Namco163Audio::new(),
+        }
+    }
+}
+
+impl Mapper for Namco163 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x4800..=0x4FFF => Some(self.audio.read_data()),
+            0x5000..=0x57FF => Some(self.irq_counter as u8),
+            0x5800..=0x5FFF => {
+                Some(((self.irq_counter >> 8) as u8 & 0x7F) | ((self.irq_enabled as u8) << 7))
+            }
+            0x8000..=0x9FFF => Some(self.rom.read_prg(self.prg_banks[0], 0x2000, address)),
+            0xA000..=0xBFFF => Some(self.rom.read_prg(self.prg_banks[1], 0x2000, address)),
+            0xC000..=0xDFFF => Some(self.rom.read_prg(self.prg_banks[2], 0x2000, address)),
+            0xE000..=0xFFFF => {
+                let last = self.rom.prg_banks(0x2000) - 1;
+                Some(self.rom.read_prg(last, 0x2000, address))
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> bool {
+        match address {
+            0x4800..=0x4FFF => self.audio.write_data(data),
+            0x5000..=0x57FF => {
+                self.irq_counter = (self.irq_counter & 0x7F00) | data as u16;
+                self.irq_pending = false;
+            }
+            0x5800..=0x5FFF => {
+                self.irq_counter = (self.irq_counter & 0x00FF) | (((data & 0x7F) as u16) << 8);
+                self.irq_enabled = data & 0x80 != 0;
+                self.irq_pending = false;
+            }
+            0x8000..=0xBFFF => self.chr_banks[((address - 0x8000) >> 11) as usize] = data as usize,
+            0xC000..=0xDFFF => self.nametable_banks[((address - 0xC000) >> 11) as usize] = data as usize,
+            0xE000..=0xE7FF => {
+                self.prg_banks[0] = (data & 0x3F) as usize;
+                self.audio.enabled = data & 0x40 == 0;
+            }
+            0xE800..=0xEFFF => self.prg_banks[1] = (data & 0x3F) as usize,
+            0xF000..=0xF7FF => self.prg_banks[2] = (data & 0x3F) as usize,
+            0xF800..=0xFFFF => self.audio.write_address(data),
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x0000..=0x1FFF => {
+                let bank = self.chr_banks[(address >> 10) as usize];
+                Some(self.rom.read_chr(bank, 0x0400, address))
+            }
+            // Nametable slots pointing below $E0 read from CHR-ROM instead of CIRAM.
+            0x2000..=0x3EFF => {
+                let bank = self.nametable_banks[((address >> 10) & 0x03) as usize];
+                if bank < 0xE0 {
+                    Some(self.rom.read_chr(bank, 0x0400, address))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x2000 {
+            let bank = self.chr_banks[(address >> 10) as usize];
+            self.rom.write_chr(bank, 0x0400, address, data);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Derives the CIRAM arrangement from the nametable registers. Slots mapped
+    /// to CHR-ROM are served by `ppu_read` and don't matter here.
+    fn mirroring(&self) -> Mirroring {
+        let pages = self.nametable_banks.map(|bank| bank & 0x01);
+        match pages {
+            [0, 0, 0, 0] => Mirroring::SingleScreenLow,
+            [1, 1, 1, 1] => Mirroring::SingleScreenHigh,
+            [0, 0, 1, 1] => Mirroring::Horizontal,
+            _ => Mirroring::Vertical,
+        }
+    }
+
+    fn irq_state(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn clock(&mut self) {
+        if self.irq_enabled && self.irq_counter < 0x7FFF {
+            self.irq_counter += 1;
+            if self.irq_counter == 0x7FFF {
+                self.irq_pending = true;
+            }
+        }
+
+        self.audio.clock();
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    /// Battery backed boards also keep the sound RAM powered.
+    fn nvram(&self) -> Option<&[u8]> {
+        if self.rom.battery {
+            Some(self.audio.ram())
+        } else {
+            None
+        }
+    }
+
+    fn load_nvram(&mut self, data: &[u8]) {
+        self.audio.load_ram(data);
+    }
+}
+
+/// Wavetable synthesis sharing the 128 byte internal RAM. Channel registers
+/// live at $40-$7F, eight bytes per channel with channel 7 at $78. The chip
+/// updates one channel every 15 CPU cycles, so enabling more channels lowers
+/// the rate of each.
+pub struct Namco163Audio {
+    ram: [u8; 128],
+    address: u8,
+    auto_increment: bool,
+    enabled: bool,
+    divider: u8,
+    current_channel: usize,
+    outputs: [i16; 8],
+}
+
+impl Namco163Audio {
+    pub fn new() -> Self {
+        Self {
+            ram: [0; 128],
+            address: 0,
+            auto_increment: false,
+            enabled: true,
+            divider: 0,
+            current_channel: 7,
+            outputs: [0; 8],
+        }
+    }
+
+    pub fn write_address(&mut self, data: u8) {
+        self.address = data & 0x7F;
+        self.auto_increment = data & 0x80 != 0;
+    }
+
+    pub fn read_data(&mut self) -> u8 {
+        let data = self.ram[self.address as usize];
+        if self.auto_increment {
+            self.address = (self.address + 1) & 0x7F;
+        }
+        data
+    }
+
+    pub fn write_data(&mut self, data: u8) {
+        self.ram[self.address as usize] = data;
+        if self.auto_increment {
+            self.address = (self.address + 1) & 0x7F;
+        }
+    }
+
+    /// The internal RAM is battery backed on some boards.
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    pub fn load_ram(&mut self, data: &[u8]) {
+        let length = data.len().min(self.ram.len());
+        self.ram[..length].copy_from_slice(&data[..length]);
+    }
+
+    fn enabled_channels(&self) -> usize {
+        (((self.ram[0x7F] >> 4) & 0x07) + 1) as usize
+    }
+
+    pub fn clock(&mut self) {
+        if !self.enabled {
+            return;
+        }
+
+        self.divider += 1;
+        if self.divider < CHANNEL_UPDATE_CYCLES {
+            return;
+        }
+        self.divider = 0;
+
+        self.update_channel(self.current_channel);
+
+        let first = 8 - self.enabled_channels();
+        if self.current_channel <= first {
+            self.current_channel = 7;
+        } else {
+            self.current_channel -= 1;
+        }
+    }
+
+    fn update_channel(&mut self, channel: usize) {
+        let base = 0x40 + channel * 8;
+        let registers = &self.ram[base..base + 8];
+
+        let frequency = registers[0] as u32
+            | (registers[2] as u32) << 8
+            | ((registers[4] & 0x03) as u32) << 16;
+        let mut phase = registers[1] as u32 | (registers[3] as u32) << 8 | (registers[5] as u32) << 16;
+        let length = 256 - (registers[4] & 0xFC) as u32;
+        let wave_address = registers[6] as u32;
+        let volume = (registers[7] & 0x0F) as i16;
+
+        phase = (phase + frequency) % (length << 16);
+
+        let sample_address = (((phase >> 16) + wave_address) & 0xFF) as usize;
+        let byte = self.ram[sample_address >> 1];
+        let sample = if sample_address & 0x01 == 0 { byte & 0x0F } else { byte >> 4 };
+
+        self.ram[base + 1] = phase as u8;
+        self.ram[base + 3] = (phase >> 8) as u8;
+        self.ram[base + 5] = (phase >> 16) as u8;
+        self.outputs[channel] = (sample as i16 - 8) * volume;
+    }
+
+    /// The real chip multiplexes its channels through one DAC; averaging
+    /// them gives the same perceived mix without the switching whine.
+    pub fn output(&self) -> f32 {
+        if !self.enabled {
+            return 0.0;
+        }
+
+        let channels = self.enabled_channels();
+        let sum: i16 = self.outputs[8 - channels..].iter().sum();
+        sum as f32 / (channels as f32 * 120.0)
+    }
+}
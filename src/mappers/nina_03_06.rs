@@ -0,0 +1,64 @@
+use crate::cartridge::{Mirroring, RomImage};
+use super::Mapper;
+
+/// Mapper 79: AVE NINA-03 and NINA-06, and Sachen's SA-016-1M clone.
+/// A register at $4100-$5FFF, wherever address lines A8 and A14 are set
+/// and A13 clear, selects a 32 KB PRG bank (bit 3) and an 8 KB CHR bank
+/// (bits 0-2).
+pub struct Nina0306 {
+    rom: RomImage,
+    prg_bank: usize,
+    chr_bank: usize,
+}
+
+impl Nina0306 {
+    pub fn new(rom: RomImage) -> Self {
+        Self {
+            rom,
+            prg_bank: 0,
+            chr_bank: 0,
+        }
+    }
+}
+
+impl Mapper for Nina0306 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        if address >= 0x8000 {
+            Some(self.rom.read_prg(self.prg_bank, 0x8000, address))
+        } else {
+            None
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> bool {
+        if address & 0xE100 != 0x4100 {
+            return false;
+        }
+
+        self.prg_bank = ((data >> 3) & 0x01) as usize;
+        self.chr_bank = (data & 0x07) as usize;
+
+        true
+    }
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8> {
+        if address < 0x2000 {
+            Some(self.rom.read_chr(self.chr_bank, 0x2000, address))
+        } else {
+            None
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x2000 {
+            self.rom.write_chr(self.chr_bank, 0x2000, address, data);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.rom.mirroring
+    }
+}
@@ -0,0 +1,399 @@
+use crate::cartridge::{Mirroring, Region};
+use crate::nsf::{self, Nsf};
+use super::Mapper;
+use super::fds_audio::FdsAudio;
+use super::fme7::Sunsoft5bAudio;
+use super::namco163::Namco163Audio;
+use super::opll::Opll;
+use super::vrc6::Vrc6Audio;
+use super::vrc7::OPLL_DIVIDER;
+
+/// Where the player's driver code lives, in a range no NSF uses.
+pub const DRIVER_ADDRESS: u16 = 0x4100;
+
+/// Registers for talking to the driver: the track number and region passed
+/// to INIT, a write once INIT returns, and a read that is nonzero when PLAY is due.
+pub const TRACK_REGISTER: u16 = 0x41F0;
+pub const REGION_REGISTER: u16 = 0x41F1;
+const INIT_DONE_REGISTER: u16 = 0x41F2;
+const PLAY_DUE_REGISTER: u16 = 0x41F3;
+
+const BANK_SIZE: usize = 0x1000;
+
+/// Assembles the driver: clear RAM and the APU, call INIT with the track in
+/// A and the region in X, then call PLAY whenever the timer says so. PLAY is
+/// never called again before the previous call has returned.
+fn assemble_driver(init: u16, play: u16) -> Vec<u8> {
+    let [init_lo, init_hi] = init.to_le_bytes();
+    let [play_lo, play_hi] = play.to_le_bytes();
+    let [done_lo, done_hi] = INIT_DONE_REGISTER.to_le_bytes();
+    let [due_lo, due_hi] = PLAY_DUE_REGISTER.to_le_bytes();
+    let [track_lo, track_hi] = TRACK_REGISTER.to_le_bytes();
+    let [region_lo, region_hi] = REGION_REGISTER.to_le_bytes();
+
+    let mut code = vec![
+        0x78,             // SEI
+        0xD8,             // CLD
+        0xA2, 0xFF,       // LDX #$FF
+        0x9A,             // TXS
+        0xA9, 0x00,       // LDA #$00
+        0xA2, 0x00,       // LDX #$00
+    ];
+
+    // Clear $0000-$07FF.
+    let clear_ram = code.len();
+    code.extend_from_slice(&[0x95, 0x00]); // STA $00,X
+    for page in 1..8 {
+        code.extend_from_slice(&[0x9D, 0x00, page]); // STA $xx00,X
+    }
+    code.extend_from_slice(&[0xE8]); // INX
+    branch(&mut code, 0xD0, clear_ram); // BNE
+
+    // Silence $4000-$4013, then enable the channels.
+    let clear_apu = code.len();
+    code.extend_from_slice(&[
+        0x9D, 0x00, 0x40, // STA $4000,X
+        0xE8,             // INX
+        0xE0, 0x14,       // CPX #$14
+    ]);
+    branch(&mut code, 0xD0, clear_apu); // BNE
+
+    code.extend_from_slice(&[
+        0x8D, 0x15, 0x40,            // STA $4015
+        0xA9, 0x0F,                  // LDA #$0F
+        0x8D, 0x15, 0x40,            // STA $4015
+        0xA9, 0x40,                  // LDA #$40
+        0x8D, 0x17, 0x40,            // STA $4017
+        0xAD, track_lo, track_hi,    // LDA track
+        0xAE, region_lo, region_hi,  // LDX region
+        0xA0, 0x00,                  // LDY #$00
+        0x20, init_lo, init_hi,      // JSR INIT
+        0x8D, done_lo, done_hi,      // STA init done
+    ]);
+
+    let wait = code.len();
+    code.extend_from_slice(&[0xAD, due_lo, due_hi]); // LDA play due
+    branch(&mut code, 0xF0, wait); // BEQ
+    code.extend_from_slice(&[0x20, play_lo, play_hi]); // JSR PLAY
+    let [wait_lo, wait_hi] = (DRIVER_ADDRESS + wait as u16).to_le_bytes();
+    code.extend_from_slice(&[0x4C, wait_lo, wait_hi]); // JMP wait
+
+    code.push(0x40); // RTI, for stray interrupts
+    code
+}
+
+/// Appends a relative branch back to `target`.
+fn branch(code: &mut Vec<u8>, opcode: u8, target: usize) {
+    let offset = target as isize - (code.len() as isize + 2);
+    code.extend_from_slice(&[opcode, offset as i8 as u8]);
+}
+
+/// Plays an NSF file through a made up board: the tune's code and data in
+/// $6000-$FFFF with optional 4 KB bank switching at $5FF8-$5FFF, the
+/// expansion sound chips it asks for, and a tiny driver that calls INIT
+/// and PLAY.
+///
+/// MMC5 audio isn't emulated; tunes that use it only get its ExRAM and multiplier.
+pub struct NsfBoard {
+    data: Vec<u8>,
+    load_address: u16,
+    initial_banks: Option<[u8; 8]>,
+    ntsc_speed: u16,
+    pal_speed: u16,
+    chips: u8,
+    driver: Vec<u8>,
+
+    /// $6000-$FFFF.
+    memory: Vec<u8>,
+    track: u8,
+    pal: bool,
+    playing: bool,
+    play_due: bool,
+    play_timer: u64,
+
+    exram: [u8; 1024],
+    multiplicand: u8,
+    multiplier: u8,
+
+    vrc6: Option<Vrc6Audio>,
+    vrc7: Option<Opll>,
+    opll_divider: u8,
+    fds: Option<FdsAudio>,
+    namco163: Option<Namco163Audio>,
+    sunsoft5b: Option<Sunsoft5bAudio>,
+}
+
+impl NsfBoard {
+    pub fn new(nsf: &Nsf) -> Self {
+        let chips = nsf.chips;
+        let mut board = Self {
+            data: nsf.data.clone(),
+            load_address: nsf.load_address,
+            initial_banks: nsf.banks,
+            ntsc_speed: nsf.ntsc_speed,
+            pal_speed: nsf.pal_speed,
+            chips,
+            driver: assemble_driver(nsf.init_address, nsf.play_address),
+            memory: vec![0; 0xA000],
+            track: nsf.starting_track as u8,
+            pal: nsf.is_pal(),
+            playing: false,
+            play_due: false,
+            play_timer: 0,
+            exram: [0; 1024],
+            multiplicand: 0xFF,
+            multiplier: 0xFF,
+            vrc6: (chips & nsf::CHIP_VRC6 != 0).then(Vrc6Audio::new),
+            vrc7: (chips & nsf::CHIP_VRC7 != 0).then(Opll::new),
+            opll_divider: 0,
+            fds: (chips & nsf::CHIP_FDS != 0).then(FdsAudio::new),
+            namco163: (chips & nsf::CHIP_NAMCO163 != 0).then(Namco163Audio::new),
+            sunsoft5b: (chips & nsf::CHIP_SUNSOFT5B != 0).then(Sunsoft5bAudio::new),
+        };
+        board.reset();
+        board
+    }
+
+    fn uses_fds(&self) -> bool {
+        self.chips & nsf::CHIP_FDS != 0
+    }
+
+    /// PLAY period in CPU cycles.
+    fn play_period(&self) -> u64 {
+        let (speed, region) = if self.pal {
+            (self.pal_speed, Region::Pal)
+        } else {
+            (self.ntsc_speed, Region::Ntsc)
+        };
+        (speed.max(1) as u64 * region.cpu_clock_rate() / 1_000_000).max(1)
+    }
+
+    /// Copies 4 KB bank `bank` into the window at `slot` * 4 KB from $6000.
+    /// Banks count from the load address rounded down to 4 KB.
+    fn switch_bank(&mut self, slot: usize, bank: u8) {
+        let padding = (self.load_address & 0x0FFF) as usize;
+        let bank_start = (bank as usize * BANK_SIZE) as isize - padding as isize;
+        let window = &mut self.memory[slot * BANK_SIZE..(slot + 1) * BANK_SIZE];
+
+        for (i, byte) in window.iter_mut().enumerate() {
+            let offset = bank_start + i as isize;
+            *byte = if offset >= 0 {
+                self.data.get(offset as usize).copied().unwrap_or(0)
+            } else {
+                0
+            };
+        }
+    }
+
+    /// Sends a write to whichever expansion chip decodes it, if the tune uses that chip.
+    fn write_expansion(&mut self, address: u16, data: u8) -> bool {
+        match address {
+            0x4040..=0x408A => {
+                if let Some(fds) = &mut self.fds {
+                    fds.write(address, data);
+                    return true;
+                }
+            }
+            0x4800..=0x4FFF => {
+                if let Some(namco163) = &mut self.namco163 {
+                    namco163.write_data(data);
+                    return true;
+                }
+            }
+            0x9000..=0x9003 | 0xA000..=0xA002 | 0xB000..=0xB002 => {
+                if let Some(vrc6) = &mut self.vrc6 {
+                    vrc6.write(address, data);
+                    return true;
+                }
+            }
+            0x9010 | 0x9030 => {
+                if let Some(opll) = &mut self.vrc7 {
+                    if address == 0x9010 {
+                        opll.write_address(data);
+                    } else {
+                        opll.write_data(data);
+                    }
+                    return true;
+                }
+            }
+            0xC000..=0xDFFF => {
+                if let Some(sunsoft5b) = &mut self.sunsoft5b {
+                    sunsoft5b.write_address(data);
+                    return true;
+                }
+            }
+            0xE000..=0xF7FF => {
+                if let Some(sunsoft5b) = &mut self.sunsoft5b {
+                    sunsoft5b.write_data(data);
+                    return true;
+                }
+            }
+            0xF800..=0xFFFF => {
+                // Both Namco 163 and Sunsoft 5B decode this range.
+                let mut handled = false;
+                if let Some(namco163) = &mut self.namco163 {
+                    namco163.write_address(data);
+                    handled = true;
+                }
+                if let Some(sunsoft5b) = &mut self.sunsoft5b {
+                    sunsoft5b.write_data(data);
+                    handled = true;
+                }
+                return handled;
+            }
+            _ => {}
+        }
+
+        false
+    }
+}
+
+impl Mapper for NsfBoard {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        let driver_end = DRIVER_ADDRESS + self.driver.len() as u16;
+        let rti = driver_end - 1;
+
+        match address {
+            TRACK_REGISTER => Some(self.track),
+            REGION_REGISTER => Some(self.pal as u8),
+            PLAY_DUE_REGISTER => {
+                let due = self.play_due;
+                self.play_due = false;
+                Some(due as u8)
+            }
+            _ if (DRIVER_ADDRESS..driver_end).contains(&address) => {
+                Some(self.driver[(address - DRIVER_ADDRESS) as usize])
+            }
+            0x4040..=0x4097 if self.fds.is_some() => self.fds.as_ref().and_then(|fds| fds.read(address)),
+            0x4800..=0x4FFF if self.namco163.is_some() => self.namco163.as_mut().map(|chip| chip.read_data()),
+            0x5205 if self.chips & nsf::CHIP_MMC5 != 0 => {
+                Some((self.multiplicand as u16 * self.multiplier as u16) as u8)
+            }
+            0x5206 if self.chips & nsf::CHIP_MMC5 != 0 => {
+                Some(((self.multiplicand as u16 * self.multiplier as u16) >> 8) as u8)
+            }
+            0x5C00..=0x5FF5 if self.chips & nsf::CHIP_MMC5 != 0 => {
+                Some(self.exram[(address - 0x5C00) as usize])
+            }
+            0xFFFA | 0xFFFE => Some(rti as u8),
+            0xFFFB | 0xFFFF => Some((rti >> 8) as u8),
+            0xFFFC => Some(DRIVER_ADDRESS as u8),
+            0xFFFD => Some((DRIVER_ADDRESS >> 8) as u8),
+            0x6000..=0xFFFF => Some(self.memory[(address - 0x6000) as usize]),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> bool {
+        if self.write_expansion(address, data) {
+            return true;
+        }
+
+        match address {
+            TRACK_REGISTER => self.track = data,
+            REGION_REGISTER => self.pal = data & 0x01 != 0,
+            INIT_DONE_REGISTER => {
+                self.playing = true;
+                self.play_timer = 0;
+            }
+            0x5205 if self.chips & nsf::CHIP_MMC5 != 0 => self.multiplicand = data,
+            0x5206 if self.chips & nsf::CHIP_MMC5 != 0 => self.multiplier = data,
+            0x5C00..=0x5FF5 if self.chips & nsf::CHIP_MMC5 != 0 => {
+                self.exram[(address - 0x5C00) as usize] = data;
+            }
+            0x5FF6..=0x5FF7 if self.uses_fds() && self.initial_banks.is_some() => {
+                self.switch_bank((address - 0x5FF6) as usize, data);
+            }
+            0x5FF8..=0x5FFF if self.initial_banks.is_some() => {
+                self.switch_bank((address - 0x5FF8) as usize + 2, data);
+            }
+            0x6000..=0x7FFF => self.memory[(address - 0x6000) as usize] = data,
+            // The Disk System keeps everything in RAM.
+            0x8000..=0xDFFF if self.uses_fds() => self.memory[(address - 0x6000) as usize] = data,
+            0x8000..=0xFFFF => {}
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn ppu_read(&mut self, _address: u16) -> Option<u8> {
+        None
+    }
+
+    fn ppu_write(&mut self, _address: u16, _data: u8) -> bool {
+        false
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        Mirroring::Horizontal
+    }
+
+    fn clock(&mut self) {
+        if self.playing {
+            self.play_timer += 1;
+            if self.play_timer >= self.play_period() {
+                self.play_timer = 0;
+                self.play_due = true;
+            }
+        }
+
+        if let Some(vrc6) = &mut self.vrc6 {
+            vrc6.clock();
+        }
+        if let Some(opll) = &mut self.vrc7 {
+            self.opll_divider += 1;
+            if self.opll_divider == OPLL_DIVIDER {
+                self.opll_divider = 0;
+                opll.step();
+            }
+        }
+        if let Some(fds) = &mut self.fds {
+            fds.clock();
+        }
+        if let Some(namco163) = &mut self.namco163 {
+            namco163.clock();
+        }
+        if let Some(sunsoft5b) = &mut self.sunsoft5b {
+            sunsoft5b.clock();
+        }
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.vrc6.as_ref().map_or(0.0, |chip| chip.output())
+            + self.vrc7.as_ref().map_or(0.0, |chip| chip.output())
+            + self.fds.as_ref().map_or(0.0, |chip| chip.output())
+            + self.namco163.as_ref().map_or(0.0, |chip| chip.output())
+            + self.sunsoft5b.as_ref().map_or(0.0, |chip| chip.output())
+    }
+
+    /// Reloads the tune, ready for the driver to INIT the selected track.
+    fn reset(&mut self) {
+        self.memory.fill(0);
+        self.playing = false;
+        self.play_due = false;
+
+        self.vrc6 = self.vrc6.take().map(|_| Vrc6Audio::new());
+        self.vrc7 = self.vrc7.take().map(|_| Opll::new());
+        self.fds = self.fds.take().map(|_| FdsAudio::new());
+        self.namco163 = self.namco163.take().map(|_| Namco163Audio::new());
+        self.sunsoft5b = self.sunsoft5b.take().map(|_| Sunsoft5bAudio::new());
+
+        match self.initial_banks {
+            Some(banks) => {
+                for (slot, &bank) in banks.iter().enumerate() {
+                    self.switch_bank(slot + 2, bank);
+                }
+                if self.uses_fds() {
+                    self.switch_bank(0, banks[6]);
+                    self.switch_bank(1, banks[7]);
+                }
+            }
+            None => {
+                let start = (self.load_address as usize).saturating_sub(0x6000);
+                let length = self.data.len().min(self.memory.len() - start);
+                self.memory[start..start + length].copy_from_slice(&self.data[..length]);
+            }
+        }
+    }
+}
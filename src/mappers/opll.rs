@@ -0,0 +1,398 @@
+use std::f32::consts::PI;
+
+/// Output rate of the OPLL: 3.58 MHz master clock divided by 72.
+const SAMPLE_RATE: f32 = 49716.0;
+
+/// Largest attenuation the envelope generator can represent, in dB.
+const MAX_ATTENUATION: f32 = 48.0;
+
+/// The 15 built-in instruments of the VRC7 variant of the YM2413.
+/// Instrument 0 is the user-defined patch held in registers $00-$07.
+const PATCHES: [[u8; 8]; 16] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x03, 0x21, 0x05, 0x06, 0xE8, 0x81, 0x42, 0x27],
+    [0x13, 0x41, 0x14, 0x0D, 0xD8, 0xF6, 0x23, 0x12],
+    [0x11, 0x11, 0x08, 0x08, 0xFA, 0xB2, 0x20, 0x12],
+    [0x31, 0x61, 0x0C, 0x07, 0xA8, 0x64, 0x61, 0x27],
+    [0x32, 0x21, 0x1E, 0x06, 0xE1, 0x76, 0x01, 0x28],
+    [0x02, 0x01, 0x06, 0x00, 0xA3, 0xE2, 0xF4, 0xF4],
+    [0x21, 0x61, 0x1D, 0x07, 0x82, 0x81, 0x11, 0x07],
+    [0x23, 0x21, 0x22, 0x17, 0xA2, 0x72, 0x01, 0x17],
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01],
+    [0xB5, 0x01, 0x0F, 0x0F, 0xA8, 0xA5, 0x51, 0x02],
+    [0x17, 0xC1, 0x24, 0x07, 0xF8, 0xF8, 0x22, 0x12],
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16],
+    [0x01, 0x02, 0xD3, 0x05, 0xC9, 0x95, 0x03, 0x02],
+    [0x61, 0x63, 0x0C, 0x00, 0x94, 0xC0, 0x33, 0xF6],
+    [0x21, 0x72, 0x0D, 0x00, 0xC1, 0xD5, 0x56, 0x06],
+];
+
+const MULTIPLIERS: [f32; 16] = [
+    0.5, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 10.0, 12.0, 12.0, 15.0, 15.0,
+];
+
+/// Key scale attenuation at block 7 for each of the top four F-number bits, in dB.
+const KEY_SCALE_LEVELS: [f32; 16] = [
+    0.0, 18.0, 24.0, 27.75, 30.0, 32.25, 33.75, 35.25,
+    36.0, 37.5, 38.25, 39.0, 39.75, 40.5, 41.25, 42.0,
+];
+
+/// dB per octave for each KSL setting, relative to the 6 dB/octave table above.
+const KEY_SCALE_FACTORS: [f32; 4] = [0.0, 0.25, 0.5, 1.0];
+
+const AM_DEPTH: f32 = 4.8;
+const AM_FREQUENCY: f32 = 3.7;
+const VIBRATO_DEPTH: f32 = 0.0081;
+const VIBRATO_FREQUENCY: f32 = 6.4;
+
+#[derive(Copy, Clone, PartialEq)]
+enum EnvelopeState {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    Off,
+}
+
+/// The parameters of one operator, decoded from an instrument patch.
+struct OperatorPatch {
+    am: bool,
+    vibrato: bool,
+    sustained: bool,
+    key_scale_rate: bool,
+    multiplier: f32,
+    key_scale_level: usize,
+    rectified: bool,
+    attack_rate: u8,
+    decay_rate: u8,
+    sustain_level: u8,
+    release_rate: u8,
+}
+
+impl OperatorPatch {
+    fn decode(patch: &[u8; 8], carrier: bool) -> Self {
+        let index = carrier as usize;
+        let flags = patch[index];
+
+        Self {
+            am: flags & 0x80 != 0,
+            vibrato: flags & 0x40 != 0,
+            sustained: flags & 0x20 != 0,
+            key_scale_rate: flags & 0x10 != 0,
+            multiplier: MULTIPLIERS[(flags & 0x0F) as usize],
+            key_scale_level: (patch[2 + index] >> 6) as usize,
+            rectified: patch[3] & (if carrier { 0x10 } else { 0x08 }) != 0,
+            attack_rate: patch[4 + index] >> 4,
+            decay_rate: patch[4 + index] & 0x0F,
+            sustain_level: patch[6 + index] >> 4,
+            release_rate: patch[6 + index] & 0x0F,
+        }
+    }
+}
+
+struct Operator {
+    phase: f32,
+    attenuation: f32,
+    state: EnvelopeState,
+    output: f32,
+    previous_output: f32,
+}
+
+impl Operator {
+    fn new() -> Self {
+        Self {
+            phase: 0.0,
+            attenuation: MAX_ATTENUATION,
+            state: EnvelopeState::Off,
+            output: 0.0,
+            previous_output: 0.0,
+        }
+    }
+
+    fn key_on(&mut self) {
+        self.phase = 0.0;
+        self.state = EnvelopeState::Attack;
+    }
+
+    fn key_off(&mut self) {
+        if self.state != EnvelopeState::Off {
+            self.state = EnvelopeState::Release;
+        }
+    }
+
+    /// Advances the envelope by one sample. `ksr_offset` is the rate bonus
+    /// derived from the channel's pitch.
+    fn step_envelope(&mut self, patch: &OperatorPatch, ksr_offset: u8, channel_sustain: bool) {
+        let rate = |r: u8| if r == 0 { 0 } else { (r * 4 + ksr_offset).min(63) };
+
+        match self.state {
+            EnvelopeState::Attack => {
+                let r = rate(patch.attack_rate);
+                if r >= 60 {
+                    self.attenuation = 0.0;
+                } else {
+                    self.attenuation -= self.attenuation * attack_coefficient(r);
+                }
+
+                if self.attenuation < 0.05 {
+                    self.attenuation = 0.0;
+                    self.state = EnvelopeState::Decay;
+                }
+            }
+            EnvelopeState::Decay => {
+                let sustain = patch.sustain_level as f32 * 3.0;
+                self.attenuation += decay_step(rate(patch.decay_rate));
+                if self.attenuation >= sustain {
+                    self.attenuation = sustain;
+                    self.state = EnvelopeState::Sustain;
+                }
+            }
+            EnvelopeState::Sustain => {
+                // Percussive patches keep fading at the release rate.
+                if !patch.sustained {
+                    self.attenuation += decay_step(rate(patch.release_rate));
+                }
+            }
+            EnvelopeState::Release => {
+                let r = if channel_sustain {
+                    5
+                } else if patch.sustained {
+                    patch.release_rate
+                } else {
+                    7
+                };
+                self.attenuation += decay_step(rate(r));
+            }
+            EnvelopeState::Off => {}
+        }
+
+        if self.attenuation >= MAX_ATTENUATION {
+            self.attenuation = MAX_ATTENUATION;
+            if self.state != EnvelopeState::Attack {
+                self.state = EnvelopeState::Off;
+            }
+        }
+    }
+
+    /// Produces the next sample, phase-modulated by `modulation` (in cycles).
+    fn step(&mut self, increment: f32, modulation: f32, rectified: bool, attenuation: f32) -> f32 {
+        self.phase = (self.phase + increment).fract();
+
+        let mut wave = (2.0 * PI * (self.phase + modulation)).sin();
+        if rectified && wave < 0.0 {
+            wave = 0.0;
+        }
+
+        let total = self.attenuation + attenuation;
+        let amplitude = if total >= MAX_ATTENUATION {
+            0.0
+        } else {
+            10f32.powf(-total / 20.0)
+        };
+
+        self.previous_output = self.output;
+        self.output = wave * amplitude;
+        self.output
+    }
+}
+
+/// dB added per sample while decaying, doubling every four rate steps.
+/// At the fastest rate a full decay takes about 2.4 ms.
+fn decay_step(rate: u8) -> f32 {
+    if rate < 4 {
+        return 0.0;
+    }
+
+    let octave = (rate >> 2) as i32 - 1;
+    4.9e-5 * 2f32.powi(octave) * (1.0 + (rate & 0x03) as f32 / 4.0)
+}
+
+/// Fraction of the remaining attenuation removed per sample while attacking,
+/// which gives the exponential attack curve of the real chip.
+fn attack_coefficient(rate: u8) -> f32 {
+    if rate < 4 {
+        return 0.0;
+    }
+
+    let octave = (rate >> 2) as i32 - 1;
+    (4.39e-5 * 2f32.powi(octave) * (1.0 + (rate & 0x03) as f32 / 4.0)).min(1.0)
+}
+
+struct Channel {
+    fnum: u16,
+    block: u8,
+    key_on: bool,
+    sustain: bool,
+    instrument: u8,
+    volume: u8,
+    modulator: Operator,
+    carrier: Operator,
+}
+
+impl Channel {
+    fn new() -> Self {
+        Self {
+            fnum: 0,
+            block: 0,
+            key_on: false,
+            sustain: false,
+            instrument: 0,
+            volume: 0,
+            modulator: Operator::new(),
+            carrier: Operator::new(),
+        }
+    }
+
+    fn key_scale_level(&self, setting: usize) -> f32 {
+        let level = KEY_SCALE_LEVELS[(self.fnum >> 5) as usize] - 6.0 * (7 - self.block) as f32;
+        level.max(0.0) * KEY_SCALE_FACTORS[setting]
+    }
+
+    fn ksr_offset(&self, key_scale_rate: bool) -> u8 {
+        let offset = (self.block << 1) | (self.fnum >> 8) as u8;
+        if key_scale_rate {
+            offset
+        } else {
+            offset >> 2
+        }
+    }
+}
+
+/// The YM2413 derived FM synthesiser inside the VRC7: six two-operator
+/// channels, fed through an address/data register pair.
+pub struct Opll {
+    address: u8,
+    custom_patch: [u8; 8],
+    channels: [Channel; 6],
+    am_phase: f32,
+    vibrato_phase: f32,
+    output: f32,
+}
+
+impl Opll {
+    pub fn new() -> Self {
+        Self {
+            address: 0,
+            custom_patch: [0; 8],
+            channels: [
+                Channel::new(), Channel::new(), Channel::new(),
+                Channel::new(), Channel::new(), Channel::new(),
+            ],
+            am_phase: 0.0,
+            vibrato_phase: 0.0,
+            output: 0.0,
+        }
+    }
+
+    pub fn write_address(&mut self, data: u8) {
+        self.address = data;
+    }
+
+    pub fn write_data(&mut self, data: u8) {
+        let register = self.address;
+        let index = (register & 0x0F) as usize;
+
+        match register {
+            0x00..=0x07 => self.custom_patch[index] = data,
+            0x10..=0x15 => {
+                let channel = &mut self.channels[index];
+                channel.fnum = (channel.fnum & 0x100) | data as u16;
+            }
+            0x20..=0x25 => {
+                let channel = &mut self.channels[index];
+                channel.fnum = (channel.fnum & 0xFF) | (((data & 0x01) as u16) << 8);
+                channel.block = (data >> 1) & 0x07;
+                channel.sustain = data & 0x20 != 0;
+
+                let key_on = data & 0x10 != 0;
+                if key_on && !channel.key_on {
+                    channel.modulator.key_on();
+                    channel.carrier.key_on();
+                } else if !key_on && channel.key_on {
+                    channel.modulator.key_off();
+                    channel.carrier.key_off();
+                }
+                channel.key_on = key_on;
+            }
+            0x30..=0x35 => {
+                let channel = &mut self.channels[index];
+                channel.instrument = data >> 4;
+                channel.volume = data & 0x0F;
+            }
+            _ => {}
+        }
+    }
+
+    /// Generates one sample. Must be called at 49716 Hz, i.e. every 36 CPU cycles.
+    pub fn step(&mut self) {
+        self.am_phase = (self.am_phase + AM_FREQUENCY / SAMPLE_RATE).fract();
+        self.vibrato_phase = (self.vibrato_phase + VIBRATO_FREQUENCY / SAMPLE_RATE).fract();
+
+        let am = (1.0 - (2.0 * self.am_phase - 1.0).abs()) * AM_DEPTH;
+        let vibrato = 1.0 + VIBRATO_DEPTH * (2.0 * PI * self.vibrato_phase).sin();
+
+        let mut mix = 0.0;
+        for channel in self.channels.iter_mut() {
+            let patch = if channel.instrument == 0 {
+                &self.custom_patch
+            } else {
+                &PATCHES[channel.instrument as usize]
+            };
+
+            let modulator_patch = OperatorPatch::decode(patch, false);
+            let carrier_patch = OperatorPatch::decode(patch, true);
+            let total_level = (patch[2] & 0x3F) as f32 * 0.75;
+            let feedback = patch[3] & 0x07;
+
+            let base = channel.fnum as f32 * (1u32 << channel.block) as f32 / (1u32 << 19) as f32;
+
+            // Modulator
+            let ksr_offset = channel.ksr_offset(modulator_patch.key_scale_rate);
+            channel.modulator.step_envelope(&modulator_patch, ksr_offset, channel.sustain);
+
+            let mut increment = base * modulator_patch.multiplier;
+            if modulator_patch.vibrato {
+                increment *= vibrato;
+            }
+
+            let feedback_cycles = if feedback == 0 {
+                0.0
+            } else {
+                let average = (channel.modulator.output + channel.modulator.previous_output) / 2.0;
+                average * 2.0 / (1 << (7 - feedback)) as f32
+            };
+
+            let mut attenuation = total_level + channel.key_scale_level(modulator_patch.key_scale_level);
+            if modulator_patch.am {
+                attenuation += am;
+            }
+
+            let modulation = channel.modulator.step(
+                increment, feedback_cycles, modulator_patch.rectified, attenuation);
+
+            // Carrier
+            let ksr_offset = channel.ksr_offset(carrier_patch.key_scale_rate);
+            channel.carrier.step_envelope(&carrier_patch, ksr_offset, channel.sustain);
+
+            let mut increment = base * carrier_patch.multiplier;
+            if carrier_patch.vibrato {
+                increment *= vibrato;
+            }
+
+            let mut attenuation = channel.volume as f32 * 3.0
+                + channel.key_scale_level(carrier_patch.key_scale_level);
+            if carrier_patch.am {
+                attenuation += am;
+            }
+
+            mix += channel.carrier.step(
+                increment, modulation * 2.0, carrier_patch.rectified, attenuation);
+        }
+
+        self.output = mix / 6.0;
+    }
+
+    pub fn output(&self) -> f32 {
+        self.output
+    }
+}
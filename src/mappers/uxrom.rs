@@ -0,0 +1,71 @@
+use crate::cartridge::{Mirroring, RomImage};
+use super::Mapper;
+
+/// Mapper 2: UxROM (UNROM, UOROM).
+/// A latch at $8000-$FFFF selects the 16 KB PRG bank at $8000; the last
+/// bank is fixed at $C000. CHR is 8 KB of unbanked RAM. Submapper 2 has bus
+/// conflicts, submapper 1 doesn't; without one, conflicts are assumed.
+pub struct UxRom {
+    rom: RomImage,
+    prg_bank: usize,
+}
+
+impl UxRom {
+    pub fn new(rom: RomImage) -> Self {
+        Self { rom, prg_bank: 0 }
+    }
+
+    fn prg_bank(&self, address: u16) -> usize {
+        if address < 0xC000 {
+            self.prg_bank
+        } else {
+            self.rom.prg_banks(0x4000) - 1
+        }
+    }
+}
+
+impl Mapper for UxRom {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        if address >= 0x8000 {
+            Some(self.rom.read_prg(self.prg_bank(address), 0x4000, address))
+        } else {
+            None
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x8000 {
+            return false;
+        }
+
+        let data = if self.rom.submapper == 1 {
+            data
+        } else {
+            data & self.rom.read_prg(self.prg_bank(address), 0x4000, address)
+        };
+        self.prg_bank = data as usize;
+
+        true
+    }
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8> {
+        if address < 0x2000 {
+            Some(self.rom.read_chr(0, 0x2000, address))
+        } else {
+            None
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x2000 {
+            self.rom.write_chr(0, 0x2000, address, data);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.rom.mirroring
+    }
+}
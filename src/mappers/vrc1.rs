@@ -0,0 +1,87 @@
+use crate::cartridge::{Mirroring, RomImage};
+use super::Mapper;
+
+/// Mapper 75: Konami VRC1.
+/// Three switchable 8 KB PRG banks plus a fixed last bank, and two 4 KB CHR
+/// banks whose fifth bit lives in the mirroring register.
+pub struct Vrc1 {
+    rom: RomImage,
+    prg_banks: [usize; 3],
+    chr_banks: [usize; 2],
+    mirroring: Mirroring,
+}
+
+impl Vrc1 {
+    pub fn new(rom: RomImage) -> Self {
+        Self {
+            rom,
+            prg_banks: [0, 1, 2],
+            chr_banks: [0, 1],
+            mirroring: Mirroring::Vertical,
+        }
+    }
+}
+
+impl Mapper for Vrc1 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x8000..=0x9FFF => Some(self.rom.read_prg(self.prg_banks[0], 0x2000, address)),
+            0xA000..=0xBFFF => Some(self.rom.read_prg(self.prg_banks[1], 0x2000, address)),
+            0xC000..=0xDFFF => Some(self.rom.read_prg(self.prg_banks[2], 0x2000, address)),
+            0xE000..=0xFFFF => {
+                let last = self.rom.prg_banks(0x2000) - 1;
+                Some(self.rom.read_prg(last, 0x2000, address))
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> bool {
+        match address & 0xF000 {
+            0x8000 => self.prg_banks[0] = (data & 0x0F) as usize,
+            0x9000 => {
+                self.mirroring = if data & 0x01 != 0 {
+                    Mirroring::Horizontal
+                } else {
+                    Mirroring::Vertical
+                };
+                self.chr_banks[0] = (self.chr_banks[0] & 0x0F) | (((data >> 1) & 0x01) << 4) as usize;
+                self.chr_banks[1] = (self.chr_banks[1] & 0x0F) | (((data >> 2) & 0x01) << 4) as usize;
+            }
+            0xA000 => self.prg_banks[1] = (data & 0x0F) as usize,
+            0xC000 => self.prg_banks[2] = (data & 0x0F) as usize,
+            0xE000 => self.chr_banks[0] = (self.chr_banks[0] & 0x10) | (data & 0x0F) as usize,
+            0xF000 => self.chr_banks[1] = (self.chr_banks[1] & 0x10) | (data & 0x0F) as usize,
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8> {
+        if address < 0x2000 {
+            let bank = self.chr_banks[(address >> 12) as usize];
+            Some(self.rom.read_chr(bank, 0x1000, address))
+        } else {
+            None
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x2000 {
+            let bank = self.chr_banks[(address >> 12) as usize];
+            self.rom.write_chr(bank, 0x1000, address, data);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if self.rom.mirroring == Mirroring::FourScreen {
+            Mirroring::FourScreen
+        } else {
+            self.mirroring
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use crate::cartridge::{Mirroring, RomImage};
+use super::Mapper;
+use super::vrc_irq::VrcIrq;
+
+/// Mappers 21, 22, 23 and 25: Konami VRC2 and VRC4.
+///
+/// Both chips decode four registers per $1000 block using two address lines,
+/// but every board revision wires a different pair of CPU address lines to
+/// them. The submapper tells which pair; submapper 0 means "unknown", in which
+/// case both candidate pairs are OR-ed together, as most emulators do.
+pub struct Vrc2And4 {
+    rom: RomImage,
+    vrc4: bool,
+    a0_lines: u16,
+    a1_lines: u16,
+    chr_shift: u8,
+
+    prg_banks: [usize; 2],
+    prg_swap_mode: bool,
+    chr_banks: [usize; 8],
+    mirroring: Mirroring,
+    microwire_latch: u8,
+    irq: VrcIrq,
+}
+
+impl Vrc2And4 {
+    pub fn new(rom: RomImage) -> Self {
+        //  (A0 lines, A1 lines, is VRC4)
+        let (a0_lines, a1_lines, vrc4) = match (rom.mapper_id, rom.submapper) {
+            (21, 1) => (0x02, 0x04, true),      // VRC4a
+            (21, 2) => (0x40, 0x80, true),      // VRC4c
+            (21, _) => (0x42, 0x84, true),
+            (22, _) => (0x02, 0x01, false),     // VRC2a
+            (23, 1) => (0x01, 0x02, true),      // VRC4f
+            (23, 2) => (0x04, 0x08, true),      // VRC4e
+            (23, 3) => (0x01, 0x02, false),     // VRC2b
+            (23, _) => (0x05, 0x0A, true),
+            (25, 1) => (0x02, 0x01, true),      // VRC4b
+            (25, 2) => (0x08, 0x04, true),      // VRC4d
+            (25, 3) => (0x02, 0x01, false),     // VRC2c
+            (_, _) => (0x0A, 0x05, true),
+        };
+
+        // VRC2a ignores the lowest CHR bank bit, addressing CHR in 2 KB steps.
+        let chr_shift = if rom.mapper_id == 22 { 1 } else { 0 };
+
+        Self {
+            rom,
+            vrc4,
+            a0_lines,
+            a1_lines,
+            chr_shift,
+            prg_banks: [0, 1],
+            prg_swap_mode: false,
+            chr_banks: [0; 8],
+            mirroring: Mirroring::Vertical,
+            microwire_latch: 0,
+            irq: VrcIrq::new(),
+        }
+    }
+
+    /// Folds the board-specific address lines into register numbers 0-3.
+    fn register(&self, address: u16) -> u16 {
+        let a0 = (address & self.a0_lines != 0) as u16;
+        let a1 = (address & self.a1_lines != 0) as u16;
+        (address & 0xF000) | (a1 << 1) | a0
+    }
+
+    fn prg_bank(&self, address: u16) -> usize {
+        // Undersized PRG-ROM just mirrors; read_prg wraps the bank number.
+        let last = self.rom.prg_banks(0x2000) - 1;
+        let second_last = last.saturating_sub(1);
+
+        match (address, self.prg_swap_mode) {
+            (0x8000..=0x9FFF, false) => self.prg_banks[0],
+            (0x8000..=0x9FFF, true) => second_last,
+            (0xA000..=0xBFFF, _) => self.prg_banks[1],
+            (0xC000..=0xDFFF, false) => second_last,
+            (0xC000..=0xDFFF, true) => self.prg_banks[0],
+            _ => last,
+        }
+    }
+
+    fn chr_bank(&self, address: u16) -> usize {
+        self.chr_banks[(address >> 10) as usize] >> self.chr_shift
+    }
+}
+
+impl Mapper for Vrc2And4 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            // Boards without PRG-RAM have a one bit latch here that some
+            // games use as a copy protection check.
+            0x6000..=0x6FFF if !self.vrc4 && self.rom.prg_ram_size == 0 => {
+                Some(self.microwire_latch)
+            }
+            0x8000..=0xFFFF => Some(self.rom.read_prg(self.prg_bank(address), 0x2000, address)),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> bool {
+        if (0x6000..=0x6FFF).contains(&address) && !self.vrc4 && self.rom.prg_ram_size == 0 {
+            self.microwire_latch = data & 0x01;
+            return true;
+        }
+
+        if address < 0x8000 {
+            return false;
+        }
+
+        let register = self.register(address);
+        match register {
+            0x8000..=0x8003 => self.prg_banks[0] = (data & 0x1F) as usize,
+            0x9000..=0x9003 if !self.vrc4 => {
+                self.mirroring = if data & 0x01 != 0 {
+                    Mirroring::Horizontal
+                } else {
+                    Mirroring::Vertical
+                };
+            }
+            0x9000 | 0x9001 => {
+                self.mirroring = match data & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLow,
+                    _ => Mirroring::SingleScreenHigh,
+                };
+            }
+            0x9002 | 0x9003 => self.prg_swap_mode = data & 0x02 != 0,
+            0xA000..=0xA003 => self.prg_banks[1] = (data & 0x1F) as usize,
+            0xB000..=0xEFFF => {
+                // Each CHR bank is split in a low nibble and a high 5 bits,
+                // two banks per $1000 block.
+                let index = (((register - 0xB000) >> 12) * 2 + ((register >> 1) & 0x01)) as usize;
+                if register & 0x01 == 0 {
+                    self.chr_banks[index] = (self.chr_banks[index] & !0x0F) | (data & 0x0F) as usize;
+                } else {
+                    self.chr_banks[index] = (self.chr_banks[index] & 0x0F) | (((data & 0x1F) as usize) << 4);
+                }
+            }
+            0xF000 if self.vrc4 => self.irq.write_latch_low(data),
+            0xF001 if self.vrc4 => self.irq.write_latch_high(data),
+            0xF002 if self.vrc4 => self.irq.write_control(data),
+            0xF003 if self.vrc4 => self.irq.acknowledge(),
+            _ => {}
+        }
+
+        true
+    }
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8> {
+        if address < 0x2000 {
+            Some(self.rom.read_chr(self.chr_bank(address), 0x0400, address))
+        } else {
+            None
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x2000 {
+            let bank = self.chr_bank(address);
+            self.rom.write_chr(bank, 0x0400, address, data);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn irq_state(&self) -> bool {
+        self.irq.state()
+    }
+
+    fn clock(&mut self) {
+        self.irq.clock();
+    }
+}
@@ -0,0 +1,317 @@
+use crate::cartridge::{Mirroring, RomImage};
+use super::Mapper;
+use super::vrc_irq::VrcIrq;
+
+/// Mappers 24 and 26: Konami VRC6.
+/// Mapper 26 (VRC6b) is the same chip with A0 and A1 swapped.
+pub struct Vrc6 {
+    rom: RomImage,
+    swap_lines: bool,
+
+    prg_16k_bank: usize,
+    prg_8k_bank: usize,
+    chr_registers: [usize; 8],
+    banking_mode: u8,
+    irq: VrcIrq,
+    audio: Vrc6Audio,
+}
+
+impl Vrc6 {
+    pub fn new(rom: RomImage) -> Self {
+        Self {
+            swap_lines: rom.mapper_id == 26,
+            rom,
+            prg_16k_bank: 0,
+            prg_8k_bank: 0,
+            chr_registers: [0; 8],
+            banking_mode: 0,
+            irq: VrcIrq::new(),
+            audio: Vrc6Audio::new(),
+        }
+    }
+
+    fn register(&self, address: u16) -> u16 {
+        if self.swap_lines {
+            (address & 0xF000) | ((address & 0x01) << 1) | ((address & 0x02) >> 1)
+        } else {
+            address & 0xF003
+        }
+    }
+
+    /// Resolves a pattern table address to a 1 KB CHR bank according to
+    /// the low two bits of $B003.
+    fn chr_bank(&self, address: u16) -> usize {
+        let slot = (address >> 10) as usize;
+        let r = &self.chr_registers;
+
+        match self.banking_mode & 0x03 {
+            0 => r[slot],
+            1 => (r[slot >> 1] << 1) | (slot & 0x01),
+            _ => {
+                if slot < 4 {
+                    r[slot]
+                } else {
+                    (r[4 + ((slot - 4) >> 1)] << 1) | (slot & 0x01)
+                }
+            }
+        }
+    }
+}
+
+impl Mapper for Vrc6 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x8000..=0xBFFF => Some(self.rom.read_prg(self.prg_16k_bank, 0x4000, address)),
+            0xC000..=0xDFFF => Some(self.rom.read_prg(self.prg_8k_bank, 0x2000, address)),
+            0xE000..=0xFFFF => {
+                let last = self.rom.prg_banks(0x2000) - 1;
+                Some(self.rom.read_prg(last, 0x2000, address))
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x8000 {
+            return false;
+        }
+
+        match self.register(address) {
+            0x8000..=0x8003 => self.prg_16k_bank = (data & 0x0F) as usize,
+            register @ 0x9000..=0xB002 => self.audio.write(register, data),
+            0xB003 => self.banking_mode = data,
+            0xC000..=0xC003 => self.prg_8k_bank = (data & 0x1F) as usize,
+            register @ 0xD000..=0xE003 => {
+                let index = (((register >> 12) - 0xD) * 4 + (register & 0x03)) as usize;
+                self.chr_registers[index] = data as usize;
+            }
+            0xF000 => self.irq.write_latch(data),
+            0xF001 => self.irq.write_control(data),
+            0xF002 => self.irq.acknowledge(),
+            _ => {}
+        }
+
+        true
+    }
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8> {
+        if address < 0x2000 {
+            Some(self.rom.read_chr(self.chr_bank(address), 0x0400, address))
+        } else {
+            None
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x2000 {
+            let bank = self.chr_bank(address);
+            self.rom.write_chr(bank, 0x0400, address, data);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match (self.banking_mode >> 2) & 0x03 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenLow,
+            _ => Mirroring::SingleScreenHigh,
+        }
+    }
+
+    fn irq_state(&self) -> bool {
+        self.irq.state()
+    }
+
+    fn clock(&mut self) {
+        self.irq.clock();
+        self.audio.clock();
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+}
+
+struct Vrc6Pulse {
+    volume: u8,
+    duty: u8,
+    ignore_duty: bool,
+    period: u16,
+    enabled: bool,
+    timer: u16,
+    step: u8,
+}
+
+impl Vrc6Pulse {
+    fn new() -> Self {
+        Self {
+            volume: 0,
+            duty: 0,
+            ignore_duty: false,
+            period: 0,
+            enabled: false,
+            timer: 0,
+            step: 0,
+        }
+    }
+
+    fn write(&mut self, register: u16, data: u8) {
+        match register & 0x03 {
+            0 => {
+                self.ignore_duty = data & 0x80 != 0;
+                self.duty = (data >> 4) & 0x07;
+                self.volume = data & 0x0F;
+            }
+            1 => self.period = (self.period & 0x0F00) | data as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | (((data & 0x0F) as u16) << 8);
+                self.enabled = data & 0x80 != 0;
+                if !self.enabled {
+                    self.step = 0;
+                }
+            }
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.timer == 0 {
+            self.timer = self.period >> shift;
+            self.step = (self.step + 1) & 0x0F;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.enabled && (self.ignore_duty || self.step <= self.duty) {
+            self.volume
+        } else {
+            0
+        }
+    }
+}
+
+struct Vrc6Sawtooth {
+    rate: u8,
+    period: u16,
+    enabled: bool,
+    timer: u16,
+    step: u8,
+    accumulator: u8,
+}
+
+impl Vrc6Sawtooth {
+    fn new() -> Self {
+        Self {
+            rate: 0,
+            period: 0,
+            enabled: false,
+            timer: 0,
+            step: 0,
+            accumulator: 0,
+        }
+    }
+
+    fn write(&mut self, register: u16, data: u8) {
+        match register & 0x03 {
+            0 => self.rate = data & 0x3F,
+            1 => self.period = (self.period & 0x0F00) | data as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | (((data & 0x0F) as u16) << 8);
+                self.enabled = data & 0x80 != 0;
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+        }
+    }
+
+    /// The accumulator is added to on every other step and cleared on the
+    /// 14th, giving a 7 step ramp.
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.timer == 0 {
+            self.timer = self.period >> shift;
+            self.step += 1;
+            if self.step == 14 {
+                self.step = 0;
+                self.accumulator = 0;
+            } else if self.step & 0x01 == 0 {
+                self.accumulator = self.accumulator.wrapping_add(self.rate);
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
+
+/// Two pulse channels and a sawtooth channel, clocked at the CPU rate.
+pub struct Vrc6Audio {
+    pulse1: Vrc6Pulse,
+    pulse2: Vrc6Pulse,
+    sawtooth: Vrc6Sawtooth,
+    halt: bool,
+    frequency_shift: u8,
+}
+
+impl Vrc6Audio {
+    pub fn new() -> Self {
+        Self {
+            pulse1: Vrc6Pulse::new(),
+            pulse2: Vrc6Pulse::new(),
+            sawtooth: Vrc6Sawtooth::new(),
+            halt: false,
+            frequency_shift: 0,
+        }
+    }
+
+    /// Takes the register already folded to $9000-$9003, $A000-$A002 or $B000-$B002.
+    pub fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0x9003 => {
+                self.halt = data & 0x01 != 0;
+                self.frequency_shift = if data & 0x04 != 0 {
+                    8
+                } else if data & 0x02 != 0 {
+                    4
+                } else {
+                    0
+                };
+            }
+            0x9000..=0x9002 => self.pulse1.write(register, data),
+            0xA000..=0xA002 => self.pulse2.write(register, data),
+            0xB000..=0xB002 => self.sawtooth.write(register, data),
+            _ => {}
+        }
+    }
+
+    pub fn clock(&mut self) {
+        if self.halt {
+            return;
+        }
+
+        self.pulse1.clock(self.frequency_shift);
+        self.pulse2.clock(self.frequency_shift);
+        self.sawtooth.clock(self.frequency_shift);
+    }
+
+    pub fn output(&self) -> f32 {
+        let sum = self.pulse1.output() + self.pulse2.output() + self.sawtooth.output();
+        sum as f32 / 61.0
+    }
+}
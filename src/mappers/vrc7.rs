@@ -0,0 +1,150 @@
+use crate::cartridge::{Mirroring, RomImage};
+use super::Mapper;
+use super::opll::Opll;
+use super::vrc_irq::VrcIrq;
+
+/// CPU cycles between two OPLL samples (3.58 MHz / 72 vs 1.79 MHz).
+pub const OPLL_DIVIDER: u8 = 36;
+
+/// Mapper 85: Konami VRC7.
+/// Submapper 1 (VRC7b) decodes the second register of each block with A3,
+/// submapper 2 (VRC7a) with A4.
+pub struct Vrc7 {
+    rom: RomImage,
+    register_lines: u16,
+
+    prg_banks: [usize; 3],
+    chr_banks: [usize; 8],
+    mirroring: Mirroring,
+    irq: VrcIrq,
+    opll: Opll,
+    opll_divider: u8,
+    audio_silenced: bool,
+}
+
+impl Vrc7 {
+    pub fn new(rom: RomImage) -> Self {
+        let register_lines = match rom.submapper {
+            1 => 0x08,
+            2 => 0x10,
+            _ => 0x18,
+        };
+
+        Self {
+            rom,
+            register_lines,
+            prg_banks: [0, 1, 2],
+            chr_banks: [0; 8],
+            mirroring: Mirroring::Vertical,
+            irq: VrcIrq::new(),
+            opll: Opll::new(),
+            opll_divider: 0,
+            audio_silenced: false,
+        }
+    }
+}
+
+impl Mapper for Vrc7 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x8000..=0x9FFF => Some(self.rom.read_prg(self.prg_banks[0], 0x2000, address)),
+            0xA000..=0xBFFF => Some(self.rom.read_prg(self.prg_banks[1], 0x2000, address)),
+            0xC000..=0xDFFF => Some(self.rom.read_prg(self.prg_banks[2], 0x2000, address)),
+            0xE000..=0xFFFF => {
+                let last = self.rom.prg_banks(0x2000) - 1;
+                Some(self.rom.read_prg(last, 0x2000, address))
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x8000 {
+            return false;
+        }
+
+        // The sound registers are decoded with A5 as well, on every variant.
+        match address & 0xF030 {
+            0x9010 => {
+                self.opll.write_address(data);
+                return true;
+            }
+            0x9030 => {
+                self.opll.write_data(data);
+                return true;
+            }
+            _ => {}
+        }
+
+        let high = (address & self.register_lines != 0) as u16;
+        match (address & 0xF000) | high {
+            0x8000 => self.prg_banks[0] = (data & 0x3F) as usize,
+            0x8001 => self.prg_banks[1] = (data & 0x3F) as usize,
+            0x9000 => self.prg_banks[2] = (data & 0x3F) as usize,
+            register @ 0xA000..=0xD001 => {
+                let index = ((((register >> 12) - 0xA) << 1) | (register & 0x01)) as usize;
+                self.chr_banks[index] = data as usize;
+            }
+            0xE000 => {
+                self.mirroring = match data & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLow,
+                    _ => Mirroring::SingleScreenHigh,
+                };
+                self.audio_silenced = data & 0x40 != 0;
+            }
+            0xE001 => self.irq.write_latch(data),
+            0xF000 => self.irq.write_control(data),
+            0xF001 => self.irq.acknowledge(),
+            _ => {}
+        }
+
+        true
+    }
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8> {
+        if address < 0x2000 {
+            let bank = self.chr_banks[(address >> 10) as usize];
+            Some(self.rom.read_chr(bank, 0x0400, address))
+        } else {
+            None
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x2000 {
+            let bank = self.chr_banks[(address >> 10) as usize];
+            self.rom.write_chr(bank, 0x0400, address, data);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn irq_state(&self) -> bool {
+        self.irq.state()
+    }
+
+    fn clock(&mut self) {
+        self.irq.clock();
+
+        self.opll_divider += 1;
+        if self.opll_divider == OPLL_DIVIDER {
+            self.opll_divider = 0;
+            self.opll.step();
+        }
+    }
+
+    fn audio_output(&self) -> f32 {
+        if self.audio_silenced {
+            0.0
+        } else {
+            self.opll.output()
+        }
+    }
+}
@@ -0,0 +1,233 @@
+pub mod player;
+
+use std::fmt;
+use std::io;
+
+use crate::archive::ArchiveError;
+
+const NSF_HEADER_SIZE: usize = 0x80;
+
+/// Expansion sound chips, as flagged in byte $7B of the header.
+pub const CHIP_VRC6: u8 = 0x01;
+pub const CHIP_VRC7: u8 = 0x02;
+pub const CHIP_FDS: u8 = 0x04;
+pub const CHIP_MMC5: u8 = 0x08;
+pub const CHIP_NAMCO163: u8 = 0x10;
+pub const CHIP_SUNSOFT5B: u8 = 0x20;
+
+#[derive(Debug)]
+pub enum NsfError {
+    Io(io::Error),
+    Archive(ArchiveError),
+    InvalidHeader,
+    Truncated,
+    MissingChunk(&'static str),
+}
+
+impl fmt::Display for NsfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NsfError::Io(error) => write!(f, "could not read NSF: {}", error),
+            NsfError::Archive(error) => write!(f, "{}", error),
+            NsfError::InvalidHeader => write!(f, "not an NSF or NSFe file"),
+            NsfError::Truncated => write!(f, "NSF file is truncated"),
+            NsfError::MissingChunk(id) => write!(f, "NSFe file has no {} chunk", id),
+        }
+    }
+}
+
+impl From<io::Error> for NsfError {
+    fn from(error: io::Error) -> Self {
+        NsfError::Io(error)
+    }
+}
+
+impl From<ArchiveError> for NsfError {
+    fn from(error: ArchiveError) -> Self {
+        NsfError::Archive(error)
+    }
+}
+
+/// Per track metadata; NSFe files may carry it, plain NSF files never do.
+#[derive(Clone, Default)]
+pub struct Track {
+    pub title: Option<String>,
+    /// Length in milliseconds before the fade starts.
+    pub length: Option<u32>,
+    /// Fade out time in milliseconds.
+    pub fade: Option<u32>,
+}
+
+/// A parsed NSF or NSFe file.
+pub struct Nsf {
+    pub load_address: u16,
+    pub init_address: u16,
+    pub play_address: u16,
+    /// Initial values of the bank registers at $5FF8-$5FFF, or `None` for
+    /// tunes that are loaded linearly at the load address.
+    pub banks: Option<[u8; 8]>,
+    /// PLAY call periods in microseconds.
+    pub ntsc_speed: u16,
+    pub pal_speed: u16,
+    /// Bit 0 set for PAL, bit 1 set for tunes that work on both.
+    pub region_flags: u8,
+    pub chips: u8,
+    pub data: Vec<u8>,
+
+    pub title: String,
+    pub artist: String,
+    pub copyright: String,
+    pub starting_track: usize,
+    pub tracks: Vec<Track>,
+}
+
+/// Reads a null terminated (or full length) string of a fixed size field.
+fn string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+/// A list of null separated strings, as used by the NSFe text chunks.
+fn strings(bytes: &[u8]) -> Vec<String> {
+    bytes.split(|&b| b == 0).map(|text| String::from_utf8_lossy(text).into_owned()).collect()
+}
+
+fn millisecond_list(bytes: &[u8]) -> Vec<i32> {
+    bytes.chunks_exact(4).map(|v| i32::from_le_bytes([v[0], v[1], v[2], v[3]])).collect()
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+impl Nsf {
+    pub fn is_nsf(bytes: &[u8]) -> bool {
+        bytes.starts_with(b"NESM\x1A") || bytes.starts_with(b"NSFE")
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, NsfError> {
+        if bytes.starts_with(b"NESM\x1A") {
+            Self::parse_nsf(bytes)
+        } else if bytes.starts_with(b"NSFE") {
+            Self::parse_nsfe(bytes)
+        } else {
+            Err(NsfError::InvalidHeader)
+        }
+    }
+
+    fn parse_nsf(bytes: &[u8]) -> Result<Self, NsfError> {
+        if bytes.len() < NSF_HEADER_SIZE {
+            return Err(NsfError::Truncated);
+        }
+
+        let banks: [u8; 8] = bytes[0x70..0x78].try_into().unwrap();
+        let track_count = (bytes[0x06] as usize).max(1);
+
+        Ok(Self {
+            load_address: u16_at(bytes, 0x08),
+            init_address: u16_at(bytes, 0x0A),
+            play_address: u16_at(bytes, 0x0C),
+            banks: banks.iter().any(|&bank| bank != 0).then_some(banks),
+            ntsc_speed: u16_at(bytes, 0x6E),
+            pal_speed: u16_at(bytes, 0x78),
+            region_flags: bytes[0x7A] & 0x03,
+            chips: bytes[0x7B],
+            data: bytes[NSF_HEADER_SIZE..].to_vec(),
+            title: string(&bytes[0x0E..0x2E]),
+            artist: string(&bytes[0x2E..0x4E]),
+            copyright: string(&bytes[0x4E..0x6E]),
+            starting_track: (bytes[0x07] as usize).clamp(1, track_count) - 1,
+            tracks: vec![Track::default(); track_count],
+        })
+    }
+
+    /// NSFe files are a "NSFE" tag followed by chunks of (little endian u32
+    /// length, 4 byte ID, data). Chunks with a lowercase first letter are optional.
+    fn parse_nsfe(bytes: &[u8]) -> Result<Self, NsfError> {
+        let mut info = None;
+        let mut data = None;
+        let mut banks = None;
+        let mut rate = None;
+        let mut titles = Vec::new();
+        let mut times = Vec::new();
+        let mut fades = Vec::new();
+        let mut authors = Vec::new();
+
+        let mut offset = 4;
+        while offset + 8 <= bytes.len() {
+            let length = u32::from_le_bytes([
+                bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3],
+            ]) as usize;
+            let id = &bytes[offset + 4..offset + 8];
+            let start = offset + 8;
+            let end = start.checked_add(length).ok_or(NsfError::Truncated)?;
+            if end > bytes.len() {
+                return Err(NsfError::Truncated);
+            }
+            let chunk = &bytes[start..end];
+
+            match id {
+                b"INFO" => info = Some(chunk),
+                b"DATA" => data = Some(chunk),
+                b"BANK" => {
+                    let mut values = [0; 8];
+                    let count = chunk.len().min(8);
+                    values[..count].copy_from_slice(&chunk[..count]);
+                    banks = Some(values);
+                }
+                b"RATE" => rate = Some(chunk),
+                b"tlbl" => titles = strings(chunk),
+                b"auth" => authors = strings(chunk),
+                b"time" => times = millisecond_list(chunk),
+                b"fade" => fades = millisecond_list(chunk),
+                b"NEND" => break,
+                _ => {}
+            }
+
+            offset = end;
+        }
+
+        let info = info.ok_or(NsfError::MissingChunk("INFO"))?;
+        let data = data.ok_or(NsfError::MissingChunk("DATA"))?;
+        if info.len() < 8 {
+            return Err(NsfError::Truncated);
+        }
+
+        let track_count = info.get(8).map_or(1, |&count| count as usize).max(1);
+        let starting_track = info.get(9).map_or(0, |&track| track as usize).min(track_count - 1);
+
+        // Negative values mean the length isn't known.
+        let millis = |values: &[i32], track: usize| values.get(track).filter(|&&ms| ms >= 0).map(|&ms| ms as u32);
+        let tracks = (0..track_count)
+            .map(|track| Track {
+                title: titles.get(track).filter(|title| !title.is_empty()).cloned(),
+                length: millis(&times, track),
+                fade: millis(&fades, track),
+            })
+            .collect();
+
+        let author = |index: usize| authors.get(index).cloned().unwrap_or_default();
+
+        Ok(Self {
+            load_address: u16_at(info, 0),
+            init_address: u16_at(info, 2),
+            play_address: u16_at(info, 4),
+            banks: banks.filter(|banks| banks.iter().any(|&bank| bank != 0)),
+            // NSFe defaults to the usual 60 Hz and 50 Hz.
+            ntsc_speed: rate.filter(|rate| rate.len() >= 2).map_or(16639, |rate| u16_at(rate, 0)),
+            pal_speed: rate.filter(|rate| rate.len() >= 4).map_or(19997, |rate| u16_at(rate, 2)),
+            region_flags: info[6] & 0x03,
+            chips: info[7],
+            data: data.to_vec(),
+            title: author(0),
+            artist: author(1),
+            copyright: author(2),
+            starting_track,
+            tracks,
+        })
+    }
+
+    pub fn is_pal(&self) -> bool {
+        self.region_flags & 0x03 == 0x01
+    }
+}
@@ -0,0 +1,174 @@
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::apu::output::AudioOutput;
+use crate::archive;
+use crate::bus::Bus;
+use crate::cartridge::{Cartridge, Region};
+use crate::mappers::{REGION_REGISTER, TRACK_REGISTER};
+use super::{Nsf, NsfError, Track};
+
+/// Plays NSF and NSFe tunes without any video: pick a track, then clock
+/// the system and read its audio output.
+pub struct NsfPlayer {
+    bus: Box<Bus>,
+    nsf: Nsf,
+    pal: bool,
+    track: usize,
+    cycles: u64,
+    audio: Option<AudioOutput>,
+}
+
+impl NsfPlayer {
+    pub fn new(nsf: Nsf) -> Self {
+        let cartridge = Rc::new(RefCell::new(Cartridge::from_nsf(&nsf)));
+
+        let mut bus = Box::new(Bus::new());
+        bus.connect_cpu();
+        // NSF cartridges have no battery, so there's no save to fail.
+        let _ = bus.insert_cartridge(cartridge);
+
+        let mut player = Self {
+            bus,
+            pal: nsf.is_pal(),
+            track: nsf.starting_track,
+            nsf,
+            cycles: 0,
+            audio: None,
+        };
+        player.select_track(player.track);
+        player
+    }
+
+    /// Loads a .nsf or .nsfe file, or the first one in a zip archive.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, NsfError> {
+        let mut bytes = fs::read(path)?;
+        if archive::is_archive(&bytes) {
+            bytes = archive::extract(&bytes, None)?.1;
+        }
+        Ok(Self::new(Nsf::parse(&bytes)?))
+    }
+
+    pub fn title(&self) -> &str {
+        &self.nsf.title
+    }
+
+    pub fn artist(&self) -> &str {
+        &self.nsf.artist
+    }
+
+    pub fn copyright(&self) -> &str {
+        &self.nsf.copyright
+    }
+
+    pub fn tracks(&self) -> &[Track] {
+        &self.nsf.tracks
+    }
+
+    pub fn track(&self) -> usize {
+        self.track
+    }
+
+    /// Expansion chips the tune uses, as the `CHIP_*` flags.
+    pub fn chips(&self) -> u8 {
+        self.nsf.chips
+    }
+
+    /// Plays the tune with PAL timing. Only tunes flagged as supporting both
+    /// regions can be switched; the change applies from the next track selection.
+    pub fn set_pal(&mut self, pal: bool) {
+        if self.nsf.region_flags & 0x02 != 0 {
+            self.pal = pal;
+        }
+    }
+
+    /// Restarts the system and runs INIT for `track`, counting from zero.
+    pub fn select_track(&mut self, track: usize) {
+        self.track = track.min(self.nsf.tracks.len() - 1);
+        self.cycles = 0;
+
+        self.bus.write(TRACK_REGISTER, self.track as u8);
+        self.bus.write(REGION_REGISTER, self.pal as u8);
+        self.bus.set_region(self.region());
+        self.bus.reset();
+        let clock_rate = self.cpu_clock_rate();
+        if let Some(audio) = &mut self.audio {
+            audio.set_clock_rate(clock_rate);
+        }
+    }
+
+    fn region(&self) -> Region {
+        if self.pal {
+            Region::Pal
+        } else {
+            Region::Ntsc
+        }
+    }
+
+    pub fn cpu_clock_rate(&self) -> u64 {
+        self.region().cpu_clock_rate()
+    }
+
+    /// Resamples the tune for the host, with the fade applied.
+    pub fn set_audio_output(&mut self, audio: Option<AudioOutput>) {
+        self.audio = audio;
+        let clock_rate = self.cpu_clock_rate();
+        if let Some(audio) = &mut self.audio {
+            audio.set_clock_rate(clock_rate);
+        }
+    }
+
+    pub fn audio(&mut self) -> Option<&mut AudioOutput> {
+        self.audio.as_mut()
+    }
+
+    /// Advances by one CPU cycle.
+    pub fn clock(&mut self) {
+        self.bus.clock();
+        self.cycles += 1;
+
+        if self.audio.is_some() {
+            let level = self.audio_output();
+            if let Some(audio) = &mut self.audio {
+                audio.clock(level);
+            }
+        }
+    }
+
+    /// Time since the track started, in milliseconds.
+    pub fn elapsed(&self) -> u64 {
+        self.cycles * 1000 / self.cpu_clock_rate()
+    }
+
+    /// Volume to apply for the track's fade out: 1.0 until the track's
+    /// length, then down to 0.0 over the fade time. Tracks without a known
+    /// length play forever.
+    pub fn fade_volume(&self) -> f32 {
+        let info = &self.nsf.tracks[self.track];
+        let Some(length) = info.length else {
+            return 1.0;
+        };
+
+        let elapsed = self.elapsed();
+        let fade = info.fade.unwrap_or(0) as u64;
+        if elapsed < length as u64 {
+            1.0
+        } else if elapsed < length as u64 + fade {
+            1.0 - (elapsed - length as u64) as f32 / fade as f32
+        } else {
+            0.0
+        }
+    }
+
+    /// True once a track with a known length has finished fading out.
+    pub fn finished(&self) -> bool {
+        self.nsf.tracks[self.track].length.is_some() && self.fade_volume() == 0.0
+    }
+
+    /// The APU and expansion chips' output for now, with the fade applied.
+    pub fn audio_output(&self) -> f32 {
+        self.bus.audio_level() * self.fade_volume()
+    }
+}
@@ -0,0 +1,77 @@
+//! A minimal PNG writer for RGBA images, for screenshots and debug views.
+//!
+//! The image data is stored uncompressed inside the zlib stream, which every
+//! decoder accepts and needs no deflate encoder.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::checksum::Crc32;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/// Largest deflate block that can be stored without compression.
+const STORED_BLOCK_SIZE: usize = 0xFFFF;
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+
+    let mut crc = Crc32::new();
+    crc.update(kind);
+    crc.update(data);
+
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    png.extend_from_slice(&crc.finish().to_be_bytes());
+}
+
+/// Encodes `width` x `height` pixels of RGBA, row by row.
+pub fn encode(width: usize, height: usize, rgba: &[u8]) -> Vec<u8> {
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per channel, RGBA, deflate, standard filters, not interlaced.
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    // Every row starts with its filter type, 0 for none.
+    let mut raw = Vec::with_capacity(height * (width * 4 + 1));
+    for row in rgba.chunks_exact(width * 4).take(height) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut zlib = vec![0x78, 0x01];
+    let blocks = raw.chunks(STORED_BLOCK_SIZE);
+    let count = blocks.len();
+    for (index, block) in blocks.enumerate() {
+        zlib.push((index + 1 == count) as u8);
+        zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut png = SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib);
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+pub fn save<P: AsRef<Path>>(path: P, width: usize, height: usize, rgba: &[u8]) -> io::Result<()> {
+    fs::write(path, encode(width, height, rgba))
+}
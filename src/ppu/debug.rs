@@ -0,0 +1,265 @@
+//! Pictures of the PPU's memory for debugging graphics: the nametables,
+//! pattern tables, sprites and palette, as RGBA images.
+//!
+//! Everything is drawn from a `Snapshot`, taken either right away or when
+//! the PPU reaches a chosen scanline and dot, since games often change
+//! banks and palettes partway through the frame.
+
+use std::io;
+use std::path::Path;
+
+use crate::png;
+use super::framebuffer;
+use super::PPU;
+
+/// Colour of the scroll window drawn over the nametables.
+const SCROLL_OUTLINE: [u8; 3] = [0xFF, 0x00, 0xFF];
+
+/// Size of each colour in the palette view.
+const SWATCH_SIZE: usize = 16;
+
+/// An RGBA picture, row by row.
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    pub(crate) fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width * height * 4],
+        }
+    }
+
+    pub(crate) fn set(&mut self, x: usize, y: usize, [r, g, b]: [u8; 3]) {
+        let index = (y * self.width + x) * 4;
+        self.pixels[index..index + 4].copy_from_slice(&[r, g, b, 0xFF]);
+    }
+
+    pub fn to_png(&self) -> Vec<u8> {
+        png::encode(self.width, self.height, &self.pixels)
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        png::save(path, self.width, self.height, &self.pixels)
+    }
+}
+
+/// One OAM entry, decoded.
+pub struct SpriteInfo {
+    pub index: usize,
+    pub x: u8,
+    pub y: u8,
+    pub tile: u8,
+    pub palette: u8,
+    pub behind_background: bool,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+}
+
+/// A copy of what the PPU can see at one moment.
+pub struct Snapshot {
+    /// $0000-$1FFF as the board maps it.
+    chr: Vec<u8>,
+    /// $2000-$2FFF, with the board's mirroring applied.
+    nametables: Vec<u8>,
+    palette: [u8; 32],
+    oam: [u8; 256],
+    control: u8,
+    /// The scroll position as `[.yyy NNYY YYYX XXXX]` and fine X.
+    scroll: u16,
+    fine_x: u8,
+    pub scanline: i16,
+    pub cycle: u16,
+}
+
+impl PPU {
+    /// Copies everything the debug views need. Reads go around the board,
+    /// so taking one doesn't disturb mappers that watch the PPU's fetches.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            chr: (0x0000..0x2000).map(|address| self.ppu_peek(address)).collect(),
+            nametables: (0x2000..0x3000).map(|address| self.ppu_peek(address)).collect(),
+            palette: self.palette,
+            oam: self.oam,
+            control: self.control,
+            scroll: self.temp_address,
+            fine_x: self.fine_x,
+            scanline: self.scanline,
+            cycle: self.cycle,
+        }
+    }
+
+    /// Takes a snapshot every frame when the PPU gets to `scanline` and
+    /// `cycle`, or stops with `None`.
+    pub fn set_capture_point(&mut self, point: Option<(i16, u16)>) {
+        self.capture_point = point;
+    }
+
+    /// The snapshot taken at the capture point in the latest frame.
+    pub fn captured(&self) -> Option<&Snapshot> {
+        self.captured.as_ref()
+    }
+
+    pub(super) fn check_capture_point(&mut self) {
+        if self.capture_point == Some((self.scanline, self.cycle)) {
+            self.captured = Some(self.snapshot());
+        }
+    }
+}
+
+impl Snapshot {
+    fn color(&self, entry: usize, colors: &[[u8; 3]]) -> [u8; 3] {
+        let index = match entry & 0x1F {
+            // The sprite palettes' first entries mirror the background's.
+            0x10 | 0x14 | 0x18 | 0x1C => entry & 0x0F,
+            entry => entry,
+        };
+        framebuffer::color(self.palette[index] as u16, colors)
+    }
+
+    /// Pixel (0 to 3) of an 8x8 tile at `address` in CHR.
+    fn tile_pixel(&self, address: usize, x: usize, y: usize) -> usize {
+        let lo = self.chr[(address + y) & 0x1FFF];
+        let hi = self.chr[(address + y + 8) & 0x1FFF];
+        let bit = 7 - x;
+        (((hi >> bit) & 0x01) << 1 | ((lo >> bit) & 0x01)) as usize
+    }
+
+    /// All four nametables in a 512x480 picture, with the screen's scroll
+    /// window outlined.
+    pub fn nametables(&self, colors: &[[u8; 3]]) -> Image {
+        let mut image = Image::new(512, 480);
+        let background_table = if self.control & 0x10 != 0 { 0x1000 } else { 0 };
+
+        for table in 0..4 {
+            let nametable = &self.nametables[table * 0x400..(table + 1) * 0x400];
+            let (left, top) = ((table & 0x01) * 256, (table >> 1) * 240);
+
+            for row in 0..30 {
+                for column in 0..32 {
+                    let tile = nametable[row * 32 + column] as usize;
+                    let attribute = nametable[0x3C0 + (row / 4) * 8 + column / 4];
+                    let shift = ((row & 0x02) << 1) | (column & 0x02);
+                    let palette = ((attribute >> shift) & 0x03) as usize;
+
+                    for y in 0..8 {
+                        for x in 0..8 {
+                            let pixel = self.tile_pixel(background_table + tile * 16, x, y);
+                            let color = if pixel == 0 { 0 } else { palette * 4 + pixel };
+                            image.set(left + column * 8 + x, top + row * 8 + y, self.color(color, colors));
+                        }
+                    }
+                }
+            }
+        }
+
+        self.outline_scroll(&mut image);
+        image
+    }
+
+    fn outline_scroll(&self, image: &mut Image) {
+        let coarse_x = (self.scroll & 0x001F) as usize;
+        let coarse_y = ((self.scroll >> 5) & 0x001F) as usize;
+        let fine_y = ((self.scroll >> 12) & 0x0007) as usize;
+        let nametable = ((self.scroll >> 10) & 0x0003) as usize;
+
+        let left = (nametable & 0x01) * 256 + coarse_x * 8 + self.fine_x as usize;
+        let top = (nametable >> 1) * 240 + coarse_y * 8 + fine_y;
+
+        // The window wraps around the edges like the scroll does.
+        for offset in 0..256 {
+            let x = (left + offset) % 512;
+            image.set(x, top % 480, SCROLL_OUTLINE);
+            image.set(x, (top + 239) % 480, SCROLL_OUTLINE);
+        }
+        for offset in 0..240 {
+            let y = (top + offset) % 480;
+            image.set(left % 512, y, SCROLL_OUTLINE);
+            image.set((left + 255) % 512, y, SCROLL_OUTLINE);
+        }
+    }
+
+    /// Pattern table 0 or 1 as a 128x128 grid of tiles, coloured with one
+    /// of the eight palettes (4 to 7 being the sprite palettes).
+    pub fn pattern_table(&self, table: usize, palette: usize, colors: &[[u8; 3]]) -> Image {
+        let mut image = Image::new(128, 128);
+        let base = (table & 0x01) * 0x1000;
+
+        for tile in 0..256 {
+            let (left, top) = ((tile % 16) * 8, (tile / 16) * 8);
+            for y in 0..8 {
+                for x in 0..8 {
+                    let pixel = self.tile_pixel(base + tile * 16, x, y);
+                    let color = if pixel == 0 { 0 } else { (palette & 0x07) * 4 + pixel };
+                    image.set(left + x, top + y, self.color(color, colors));
+                }
+            }
+        }
+        image
+    }
+
+    /// Decodes every OAM entry.
+    pub fn sprite_info(&self) -> Vec<SpriteInfo> {
+        self.oam
+            .chunks_exact(4)
+            .enumerate()
+            .map(|(index, entry)| SpriteInfo {
+                index,
+                y: entry[0],
+                tile: entry[1],
+                palette: entry[2] & 0x03,
+                behind_background: entry[2] & 0x20 != 0,
+                flip_horizontal: entry[2] & 0x40 != 0,
+                flip_vertical: entry[2] & 0x80 != 0,
+                x: entry[3],
+            })
+            .collect()
+    }
+
+    /// All 64 sprites in an 8x8 grid of 8x16 cells (8x8 sprites use the top
+    /// half), drawn as they would appear, transparent pixels included.
+    pub fn sprites(&self, colors: &[[u8; 3]]) -> Image {
+        let mut image = Image::new(64, 128);
+        let tall = self.control & 0x20 != 0;
+        let height = if tall { 16 } else { 8 };
+
+        for sprite in self.sprite_info() {
+            let (left, top) = ((sprite.index % 8) * 8, (sprite.index / 8) * 16);
+            for y in 0..height {
+                let row = if sprite.flip_vertical { height - 1 - y } else { y };
+                let address = if tall {
+                    let table = (sprite.tile as usize & 0x01) * 0x1000;
+                    table + ((sprite.tile as usize & 0xFE) + row / 8) * 16
+                } else {
+                    let table = if self.control & 0x08 != 0 { 0x1000 } else { 0 };
+                    table + sprite.tile as usize * 16
+                };
+
+                for x in 0..8 {
+                    let column = if sprite.flip_horizontal { 7 - x } else { x };
+                    let pixel = self.tile_pixel(address, column, row & 0x07);
+                    let color = if pixel == 0 { 0 } else { 0x10 + sprite.palette as usize * 4 + pixel };
+                    image.set(left + x, top + y, self.color(color, colors));
+                }
+            }
+        }
+        image
+    }
+
+    /// The 32 palette entries as a 16x2 grid of swatches, backgrounds on
+    /// top and sprites below.
+    pub fn palette(&self, colors: &[[u8; 3]]) -> Image {
+        let mut image = Image::new(16 * SWATCH_SIZE, 2 * SWATCH_SIZE);
+        for y in 0..image.height {
+            for x in 0..image.width {
+                let entry = (y / SWATCH_SIZE) * 16 + x / SWATCH_SIZE;
+                image.set(x, y, self.color(entry, colors));
+            }
+        }
+        image
+    }
+}
@@ -0,0 +1,56 @@
+//! Turning the PPU's output into pixels a frontend can draw.
+//!
+//! Frames hold one `u16` per pixel: the palette colour in bits 0-5 and the
+//! PPUMASK emphasis bits (red, green, blue) in bits 6-8. Colour tables can
+//! have 64 entries, which ignores emphasis, or 512, indexed by all 9 bits.
+
+pub enum PixelFormat {
+    /// Bytes R, G, B, A.
+    Rgba8888,
+    /// Bytes B, G, R, A.
+    Bgra8888,
+    /// Bytes A, R, G, B.
+    Argb8888,
+    /// 5-6-5 bit `u16`s, little endian.
+    Rgb565,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            PixelFormat::Rgb565 => 2,
+            _ => 4,
+        }
+    }
+
+    fn encode(&self, [r, g, b]: [u8; 3], out: &mut [u8]) {
+        match self {
+            PixelFormat::Rgba8888 => out.copy_from_slice(&[r, g, b, 0xFF]),
+            PixelFormat::Bgra8888 => out.copy_from_slice(&[b, g, r, 0xFF]),
+            PixelFormat::Argb8888 => out.copy_from_slice(&[0xFF, r, g, b]),
+            PixelFormat::Rgb565 => {
+                let pixel = ((r as u16 >> 3) << 11) | ((g as u16 >> 2) << 5) | (b as u16 >> 3);
+                out.copy_from_slice(&pixel.to_le_bytes());
+            }
+        }
+    }
+}
+
+/// The colour of one pixel from a 64 or 512 entry table.
+pub fn color(pixel: u16, colors: &[[u8; 3]]) -> [u8; 3] {
+    if colors.len() >= 512 {
+        colors[(pixel & 0x01FF) as usize]
+    } else {
+        colors[(pixel & 0x003F) as usize]
+    }
+}
+
+/// Converts a frame to `format`, replacing the contents of `out`.
+pub fn convert(frame: &[u16], colors: &[[u8; 3]], format: PixelFormat, out: &mut Vec<u8>) {
+    let size = format.bytes_per_pixel();
+    out.resize(frame.len() * size, 0);
+
+    for (&pixel, out) in frame.iter().zip(out.chunks_exact_mut(size)) {
+        format.encode(color(pixel, colors), out);
+    }
+}
@@ -0,0 +1,1183 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::cartridge::{Cartridge, Mirroring, Region, VsPpu};
+
+pub mod debug;
+pub mod framebuffer;
+pub mod ntsc;
+pub mod palette;
+
+use self::palette::Palette;
+
+enum Control {
+    NametableX          = (1 << 0),
+    NametableY          = (1 << 1),
+    IncrementMode       = (1 << 2),
+    PatternSprite       = (1 << 3),
+    PatternBackground   = (1 << 4),
+    SpriteSize          = (1 << 5),
+    SlaveMode           = (1 << 6),
+    EnableNmi           = (1 << 7),
+}
+
+enum Mask {
+    Grayscale           = (1 << 0),
+    ShowBackgroundLeft  = (1 << 1),
+    ShowSpritesLeft     = (1 << 2),
+    ShowBackground      = (1 << 3),
+    ShowSprites         = (1 << 4),
+    EmphasizeRed        = (1 << 5),
+    EmphasizeGreen      = (1 << 6),
+    EmphasizeBlue       = (1 << 7),
+}
+
+enum Status {
+    SpriteOverflow      = (1 << 5),
+    SpriteZeroHit       = (1 << 6),
+    VerticalBlank       = (1 << 7),
+}
+
+pub const DOTS_PER_SCANLINE: u16 = 341;
+
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 240;
+
+/// Sprites the hardware can draw on one scanline.
+const SPRITES_PER_SCANLINE: usize = 8;
+const SPRITE_COUNT: usize = 64;
+
+/// OAM is dynamic RAM that's only refreshed by rendering, and on PAL at
+/// the end of VBlank. A row left alone for about 3000 CPU cycles has lost
+/// its contents.
+const OAM_DECAY_DOTS: u32 = 9000;
+
+/// The CPU side data bus of the PPU holds its last value in the wiring's
+/// capacitance, each bit fading to 0 after about 600 ms unless refreshed.
+const IO_LATCH_DECAY_DOTS: u32 = 3_200_000;
+
+/// Byte 2 of each OAM entry only has the bits `[VPH. ..PP]`.
+const SPRITE_ATTRIBUTE_MASK: u8 = 0xE3;
+
+enum SpriteAttribute {
+    BehindBackground    = (1 << 5),
+    FlipHorizontal      = (1 << 6),
+    FlipVertical        = (1 << 7),
+}
+
+/// The state of the sprite evaluation running over dots 65 to 256.
+#[derive(Clone, Copy, PartialEq)]
+enum Evaluation {
+    /// Looking for the next sprite on the following scanline.
+    Search,
+    /// Copying the other three bytes of a sprite that's in range.
+    Copy,
+    /// Secondary OAM is full; checking for overflow, with the hardware bug
+    /// that moves diagonally through OAM.
+    Overflow,
+    /// Gone through all 64 sprites.
+    Done,
+}
+
+/// The picture processing unit (2C02).
+///
+/// Scanlines are numbered from -1, the pre-render line, to 260 (310 on PAL
+/// and Dendy), so that the visible picture is lines 0 to 239 and VBlank
+/// starts on line 241 (291 on Dendy).
+pub struct PPU {
+    control: u8,
+    mask: u8,
+    status: u8,
+
+    oam_address: u8,
+    oam: [u8; 256],
+    /// The value last read out of OAM, which is what $2004 returns while rendering.
+    oam_bus: u8,
+    /// The dot each 8 byte row of OAM was last accessed, for decay.
+    oam_refreshed: [u32; 32],
+    /// Turning rendering off mid-scanline corrupts this row once it's back on.
+    oam_corrupt_row: Option<usize>,
+
+    /// Sprites found for the next scanline.
+    secondary_oam: [u8; SPRITES_PER_SCANLINE * 4],
+    secondary_oam_address: usize,
+    evaluation: Evaluation,
+    sprite_zero_next: bool,
+    sprite_count_next: usize,
+
+    /// Where in OAM the evaluation for the next line started.
+    evaluation_start: u8,
+
+    /// Sprites drawn on the current scanline. Only the first eight are
+    /// used unless the sprite limit is off.
+    sprite_count: usize,
+    sprite_zero_on_line: bool,
+    sprite_x: [u8; SPRITE_COUNT],
+    sprite_attribute: [u8; SPRITE_COUNT],
+    sprite_pattern_lo: [u8; SPRITE_COUNT],
+    sprite_pattern_hi: [u8; SPRITE_COUNT],
+    /// Draws every sprite on a line rather than the first eight. The CPU
+    /// still sees the overflow flag and timing of the real evaluation.
+    sprite_limit: bool,
+
+    /// Two nametables of CIRAM, plus two more standing in for the extra
+    /// RAM that four screen boards carry on the cartridge.
+    nametables: [u8; 4 * 1024],
+    palette: [u8; 32],
+
+    /// The loopy registers: current and temporary VRAM address, fine X
+    /// scroll and the write toggle shared by $2005 and $2006.
+    vram_address: u16,
+    temp_address: u16,
+    fine_x: u8,
+    write_toggle: bool,
+    data_buffer: u8,
+    /// Open bus on $2000-$2007: the last value written to or read from a
+    /// register, and when each bit was last driven.
+    io_latch: u8,
+    io_latch_refreshed: [u32; 8],
+
+    /// Background tile data fetched for the next 8 pixels.
+    bg_next_tile_id: u8,
+    bg_next_tile_attribute: u8,
+    bg_next_tile_lsb: u8,
+    bg_next_tile_msb: u8,
+    /// The pattern and attribute shift registers; the top byte holds the
+    /// tile being drawn, the low byte the next one.
+    bg_shifter_pattern_lo: u16,
+    bg_shifter_pattern_hi: u16,
+    bg_shifter_attribute_lo: u16,
+    bg_shifter_attribute_hi: u16,
+
+    /// The picture being drawn, and the last finished one. Pixels are
+    /// `[...E EECC CCCC]`: a palette colour plus the emphasis bits of PPUMASK.
+    screen: Vec<u16>,
+    frame: Vec<u16>,
+
+    scanline: i16,
+    cycle: u16,
+    /// Free running dot counter, for timing OAM decay.
+    dots: u32,
+    odd_frame: bool,
+    /// Phase of the colour subcarrier, in twelfths, at the start of the
+    /// current line, of line 0 of this frame, and of the last finished one.
+    color_phase: u8,
+    line_zero_phase: u8,
+    frame_phase: u8,
+    nmi: bool,
+    /// Set when VBlank starts and a new frame is ready.
+    pub frame_complete: bool,
+
+    /// Where to take a snapshot for the debug views each frame.
+    capture_point: Option<(i16, u16)>,
+    captured: Option<debug::Snapshot>,
+
+    region: Region,
+    vs_ppu: Option<VsPpu>,
+    rgb_palette: Option<Palette>,
+    cartridge: Option<Rc<RefCell<Cartridge>>>,
+}
+
+impl PPU {
+    pub fn new() -> Self {
+        Self {
+            control: 0,
+            mask: 0,
+            status: 0,
+            oam_address: 0,
+            oam: [0; 256],
+            oam_bus: 0,
+            oam_refreshed: [0; 32],
+            oam_corrupt_row: None,
+            secondary_oam: [0xFF; SPRITES_PER_SCANLINE * 4],
+            secondary_oam_address: 0,
+            evaluation: Evaluation::Done,
+            sprite_zero_next: false,
+            sprite_count_next: 0,
+            evaluation_start: 0,
+            sprite_count: 0,
+            sprite_zero_on_line: false,
+            sprite_x: [0; SPRITE_COUNT],
+            sprite_attribute: [0; SPRITE_COUNT],
+            sprite_pattern_lo: [0; SPRITE_COUNT],
+            sprite_pattern_hi: [0; SPRITE_COUNT],
+            sprite_limit: true,
+            nametables: [0; 4 * 1024],
+            palette: [0; 32],
+            vram_address: 0,
+            temp_address: 0,
+            fine_x: 0,
+            write_toggle: false,
+            data_buffer: 0,
+            io_latch: 0,
+            io_latch_refreshed: [0; 8],
+            bg_next_tile_id: 0,
+            bg_next_tile_attribute: 0,
+            bg_next_tile_lsb: 0,
+            bg_next_tile_msb: 0,
+            bg_shifter_pattern_lo: 0,
+            bg_shifter_pattern_hi: 0,
+            bg_shifter_attribute_lo: 0,
+            bg_shifter_attribute_hi: 0,
+            screen: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            frame: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            scanline: -1,
+            cycle: 0,
+            dots: 0,
+            odd_frame: false,
+            color_phase: 0,
+            line_zero_phase: 0,
+            frame_phase: 0,
+            nmi: false,
+            frame_complete: false,
+            capture_point: None,
+            captured: None,
+            region: Region::Ntsc,
+            vs_ppu: None,
+            rgb_palette: None,
+            cartridge: None,
+        }
+    }
+
+    pub fn connect_cartridge(&mut self, cartridge: Rc<RefCell<Cartridge>>) {
+        self.cartridge = Some(cartridge);
+    }
+
+    /// Switches between the 2C02 (NTSC), 2C07 (PAL) and the Dendy's UA6538.
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    pub fn scanlines_per_frame(&self) -> i16 {
+        match self.region {
+            Region::Ntsc | Region::Multiple => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    /// The Dendy puts its extra 50 lines before VBlank so that it stays
+    /// 20 lines long, like on NTSC.
+    fn vblank_scanline(&self) -> i16 {
+        match self.region {
+            Region::Dendy => 291,
+            _ => 241,
+        }
+    }
+
+    /// Selects one of the Vs. System RGB PPUs instead of the 2C02, along
+    /// with its colours. The 2C04s' scrambled palettes aren't built in, so
+    /// they start with the 2C03's until `set_rgb_palette` gives the right one.
+    pub fn set_vs_ppu(&mut self, vs_ppu: Option<VsPpu>) {
+        self.vs_ppu = vs_ppu;
+        self.rgb_palette = vs_ppu.map(|_| Palette::rgb_ppu());
+    }
+
+    /// Replaces the RGB PPU's colours, such as with a palette dumped from
+    /// a 2C04 and made with `Palette::rgb`.
+    pub fn set_rgb_palette(&mut self, palette: Palette) {
+        if self.vs_ppu.is_some() {
+            self.rgb_palette = Some(palette);
+        }
+    }
+
+    /// The colours a frontend has to show frames with instead of its own
+    /// palette, when the game runs on an RGB PPU.
+    pub fn rgb_palette(&self) -> Option<&Palette> {
+        self.rgb_palette.as_ref()
+    }
+
+    /// With `enabled` false, lines with more than eight sprites are drawn
+    /// in full instead of flickering.
+    pub fn set_sprite_limit(&mut self, enabled: bool) {
+        self.sprite_limit = enabled;
+    }
+
+    /// The last complete frame, 256x240 pixels row by row. See
+    /// `framebuffer` for turning it into colours.
+    pub fn frame(&self) -> &[u16] {
+        &self.frame
+    }
+
+    /// The colour subcarrier phase (0 to 11) at dot 0 of line 0 of the last
+    /// frame, which the NTSC filter needs for artifacts and dot crawl.
+    pub fn frame_phase(&self) -> u8 {
+        self.frame_phase
+    }
+
+    pub fn scanline(&self) -> i16 {
+        self.scanline
+    }
+
+    pub fn cycle(&self) -> u16 {
+        self.cycle
+    }
+
+    fn get_control(&self, flag: Control) -> bool {
+        (self.control & (flag as u8)) != 0
+    }
+
+    fn get_mask(&self, flag: Mask) -> bool {
+        (self.mask & (flag as u8)) != 0
+    }
+
+    fn set_status(&mut self, flag: Status, value: bool) {
+        if value {
+            self.status |= flag as u8;
+        } else {
+            self.status &= !(flag as u8);
+        }
+    }
+
+    fn rendering_enabled(&self) -> bool {
+        self.get_mask(Mask::ShowBackground) || self.get_mask(Mask::ShowSprites)
+    }
+
+    /// True while the PPU is fetching for the picture, when it owns the VRAM address.
+    fn rendering(&self) -> bool {
+        self.rendering_enabled() && self.scanline < 240
+    }
+
+    /// Returns true once per NMI the CPU should take.
+    pub fn take_nmi(&mut self) -> bool {
+        std::mem::take(&mut self.nmi)
+    }
+
+    /// The open bus value, with the bits that haven't been driven for a
+    /// while faded to 0.
+    fn io_latch(&mut self) -> u8 {
+        for bit in 0..8 {
+            if self.dots.wrapping_sub(self.io_latch_refreshed[bit]) > IO_LATCH_DECAY_DOTS {
+                self.io_latch &= !(1 << bit);
+            }
+        }
+        self.io_latch
+    }
+
+    /// Puts the bits in `mask` of `data` on the open bus.
+    fn drive_io_latch(&mut self, data: u8, mask: u8) {
+        self.io_latch = (self.io_latch & !mask) | (data & mask);
+        for bit in 0..8 {
+            if mask & (1 << bit) != 0 {
+                self.io_latch_refreshed[bit] = self.dots;
+            }
+        }
+    }
+
+    /// Reads one of the eight registers at $2000-$2007. With `read_only`
+    /// set, as debuggers do, nothing is changed by the read.
+    pub fn cpu_read(&mut self, address: u16, read_only: bool) -> u8 {
+        let latch = self.io_latch();
+        match address & 0x0007 {
+            // The low bits are open bus, except on 2C05s which put their ID there.
+            0x0002 => {
+                let low_bits = self
+                    .vs_ppu
+                    .and_then(|vs_ppu| vs_ppu.status_id())
+                    .unwrap_or(latch & 0x1F);
+                let data = (self.status & 0xE0) | low_bits;
+
+                if !read_only {
+                    self.set_status(Status::VerticalBlank, false);
+                    self.write_toggle = false;
+                    self.drive_io_latch(data, 0xE0);
+                }
+                data
+            }
+            0x0004 => {
+                let data = if self.rendering() {
+                    self.oam_bus
+                } else {
+                    self.decay_oam_row(self.oam_address as usize);
+                    self.oam[self.oam_address as usize]
+                };
+                if !read_only {
+                    self.drive_io_latch(data, 0xFF);
+                }
+                data
+            }
+            0x0007 => {
+                if read_only {
+                    return self.data_buffer;
+                }
+
+                // Reads are delayed through a buffer, except for the
+                // palette, which is returned immediately while the buffer
+                // is filled from the nametable "underneath" it. Palette
+                // entries are 6 bits, the top two come from open bus.
+                let address = self.vram_address & 0x3FFF;
+                let data = if address >= 0x3F00 {
+                    let data = (self.ppu_read(address) & 0x3F) | (latch & 0xC0);
+                    self.data_buffer = self.ppu_read(address - 0x1000);
+                    self.drive_io_latch(data, 0x3F);
+                    data
+                } else {
+                    let data = self.data_buffer;
+                    self.data_buffer = self.ppu_read(address);
+                    self.drive_io_latch(data, 0xFF);
+                    data
+                };
+
+                self.advance_vram_address();
+                data
+            }
+            // The rest are write only, and read back as open bus.
+            _ => latch,
+        }
+    }
+
+    pub fn cpu_write(&mut self, address: u16, data: u8) {
+        self.drive_io_latch(data, 0xFF);
+
+        let mut register = address & 0x0007;
+        if self.vs_ppu.is_some_and(|vs_ppu| vs_ppu.swaps_control_registers()) && register < 2 {
+            register ^= 0x0001;
+        }
+
+        match register {
+            0x0000 => {
+                let nmi_was_enabled = self.get_control(Control::EnableNmi);
+                self.control = data;
+                self.temp_address = (self.temp_address & 0xF3FF) | ((data as u16 & 0x03) << 10);
+
+                // Enabling NMI during VBlank fires one straight away.
+                let in_vblank = self.status & (Status::VerticalBlank as u8) != 0;
+                if !nmi_was_enabled && self.get_control(Control::EnableNmi) && in_vblank {
+                    self.nmi = true;
+                }
+            }
+            0x0001 => {
+                let was_rendering = self.rendering();
+                self.mask = data;
+                if was_rendering && !self.rendering_enabled() {
+                    self.oam_corrupt_row = Some((self.oam_address >> 3) as usize);
+                }
+            }
+            0x0003 => self.oam_address = data,
+            0x0004 => {
+                // While rendering the write is lost, and only the sprite
+                // number part of the address moves on.
+                if self.rendering() {
+                    self.oam_address = self.oam_address.wrapping_add(4);
+                    return;
+                }
+
+                let address = self.oam_address as usize;
+                self.decay_oam_row(address);
+                self.oam[address] = if address & 0x03 == 2 { data & SPRITE_ATTRIBUTE_MASK } else { data };
+                self.oam_address = self.oam_address.wrapping_add(1);
+            }
+            0x0005 => {
+                if !self.write_toggle {
+                    self.temp_address = (self.temp_address & !0x001F) | (data >> 3) as u16;
+                    self.fine_x = data & 0x07;
+                } else {
+                    self.temp_address = (self.temp_address & 0x8C1F)
+                        | ((data as u16 & 0x07) << 12)
+                        | ((data as u16 & 0xF8) << 2);
+                }
+                self.write_toggle = !self.write_toggle;
+            }
+            0x0006 => {
+                if !self.write_toggle {
+                    self.temp_address = (self.temp_address & 0x00FF) | ((data as u16 & 0x3F) << 8);
+                } else {
+                    self.temp_address = (self.temp_address & 0xFF00) | data as u16;
+                    self.vram_address = self.temp_address;
+                }
+                self.write_toggle = !self.write_toggle;
+            }
+            0x0007 => {
+                self.ppu_write(self.vram_address & 0x3FFF, data);
+                self.advance_vram_address();
+            }
+            // $2002 is read only.
+            _ => {}
+        }
+    }
+
+    /// Moves on after a $2007 access. While rendering, the address lines are
+    /// busy with tile fetches, and the access bumps coarse X and Y instead.
+    fn advance_vram_address(&mut self) {
+        if self.rendering() {
+            self.increment_scroll_x();
+            self.increment_scroll_y();
+        } else {
+            let step = if self.get_control(Control::IncrementMode) { 32 } else { 1 };
+            self.vram_address = self.vram_address.wrapping_add(step) & 0x7FFF;
+        }
+    }
+
+    // The VRAM address doubles as the scroll position while rendering:
+    // `[.yyy NNYY YYYX XXXX]`, fine Y, nametable, coarse Y and coarse X.
+
+    fn increment_scroll_x(&mut self) {
+        if self.vram_address & 0x001F == 31 {
+            self.vram_address &= !0x001F;
+            self.vram_address ^= 0x0400;
+        } else {
+            self.vram_address += 1;
+        }
+    }
+
+    fn increment_scroll_y(&mut self) {
+        if self.vram_address & 0x7000 != 0x7000 {
+            self.vram_address += 0x1000;
+            return;
+        }
+
+        self.vram_address &= !0x7000;
+        let mut coarse_y = (self.vram_address & 0x03E0) >> 5;
+        if coarse_y == 29 {
+            coarse_y = 0;
+            self.vram_address ^= 0x0800;
+        } else if coarse_y == 31 {
+            // Out of range rows wrap without switching nametables.
+            coarse_y = 0;
+        } else {
+            coarse_y += 1;
+        }
+        self.vram_address = (self.vram_address & !0x03E0) | (coarse_y << 5);
+    }
+
+    fn transfer_address_x(&mut self) {
+        self.vram_address = (self.vram_address & !0x041F) | (self.temp_address & 0x041F);
+    }
+
+    fn transfer_address_y(&mut self) {
+        self.vram_address = (self.vram_address & !0x7BE0) | (self.temp_address & 0x7BE0);
+    }
+
+    fn load_background_shifters(&mut self) {
+        self.bg_shifter_pattern_lo = (self.bg_shifter_pattern_lo & 0xFF00) | self.bg_next_tile_lsb as u16;
+        self.bg_shifter_pattern_hi = (self.bg_shifter_pattern_hi & 0xFF00) | self.bg_next_tile_msb as u16;
+
+        // The attribute applies to the whole tile, so it's spread over all 8 bits.
+        let attribute_lo = if self.bg_next_tile_attribute & 0x01 != 0 { 0xFF } else { 0x00 };
+        let attribute_hi = if self.bg_next_tile_attribute & 0x02 != 0 { 0xFF } else { 0x00 };
+        self.bg_shifter_attribute_lo = (self.bg_shifter_attribute_lo & 0xFF00) | attribute_lo;
+        self.bg_shifter_attribute_hi = (self.bg_shifter_attribute_hi & 0xFF00) | attribute_hi;
+    }
+
+    fn update_shifters(&mut self) {
+        self.bg_shifter_pattern_lo <<= 1;
+        self.bg_shifter_pattern_hi <<= 1;
+        self.bg_shifter_attribute_lo <<= 1;
+        self.bg_shifter_attribute_hi <<= 1;
+    }
+
+    /// One step of the 8 dot fetch pattern: nametable byte, attribute byte,
+    /// then the low and high pattern planes, two dots each.
+    fn fetch_background(&mut self) {
+        match (self.cycle - 1) % 8 {
+            0 => {
+                self.load_background_shifters();
+                self.bg_next_tile_id = self.ppu_read(0x2000 | (self.vram_address & 0x0FFF));
+            }
+            2 => {
+                let v = self.vram_address;
+                let address = 0x23C0 | (v & 0x0C00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07);
+                let mut attribute = self.ppu_read(address);
+                if v & 0x0040 != 0 {
+                    attribute >>= 4;
+                }
+                if v & 0x0002 != 0 {
+                    attribute >>= 2;
+                }
+                self.bg_next_tile_attribute = attribute & 0x03;
+            }
+            4 => self.bg_next_tile_lsb = self.ppu_read(self.background_pattern_address()),
+            6 => self.bg_next_tile_msb = self.ppu_read(self.background_pattern_address() + 8),
+            7 => self.increment_scroll_x(),
+            _ => {}
+        }
+    }
+
+    fn background_pattern_address(&self) -> u16 {
+        let table = if self.get_control(Control::PatternBackground) { 0x1000 } else { 0 };
+        let fine_y = (self.vram_address >> 12) & 0x07;
+        table | ((self.bg_next_tile_id as u16) << 4) | fine_y
+    }
+
+    /// The background pixel (0 to 3) and its palette at screen column `x`.
+    fn background_pixel(&self, x: usize) -> (u8, u8) {
+        if !self.get_mask(Mask::ShowBackground) || (x < 8 && !self.get_mask(Mask::ShowBackgroundLeft)) {
+            return (0, 0);
+        }
+
+        let bit = 0x8000 >> self.fine_x;
+        let pixel = ((self.bg_shifter_pattern_hi & bit != 0) as u8) << 1
+            | (self.bg_shifter_pattern_lo & bit != 0) as u8;
+        let palette = ((self.bg_shifter_attribute_hi & bit != 0) as u8) << 1
+            | (self.bg_shifter_attribute_lo & bit != 0) as u8;
+        (pixel, palette)
+    }
+
+    /// Forgets the row of OAM holding `address` if it hasn't been touched
+    /// for too long, then marks it as refreshed.
+    fn decay_oam_row(&mut self, address: usize) {
+        let row = address >> 3;
+        if self.dots.wrapping_sub(self.oam_refreshed[row]) > OAM_DECAY_DOTS {
+            self.oam[row * 8..row * 8 + 8].fill(0x10);
+        }
+        self.oam_refreshed[row] = self.dots;
+    }
+
+    fn sprite_height(&self) -> i16 {
+        if self.get_control(Control::SpriteSize) { 16 } else { 8 }
+    }
+
+    fn sprite_in_range(&self, y: u8) -> bool {
+        let row = self.scanline - y as i16;
+        (0..self.sprite_height()).contains(&row)
+    }
+
+    /// Dots 1 to 256 of a visible line: clear secondary OAM, then go through
+    /// OAM for the sprites on the next line, reading on odd dots and writing
+    /// on even ones.
+    fn evaluate_sprites(&mut self) {
+        match self.cycle {
+            1..=64 => {
+                self.oam_bus = 0xFF;
+                if self.cycle & 0x01 == 0 {
+                    self.secondary_oam[(self.cycle as usize / 2 - 1) & 0x1F] = 0xFF;
+                }
+                if self.cycle == 64 {
+                    self.secondary_oam_address = 0;
+                    self.evaluation_start = self.oam_address;
+                    self.evaluation = Evaluation::Search;
+                    self.sprite_zero_next = false;
+                    // Rendering refreshes every row, but rows left alone
+                    // since rendering was last on have already decayed.
+                    for row in 0..self.oam_refreshed.len() {
+                        self.decay_oam_row(row * 8);
+                    }
+                }
+            }
+            65..=256 if self.cycle & 0x01 == 1 => {
+                self.oam_bus = self.oam[self.oam_address as usize];
+            }
+            65..=256 => self.evaluation_step(),
+            _ => {}
+        }
+
+        if self.cycle == 256 {
+            self.sprite_count_next = self.secondary_oam_address / 4;
+        }
+    }
+
+    fn evaluation_step(&mut self) {
+        let data = self.oam_bus;
+        let secondary_full = self.secondary_oam_address >= self.secondary_oam.len();
+
+        match self.evaluation {
+            Evaluation::Search if !secondary_full => {
+                self.secondary_oam[self.secondary_oam_address] = data;
+                if self.sprite_in_range(data) {
+                    // Whichever sprite is checked first acts as sprite 0,
+                    // which is only sprite 0 if OAMADDR started at zero.
+                    if self.cycle == 66 {
+                        self.sprite_zero_next = true;
+                    }
+                    self.secondary_oam_address += 1;
+                    self.oam_address = self.oam_address.wrapping_add(1);
+                    self.evaluation = Evaluation::Copy;
+                } else {
+                    self.next_sprite();
+                }
+            }
+            Evaluation::Search => {
+                self.evaluation = Evaluation::Overflow;
+                self.evaluation_step();
+            }
+            Evaluation::Copy => {
+                self.secondary_oam[self.secondary_oam_address] = data;
+                self.secondary_oam_address += 1;
+                self.oam_address = self.oam_address.wrapping_add(1);
+                if self.oam_address & 0x03 == 0 {
+                    self.evaluation = if self.oam_address == 0 { Evaluation::Done } else { Evaluation::Search };
+                }
+            }
+            Evaluation::Overflow => {
+                if self.sprite_in_range(data) {
+                    self.set_status(Status::SpriteOverflow, true);
+                    self.evaluation = Evaluation::Done;
+                } else {
+                    // The bug: the byte index goes up along with the sprite
+                    // number, so the wrong bytes get compared as Y.
+                    let sprite = (self.oam_address & 0xFC).wrapping_add(4);
+                    let byte = self.oam_address.wrapping_add(1) & 0x03;
+                    self.oam_address = sprite | byte;
+                    if sprite == 0 {
+                        self.evaluation = Evaluation::Done;
+                    }
+                }
+            }
+            Evaluation::Done => {
+                self.oam_address = self.oam_address.wrapping_add(4) & 0xFC;
+            }
+        }
+    }
+
+    fn next_sprite(&mut self) {
+        self.oam_address = self.oam_address.wrapping_add(4) & 0xFC;
+        if self.oam_address == 0 {
+            self.evaluation = Evaluation::Done;
+        }
+    }
+
+    /// Dots 257 to 320: eight fetches of four dots' worth of garbage
+    /// nametable reads plus the two pattern planes of each sprite found.
+    fn fetch_sprites(&mut self) {
+        self.oam_address = 0;
+
+        let slot = (self.cycle - 257) as usize / 8;
+        let step = (self.cycle - 257) % 8;
+        let entry = &self.secondary_oam[slot * 4..slot * 4 + 4];
+        let (y, tile, attribute, x) = (entry[0], entry[1], entry[2], entry[3]);
+        self.oam_bus = entry[(step as usize).min(3)];
+
+        match step {
+            0 | 2 => {
+                self.ppu_read(0x2000 | (self.vram_address & 0x0FFF));
+            }
+            4 | 6 => {
+                let address = self.sprite_pattern_address(y, tile, attribute) + if step == 6 { 8 } else { 0 };
+                let mut data = self.ppu_read(address);
+
+                // Empty slots still fetch tile $FF but are drawn transparent.
+                if slot >= self.sprite_count_next {
+                    data = 0;
+                } else if attribute & (SpriteAttribute::FlipHorizontal as u8) != 0 {
+                    data = data.reverse_bits();
+                }
+
+                if step == 4 {
+                    self.sprite_pattern_lo[slot] = data;
+                } else {
+                    self.sprite_pattern_hi[slot] = data;
+                    self.sprite_x[slot] = x;
+                    self.sprite_attribute[slot] = attribute;
+                }
+            }
+            _ => {}
+        }
+
+        if self.cycle == 320 {
+            self.sprite_count = self.sprite_count_next;
+            self.sprite_zero_on_line = self.sprite_zero_next;
+
+            if !self.sprite_limit && self.sprite_count == SPRITES_PER_SCANLINE && self.scanline >= 0 {
+                self.fetch_extra_sprites();
+            }
+        }
+    }
+
+    /// Finds the sprites the evaluation dropped after the first eight and
+    /// loads them behind the others, without any fetches the board could see.
+    fn fetch_extra_sprites(&mut self) {
+        let start = (self.evaluation_start >> 2) as usize;
+        let extra: Vec<usize> = (0..SPRITE_COUNT)
+            .map(|n| (start + n) % SPRITE_COUNT)
+            .filter(|&sprite| self.sprite_in_range(self.oam[sprite * 4]))
+            .skip(SPRITES_PER_SCANLINE)
+            .collect();
+
+        for sprite in extra {
+            let entry = &self.oam[sprite * 4..sprite * 4 + 4];
+            let (y, tile, attribute, x) = (entry[0], entry[1], entry[2], entry[3]);
+            let address = self.sprite_pattern_address(y, tile, attribute);
+            let (mut lo, mut hi) = (self.ppu_peek(address), self.ppu_peek(address + 8));
+            if attribute & (SpriteAttribute::FlipHorizontal as u8) != 0 {
+                lo = lo.reverse_bits();
+                hi = hi.reverse_bits();
+            }
+
+            let slot = self.sprite_count;
+            self.sprite_x[slot] = x;
+            self.sprite_attribute[slot] = attribute;
+            self.sprite_pattern_lo[slot] = lo;
+            self.sprite_pattern_hi[slot] = hi;
+            self.sprite_count += 1;
+        }
+    }
+
+    fn sprite_pattern_address(&self, y: u8, tile: u8, attribute: u8) -> u16 {
+        let height = self.sprite_height() as u16;
+        let mut row = (self.scanline as u16).wrapping_sub(y as u16) & (height - 1);
+        if attribute & (SpriteAttribute::FlipVertical as u8) != 0 {
+            row = height - 1 - row;
+        }
+
+        if height == 16 {
+            // Bit 0 of the tile number picks the table for 8x16 sprites.
+            let table = (tile as u16 & 0x01) << 12;
+            let tile = (tile & 0xFE) as u16 + (row >> 3);
+            table | (tile << 4) | (row & 0x07)
+        } else {
+            let table = if self.get_control(Control::PatternSprite) { 0x1000 } else { 0 };
+            table | ((tile as u16) << 4) | row
+        }
+    }
+
+    /// The first opaque sprite pixel at column `x`: pixel, palette, whether
+    /// it goes behind the background, and whether it belongs to sprite 0.
+    fn sprite_pixel(&self, x: usize) -> Option<(u8, u8, bool, bool)> {
+        if !self.get_mask(Mask::ShowSprites) || (x < 8 && !self.get_mask(Mask::ShowSpritesLeft)) {
+            return None;
+        }
+
+        (0..self.sprite_count).find_map(|slot| {
+            let column = x.wrapping_sub(self.sprite_x[slot] as usize);
+            if column >= 8 {
+                return None;
+            }
+
+            let bit = 0x80 >> column;
+            let pixel = ((self.sprite_pattern_hi[slot] & bit != 0) as u8) << 1
+                | (self.sprite_pattern_lo[slot] & bit != 0) as u8;
+            if pixel == 0 {
+                return None;
+            }
+
+            let attribute = self.sprite_attribute[slot];
+            let behind = attribute & (SpriteAttribute::BehindBackground as u8) != 0;
+            Some((pixel, attribute & 0x03, behind, slot == 0 && self.sprite_zero_on_line))
+        })
+    }
+
+    fn render_pixel(&mut self) {
+        let x = (self.cycle - 1) as usize;
+        let y = self.scanline as usize;
+
+        let address = if self.rendering_enabled() {
+            let (bg_pixel, bg_palette) = self.background_pixel(x);
+            let background = 0x3F00 | ((bg_palette as u16) << 2) | bg_pixel as u16;
+
+            match self.sprite_pixel(x) {
+                Some((pixel, palette, behind, sprite_zero)) => {
+                    // Sprite 0 hit needs both pixels opaque, and never
+                    // happens at x = 255.
+                    if sprite_zero && bg_pixel != 0 && x != 255 {
+                        self.set_status(Status::SpriteZeroHit, true);
+                    }
+
+                    if behind && bg_pixel != 0 {
+                        background
+                    } else {
+                        0x3F10 | ((palette as u16) << 2) | pixel as u16
+                    }
+                }
+                None if bg_pixel == 0 => 0x3F00,
+                None => background,
+            }
+        } else if self.vram_address & 0x3F00 == 0x3F00 {
+            // With rendering off, pointing the VRAM address at the palette
+            // shows that colour instead of the backdrop.
+            self.vram_address & 0x3FFF
+        } else {
+            0x3F00
+        };
+
+        let mut emphasis = ((self.mask & 0xE0) as u16) << 1;
+        if matches!(self.region, Region::Pal | Region::Dendy) {
+            // Red and green are the other way round on PAL PPUs.
+            emphasis = (emphasis & 0x100) | ((emphasis & 0x40) << 1) | ((emphasis & 0x80) >> 1);
+        }
+        self.screen[y * SCREEN_WIDTH + x] = (self.ppu_read(address) & 0x3F) as u16 | emphasis;
+    }
+
+    /// Maps $2000-$3EFF onto the nametable RAM according to the board's mirroring.
+    fn nametable_index(&self, address: u16, mirroring: Mirroring) -> usize {
+        let address = (address & 0x0FFF) as usize;
+        let table = address >> 10;
+        let offset = address & 0x03FF;
+
+        let page = match mirroring {
+            Mirroring::Horizontal => table >> 1,
+            Mirroring::Vertical => table & 0x01,
+            Mirroring::SingleScreenLow => 0,
+            Mirroring::SingleScreenHigh => 1,
+            Mirroring::FourScreen => table,
+        };
+
+        (page << 10) | offset
+    }
+
+    /// $3F10/$3F14/$3F18/$3F1C are mirrors of $3F00/$3F04/$3F08/$3F0C.
+    fn palette_index(address: u16) -> usize {
+        let mut index = (address & 0x001F) as usize;
+        if index & 0x13 == 0x10 {
+            index &= !0x10;
+        }
+        index
+    }
+
+    pub fn ppu_read(&mut self, address: u16) -> u8 {
+        let address = address & 0x3FFF;
+
+        if address < 0x3F00 {
+            if let Some(cartridge) = &self.cartridge {
+                let mut cartridge = cartridge.borrow_mut();
+                if let Some(data) = cartridge.ppu_read(address) {
+                    return data;
+                }
+                if address >= 0x2000 {
+                    let index = self.nametable_index(address, cartridge.mirroring());
+                    return self.nametables[index];
+                }
+            }
+            return 0;
+        }
+
+        let data = self.palette[Self::palette_index(address)];
+        if self.get_mask(Mask::Grayscale) {
+            data & 0x30
+        } else {
+            data
+        }
+    }
+
+    /// Like `ppu_read`, but the board doesn't see the access.
+    pub fn ppu_peek(&self, address: u16) -> u8 {
+        let address = address & 0x3FFF;
+
+        if address < 0x3F00 {
+            if let Some(cartridge) = &self.cartridge {
+                let mut cartridge = cartridge.borrow_mut();
+                if let Some(data) = cartridge.ppu_peek(address) {
+                    return data;
+                }
+                if address >= 0x2000 {
+                    return self.nametables[self.nametable_index(address, cartridge.mirroring())];
+                }
+            }
+            return 0;
+        }
+
+        self.palette[Self::palette_index(address)]
+    }
+
+    pub fn ppu_write(&mut self, address: u16, data: u8) {
+        let address = address & 0x3FFF;
+
+        if address < 0x3F00 {
+            if let Some(cartridge) = &self.cartridge {
+                let mut cartridge = cartridge.borrow_mut();
+                if !cartridge.ppu_write(address, data) && address >= 0x2000 {
+                    let index = self.nametable_index(address, cartridge.mirroring());
+                    self.nametables[index] = data;
+                }
+            }
+            return;
+        }
+
+        self.palette[Self::palette_index(address)] = data & 0x3F;
+    }
+
+    /// Advances by one dot.
+    pub fn clock(&mut self) {
+        if self.capture_point.is_some() {
+            self.check_capture_point();
+        }
+
+        if self.scanline < 240 && self.rendering_enabled() {
+            if self.cycle == 1 {
+                if let Some(row) = self.oam_corrupt_row.take() {
+                    self.oam.copy_within(0..8, row * 8);
+                }
+            }
+
+            if self.scanline >= 0 && (1..=256).contains(&self.cycle) {
+                self.evaluate_sprites();
+            }
+            if (257..=320).contains(&self.cycle) {
+                self.fetch_sprites();
+            }
+
+            match self.cycle {
+                2..=257 | 321..=337 => {
+                    self.update_shifters();
+                    self.fetch_background();
+                }
+                // Unused nametable fetches, which some mappers count.
+                338 | 340 => {
+                    self.bg_next_tile_id = self.ppu_read(0x2000 | (self.vram_address & 0x0FFF));
+                }
+                _ => {}
+            }
+
+            if self.cycle == 256 {
+                self.increment_scroll_y();
+            }
+            if self.cycle == 257 {
+                self.transfer_address_x();
+            }
+            if self.scanline == -1 && (280..=304).contains(&self.cycle) {
+                self.transfer_address_y();
+            }
+        }
+
+        if (0..240).contains(&self.scanline) && (1..=256).contains(&self.cycle) {
+            self.render_pixel();
+        }
+
+        if self.scanline == -1 && self.cycle == 1 {
+            self.set_status(Status::VerticalBlank, false);
+            self.set_status(Status::SpriteZeroHit, false);
+            self.set_status(Status::SpriteOverflow, false);
+
+            // Nothing is evaluated on the pre-render line, so line 0 has no sprites.
+            self.sprite_count_next = 0;
+            self.sprite_zero_next = false;
+
+            // VBlank is too long on the 2C07 for OAM to last through it, so
+            // it refreshes OAM itself for the last part of it.
+            if self.region == Region::Pal {
+                self.oam_refreshed.fill(self.dots);
+            }
+        }
+
+        if self.scanline == self.vblank_scanline() && self.cycle == 1 {
+            std::mem::swap(&mut self.screen, &mut self.frame);
+            self.frame_phase = self.line_zero_phase;
+            self.frame_complete = true;
+
+            self.set_status(Status::VerticalBlank, true);
+            if self.get_control(Control::EnableNmi) {
+                self.nmi = true;
+            }
+        }
+
+        // With rendering on, odd frames skip the last dot of the pre-render
+        // line. PAL PPUs always draw the whole line.
+        let mut line_dots = DOTS_PER_SCANLINE;
+        let skips_dot = matches!(self.region, Region::Ntsc | Region::Multiple);
+        if skips_dot && self.scanline == -1 && self.cycle == 339 && self.odd_frame && self.rendering_enabled() {
+            self.cycle = 340;
+            line_dots -= 1;
+        }
+
+        self.dots = self.dots.wrapping_add(1);
+        self.cycle += 1;
+        if self.cycle >= DOTS_PER_SCANLINE {
+            self.cycle = 0;
+            self.scanline += 1;
+
+            // Each dot is 8 of the 12 steps of the subcarrier.
+            self.color_phase = ((self.color_phase as u16 + line_dots * 8) % 12) as u8;
+            if self.scanline == 0 {
+                self.line_zero_phase = self.color_phase;
+            }
+
+            if self.scanline >= self.scanlines_per_frame() - 1 {
+                self.scanline = -1;
+                self.odd_frame = !self.odd_frame;
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.control = 0;
+        self.mask = 0;
+        self.write_toggle = false;
+        self.data_buffer = 0;
+        self.fine_x = 0;
+        self.temp_address = 0;
+        self.scanline = -1;
+        self.cycle = 0;
+        self.odd_frame = false;
+        self.nmi = false;
+        self.frame_complete = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes OAM at the start of VBlank, as an NMI handler would, turns
+    /// rendering on and runs to the middle of line 0.
+    fn oam_after_vblank(region: Region) -> Vec<u8> {
+        let mut ppu = PPU::new();
+        ppu.set_region(region);
+        ppu.scanline = 241;
+        ppu.cycle = 0;
+
+        ppu.cpu_write(0x2003, 0x00);
+        for i in 0..8 {
+            ppu.cpu_write(0x2004, 0x20 + i);
+        }
+        ppu.cpu_write(0x2001, 0x18);
+
+        while ppu.scanline != 0 || ppu.cycle < 100 {
+            ppu.clock();
+        }
+        ppu.oam[0..8].to_vec()
+    }
+
+    #[test]
+    fn oam_written_in_vblank_survives_to_line_0() {
+        let written = [0x20, 0x21, 0x22 & SPRITE_ATTRIBUTE_MASK, 0x23, 0x24, 0x25, 0x26 & SPRITE_ATTRIBUTE_MASK, 0x27];
+        assert_eq!(oam_after_vblank(Region::Ntsc), written);
+        assert_eq!(oam_after_vblank(Region::Pal), written);
+    }
+
+    /// Evaluates sprites on line 10 with sprites 0-7 on it and `oam`
+    /// written from sprite 8 on, returning whether overflow was flagged.
+    fn overflow_after(oam: &[u8]) -> bool {
+        let mut ppu = PPU::new();
+        ppu.oam.fill(0xFF);
+        for sprite in 0..8 {
+            ppu.oam[sprite * 4] = 10;
+        }
+        ppu.oam[32..32 + oam.len()].copy_from_slice(oam);
+        ppu.mask = 0x18;
+        ppu.scanline = 10;
+        ppu.cycle = 1;
+
+        while ppu.cycle <= 256 {
+            ppu.clock();
+        }
+        ppu.status & Status::SpriteOverflow as u8 != 0
+    }
+
+    #[test]
+    fn sprite_overflow_scans_oam_diagonally() {
+        // Sprite 8 is off the line, but once it's been checked the tile
+        // number of sprite 9 is taken for its Y.
+        assert!(overflow_after(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 10]));
+        // Sprite 9 is on the line, but by then it's the tile number being checked.
+        assert!(!overflow_after(&[0xFF, 0xFF, 0xFF, 0xFF, 10, 0xFF]));
+        // With the first check it's a real ninth sprite.
+        assert!(overflow_after(&[10]));
+    }
+
+    #[test]
+    fn sprite_palette_backdrops_mirror_the_background_ones() {
+        let mut ppu = PPU::new();
+        for (i, address) in [0x3F10, 0x3F14, 0x3F18, 0x3F1C].into_iter().enumerate() {
+            ppu.ppu_write(address, 0x20 + i as u8);
+            assert_eq!(ppu.ppu_read(address & 0x3F0F), 0x20 + i as u8);
+        }
+
+        ppu.ppu_write(0x3F01, 0x01);
+        ppu.ppu_write(0x3F11, 0x11);
+        assert_eq!(ppu.ppu_read(0x3F01), 0x01);
+        assert_eq!(ppu.ppu_read(0x3F31), 0x11);
+    }
+
+    #[test]
+    fn rc2c05_swaps_control_and_mask() {
+        let mut ppu = PPU::new();
+        ppu.set_vs_ppu(Some(VsPpu::Rc2C05(1)));
+        ppu.cpu_write(0x2000, 0x1E);
+        ppu.cpu_write(0x2001, 0x80);
+        assert_eq!((ppu.control, ppu.mask), (0x80, 0x1E));
+
+        ppu.set_vs_ppu(Some(VsPpu::Rp2C03));
+        ppu.cpu_write(0x2000, 0x00);
+        ppu.cpu_write(0x2001, 0x00);
+        ppu.cpu_write(0x2000, 0x80);
+        assert_eq!((ppu.control, ppu.mask), (0x80, 0x00));
+    }
+}
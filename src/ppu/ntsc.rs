@@ -0,0 +1,172 @@
+//! A software NTSC filter in the spirit of blargg's nes_ntsc.
+//!
+//! Each line of PPU pixels is turned back into the composite signal the
+//! 2C02 would put out, 8 samples per pixel at 12 samples per cycle of the
+//! colour subcarrier, and then decoded the way a TV would. Luma and chroma
+//! share the signal, so sharp edges bleed into colour (artifacts), and the
+//! subcarrier phase moving from line to line and frame to frame makes the
+//! pattern crawl.
+
+use super::palette::{self, NtscSettings, Palette};
+use super::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// Width of the filtered picture.
+pub const NTSC_WIDTH: usize = 602;
+
+const SAMPLES_PER_PIXEL: usize = 8;
+const SAMPLES_PER_LINE: usize = SCREEN_WIDTH * SAMPLES_PER_PIXEL;
+
+/// Samples per cycle of the colour subcarrier.
+const SUBCARRIER_SAMPLES: usize = 12;
+
+/// Samples after dot 0 of a line where the first visible pixel starts.
+const PICTURE_OFFSET: usize = SAMPLES_PER_PIXEL;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum NtscPreset {
+    /// Everything through one wire: artifact colours and dot crawl.
+    Composite,
+    /// Separate luma and chroma: sharp, with no artifacts, but colour is
+    /// still band limited.
+    SVideo,
+    /// No signal at all, just the palette stretched to the same width.
+    Rgb,
+    /// A black and white set showing the chroma as a fine pattern.
+    Monochrome,
+}
+
+impl NtscPreset {
+    /// Width in samples of the luma filter, a box that cancels chroma
+    /// completely at 12. Anything narrower is sharper but lets the chroma
+    /// through as a pattern.
+    fn luma_width(&self) -> usize {
+        match self {
+            NtscPreset::Composite => 12,
+            NtscPreset::SVideo => 4,
+            NtscPreset::Rgb => 1,
+            NtscPreset::Monochrome => 6,
+        }
+    }
+}
+
+pub struct NtscFilter {
+    preset: NtscPreset,
+    settings: NtscSettings,
+    /// The signal of each 9 bit pixel at each subcarrier phase.
+    signal: Vec<[f32; SUBCARRIER_SAMPLES]>,
+    /// The luma part of each pixel's signal, what an S-Video cable carries on its own.
+    luma: Vec<f32>,
+    cos: [f32; SUBCARRIER_SAMPLES],
+    sin: [f32; SUBCARRIER_SAMPLES],
+    palette: Palette,
+
+    samples: Vec<f32>,
+    luma_samples: Vec<f32>,
+}
+
+impl NtscFilter {
+    pub fn new(preset: NtscPreset) -> Self {
+        Self::with_settings(preset, NtscSettings::default())
+    }
+
+    pub fn with_settings(preset: NtscPreset, settings: NtscSettings) -> Self {
+        let signal: Vec<[f32; SUBCARRIER_SAMPLES]> = (0..512)
+            .map(|pixel| std::array::from_fn(|phase| palette::composite_signal(pixel, phase)))
+            .collect();
+        let luma = signal
+            .iter()
+            .map(|samples| samples.iter().sum::<f32>() / SUBCARRIER_SAMPLES as f32)
+            .collect();
+
+        Self {
+            preset,
+            settings,
+            signal,
+            luma,
+            cos: std::array::from_fn(|phase| palette::subcarrier_angle(phase, &settings).cos()),
+            sin: std::array::from_fn(|phase| palette::subcarrier_angle(phase, &settings).sin()),
+            palette: Palette::generate(&settings),
+            samples: vec![0.0; SAMPLES_PER_LINE],
+            luma_samples: vec![0.0; SAMPLES_PER_LINE],
+        }
+    }
+
+    pub fn preset(&self) -> NtscPreset {
+        self.preset
+    }
+
+    /// Filters a frame from `PPU::frame`, `phase` being `PPU::frame_phase`,
+    /// into `NTSC_WIDTH` x 240 pixels of 3 byte RGB.
+    pub fn render(&mut self, frame: &[u16], phase: u8, out: &mut Vec<u8>) {
+        out.resize(NTSC_WIDTH * SCREEN_HEIGHT * 3, 0);
+
+        for (line, pixels) in frame.chunks_exact(SCREEN_WIDTH).enumerate() {
+            let out = &mut out[line * NTSC_WIDTH * 3..(line + 1) * NTSC_WIDTH * 3];
+
+            if self.preset == NtscPreset::Rgb {
+                self.render_rgb_line(pixels, out);
+                continue;
+            }
+
+            // Lines are 341 * 8 samples long, moving the phase on by 4 each line.
+            let line_phase = phase as usize + line * 4 + PICTURE_OFFSET;
+            for (sample, (value, luma)) in self.samples.iter_mut().zip(self.luma_samples.iter_mut()).enumerate() {
+                let pixel = (pixels[sample / SAMPLES_PER_PIXEL] & 0x01FF) as usize;
+                *value = self.signal[pixel][(line_phase + sample) % SUBCARRIER_SAMPLES];
+                *luma = self.luma[pixel];
+            }
+
+            for (x, rgb) in out.chunks_exact_mut(3).enumerate() {
+                let center = (2 * x + 1) * SAMPLES_PER_LINE / (2 * NTSC_WIDTH);
+                rgb.copy_from_slice(&self.decode(center, line_phase));
+            }
+        }
+    }
+
+    /// Decodes the picture around one sample into RGB.
+    fn decode(&self, center: usize, line_phase: usize) -> [u8; 3] {
+        // Kept inside the line so the edges still see whole cycles.
+        let window = |width: usize| {
+            let start = center.saturating_sub(width / 2).min(SAMPLES_PER_LINE - width);
+            start..start + width
+        };
+
+        let luma_window = window(self.preset.luma_width());
+        let y = match self.preset {
+            NtscPreset::SVideo => &self.luma_samples[luma_window.clone()],
+            _ => &self.samples[luma_window.clone()],
+        }
+        .iter()
+        .sum::<f32>()
+            / luma_window.len() as f32;
+
+        if self.preset == NtscPreset::Monochrome {
+            return palette::yiq_to_rgb(y, 0.0, 0.0, &self.settings);
+        }
+
+        // Chroma is demodulated over one cycle of the subcarrier. Through
+        // S-Video the luma is taken off first, so edges can't leak into it.
+        let chroma_window = window(SUBCARRIER_SAMPLES);
+        let (mut i, mut q) = (0.0, 0.0);
+        for sample in chroma_window.clone() {
+            let mut value = self.samples[sample];
+            if self.preset == NtscPreset::SVideo {
+                value -= self.luma_samples[sample];
+            }
+            let phase = (line_phase + sample) % SUBCARRIER_SAMPLES;
+            i += value * self.cos[phase];
+            q += value * self.sin[phase];
+        }
+        let length = chroma_window.len() as f32;
+
+        palette::yiq_to_rgb(y, i / length, q / length, &self.settings)
+    }
+
+    fn render_rgb_line(&self, pixels: &[u16], out: &mut [u8]) {
+        let colors = self.palette.colors();
+        for (x, rgb) in out.chunks_exact_mut(3).enumerate() {
+            let pixel = pixels[x * SCREEN_WIDTH / NTSC_WIDTH];
+            rgb.copy_from_slice(&colors[(pixel & 0x01FF) as usize]);
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use std::f32::consts::PI;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Colours for every 9 bit pixel the PPU outputs: 64 palette colours times
+/// the 8 combinations of emphasis bits.
+const COLOR_COUNT: usize = 512;
+
+/// How much an emphasis bit darkens the colours it doesn't emphasise.
+const EMPHASIS_ATTENUATION: f32 = 0.746;
+
+/// 2C02 output voltages for the four luma levels, low then high.
+const SIGNAL_LEVELS: [f32; 8] = [0.350, 0.518, 0.962, 1.550, 1.094, 1.506, 1.962, 1.962];
+const SIGNAL_BLACK: f32 = 0.518;
+const SIGNAL_WHITE: f32 = 1.962;
+
+/// Lines up colour $x6 with red when the hue setting is zero.
+const HUE_PHASE: f32 = 3.9;
+
+/// The 2C03's colours as 3 bit red, green and blue digits, which the 2C05
+/// shares. The 2C04s output the same colours in a scrambled order.
+const RGB_PPU_COLORS: [u16; 64] = [
+    0o333, 0o014, 0o006, 0o326, 0o403, 0o503, 0o510, 0o420, 0o320, 0o120, 0o031, 0o040, 0o022, 0o000, 0o000, 0o000,
+    0o555, 0o036, 0o027, 0o407, 0o507, 0o704, 0o700, 0o630, 0o430, 0o140, 0o040, 0o053, 0o044, 0o000, 0o000, 0o000,
+    0o777, 0o357, 0o447, 0o637, 0o707, 0o737, 0o740, 0o750, 0o660, 0o360, 0o070, 0o276, 0o077, 0o000, 0o000, 0o000,
+    0o777, 0o567, 0o657, 0o757, 0o747, 0o755, 0o764, 0o772, 0o773, 0o572, 0o473, 0o276, 0o467, 0o000, 0o000, 0o000,
+];
+
+#[derive(Debug)]
+pub enum PaletteError {
+    Io(io::Error),
+    /// `.pal` files have 64 or 512 colours of 3 bytes each.
+    InvalidSize(usize),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaletteError::Io(error) => write!(f, "{}", error),
+            PaletteError::InvalidSize(size) => write!(f, "palette is {} bytes, expected 192 or 1536", size),
+        }
+    }
+}
+
+impl From<io::Error> for PaletteError {
+    fn from(error: io::Error) -> Self {
+        PaletteError::Io(error)
+    }
+}
+
+/// Settings for generating a palette from the 2C02's composite signal.
+#[derive(Clone, Copy)]
+pub struct NtscSettings {
+    /// Hue rotation in degrees.
+    pub hue: f32,
+    pub saturation: f32,
+    pub contrast: f32,
+    /// Added to every channel, 0.0 leaves it alone.
+    pub brightness: f32,
+    /// Gamma of the display the colours were tuned on.
+    pub gamma: f32,
+}
+
+impl Default for NtscSettings {
+    fn default() -> Self {
+        Self {
+            hue: 0.0,
+            saturation: 1.0,
+            contrast: 1.0,
+            brightness: 0.0,
+            gamma: 1.8,
+        }
+    }
+}
+
+/// The palettes available without a `.pal` file: presets for the
+/// generator, and the RGB PPU's fixed colours.
+#[derive(Clone, Copy)]
+pub enum Builtin {
+    /// The generator's defaults.
+    Composite,
+    /// More saturated and contrasty, closer to what a CRT set up for games shows.
+    Vivid,
+    /// Less saturated, with a gamma of 2.2.
+    Natural,
+    /// The 2C03's table, as on a PlayChoice-10 or an RGB modded console.
+    Rgb,
+}
+
+impl Builtin {
+    fn settings(&self) -> Option<NtscSettings> {
+        match self {
+            Builtin::Composite => Some(NtscSettings::default()),
+            Builtin::Vivid => Some(NtscSettings { saturation: 1.3, contrast: 1.1, ..Default::default() }),
+            Builtin::Natural => Some(NtscSettings { saturation: 0.8, gamma: 2.2, ..Default::default() }),
+            Builtin::Rgb => None,
+        }
+    }
+}
+
+/// `RGB_PPU_COLORS` stretched to 8 bits per channel.
+fn rgb_ppu_colors() -> [[u8; 3]; 64] {
+    RGB_PPU_COLORS.map(|rgb| [rgb >> 6, rgb >> 3, rgb].map(|level| ((level & 0x07) * 255 / 7) as u8))
+}
+
+pub struct Palette {
+    colors: Vec<[u8; 3]>,
+}
+
+impl Palette {
+    /// Makes a palette from 64 colours, working out the emphasised versions.
+    pub fn new(colors: &[[u8; 3]; 64]) -> Self {
+        let colors = (0..COLOR_COUNT)
+            .map(|pixel| {
+                let [r, g, b] = colors[pixel & 0x3F];
+                let emphasis = pixel >> 6;
+                let mut channels = [r as f32, g as f32, b as f32];
+
+                // Each emphasis bit darkens the other two channels.
+                for (channel, value) in channels.iter_mut().enumerate() {
+                    for bit in 0..3 {
+                        if emphasis & (1 << bit) != 0 && bit != channel {
+                            *value *= EMPHASIS_ATTENUATION;
+                        }
+                    }
+                }
+                channels.map(|value| value.round() as u8)
+            })
+            .collect();
+
+        Self { colors }
+    }
+
+    /// Makes a palette from all 512 colours, emphasised versions included.
+    pub fn with_emphasis(colors: &[[u8; 3]; COLOR_COUNT]) -> Self {
+        Self { colors: colors.to_vec() }
+    }
+
+    /// Makes a palette for the RGB PPUs (2C03, 2C04, 2C05), where the
+    /// emphasis bits turn their channel fully on instead of darkening.
+    pub fn rgb(colors: &[[u8; 3]; 64]) -> Self {
+        let colors = (0..COLOR_COUNT)
+            .map(|pixel| {
+                let mut color = colors[pixel & 0x3F];
+                for (channel, value) in color.iter_mut().enumerate() {
+                    if (pixel >> 6) & (1 << channel) != 0 {
+                        *value = 0xFF;
+                    }
+                }
+                color
+            })
+            .collect();
+
+        Self { colors }
+    }
+
+    /// The colours of the 2C03 and 2C05 Vs. System PPUs.
+    pub fn rgb_ppu() -> Self {
+        Self::rgb(&rgb_ppu_colors())
+    }
+
+    pub fn builtin(builtin: Builtin) -> Self {
+        match builtin.settings() {
+            Some(settings) => Self::generate(&settings),
+            None => Self::new(&rgb_ppu_colors()),
+        }
+    }
+
+    /// Reads a `.pal` file of 64 or 512 RGB triples.
+    pub fn from_pal(bytes: &[u8]) -> Result<Self, PaletteError> {
+        if bytes.len() != 64 * 3 && bytes.len() != COLOR_COUNT * 3 {
+            return Err(PaletteError::InvalidSize(bytes.len()));
+        }
+
+        let colors: Vec<[u8; 3]> = bytes.chunks_exact(3).map(|rgb| [rgb[0], rgb[1], rgb[2]]).collect();
+        match <&[[u8; 3]; 64]>::try_from(colors.as_slice()) {
+            Ok(colors) => Ok(Self::new(colors)),
+            // The size was checked, so this is all 512.
+            Err(_) => Ok(Self { colors }),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PaletteError> {
+        Self::from_pal(&fs::read(path)?)
+    }
+
+    /// Generates all 512 colours by decoding 12 samples of the composite
+    /// signal the PPU puts out for each one.
+    pub fn generate(settings: &NtscSettings) -> Self {
+        let colors = (0..COLOR_COUNT)
+            .map(|pixel| {
+                let (mut y, mut i, mut q) = (0.0, 0.0, 0.0);
+                for phase in 0..12 {
+                    let signal = composite_signal(pixel, phase);
+                    let angle = subcarrier_angle(phase, settings);
+                    y += signal;
+                    i += signal * angle.cos();
+                    q += signal * angle.sin();
+                }
+
+                yiq_to_rgb(y / 12.0, i / 12.0, q / 12.0, settings)
+            })
+            .collect();
+
+        Self { colors }
+    }
+
+    /// All 512 colours, indexed by the PPU's 9 bit pixels; for `framebuffer::convert`.
+    pub fn colors(&self) -> &[[u8; 3]] {
+        &self.colors
+    }
+}
+
+/// The level of the 2C02's video signal for a 9 bit pixel at one of the 12
+/// phases of the colour subcarrier, from 0.0 for black to 1.0 for white.
+pub(super) fn composite_signal(pixel: usize, phase: usize) -> f32 {
+    let color = pixel & 0x0F;
+    let level = if color > 13 { 1 } else { (pixel >> 4) & 0x03 };
+    let emphasis = pixel >> 6;
+
+    // Colour 0 stays high, 13 to 15 stay low, the rest are a square wave
+    // between the two in the colour's phase.
+    let mut low = SIGNAL_LEVELS[level];
+    let mut high = SIGNAL_LEVELS[4 + level];
+    if color == 0 {
+        low = high;
+    }
+    if color > 12 {
+        high = low;
+    }
+
+    let in_phase = |color: usize| (color + phase) % 12 < 6;
+    let mut signal = if in_phase(color) { high } else { low };
+    if (emphasis & 0x01 != 0 && in_phase(0))
+        || (emphasis & 0x02 != 0 && in_phase(4))
+        || (emphasis & 0x04 != 0 && in_phase(8))
+    {
+        signal *= EMPHASIS_ATTENUATION;
+    }
+
+    (signal - SIGNAL_BLACK) / (SIGNAL_WHITE - SIGNAL_BLACK)
+}
+
+/// Angle of the subcarrier a decoder compares `phase` against.
+pub(super) fn subcarrier_angle(phase: usize, settings: &NtscSettings) -> f32 {
+    PI / 6.0 * (phase as f32 + HUE_PHASE + settings.hue / 30.0)
+}
+
+/// Converts decoded YIQ to RGB with the picture controls applied.
+pub(super) fn yiq_to_rgb(y: f32, i: f32, q: f32, settings: &NtscSettings) -> [u8; 3] {
+    let i = i * settings.saturation;
+    let q = q * settings.saturation;
+
+    let rgb = [
+        y + 0.946882 * i + 0.623557 * q,
+        y - 0.274788 * i - 0.635691 * q,
+        y - 1.108545 * i + 1.709007 * q,
+    ];
+    rgb.map(|value| {
+        let value = value * settings.contrast + settings.brightness;
+        let value = value.max(0.0).powf(2.2 / settings.gamma);
+        (value * 255.0).round().clamp(0.0, 255.0) as u8
+    })
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::builtin(Builtin::Composite)
+    }
+}
@@ -0,0 +1,86 @@
+use crate::cartridge::{VsHardware, VsPpu};
+
+/// How long a coin keeps its input active, in CPU cycles (about 3 frames).
+/// Games that see a shorter pulse don't always count it.
+const COIN_CYCLES: u32 = 3 * 29781;
+
+/// The cabinet side of a Vs. System: DIP switches, coin slots and the
+/// service button, read through $4016 and $4017 alongside the controllers.
+pub struct VsSystem {
+    pub ppu: VsPpu,
+    pub hardware: VsHardware,
+    /// Switches 1 to 8 in bits 0 to 7.
+    pub dip_switches: u8,
+    pub service_button: bool,
+    /// Set on the second CPU of a dual system.
+    pub secondary: bool,
+
+    coin_timers: [u32; 2],
+    coin_counter: bool,
+    chr_select: bool,
+}
+
+impl VsSystem {
+    pub fn new(ppu: VsPpu, hardware: VsHardware) -> Self {
+        Self {
+            ppu,
+            hardware,
+            dip_switches: 0,
+            service_button: false,
+            secondary: false,
+            coin_timers: [0; 2],
+            coin_counter: false,
+            chr_select: false,
+        }
+    }
+
+    /// Drops a coin into `slot` (0 or 1).
+    pub fn insert_coin(&mut self, slot: usize) {
+        if let Some(timer) = self.coin_timers.get_mut(slot) {
+            *timer = COIN_CYCLES;
+        }
+    }
+
+    /// Whether the game is driving the coin counter's solenoid.
+    pub fn coin_counter(&self) -> bool {
+        self.coin_counter
+    }
+
+    /// The CHR bank select line ($4016 bit 2), used by mapper 99 boards.
+    pub fn chr_select(&self) -> bool {
+        self.chr_select
+    }
+
+    /// Bits the cabinet drives on $4016 and $4017; bit 0 is left to the controllers.
+    /// ```text
+    /// $4016: PCCD DS0. P secondary CPU, C coins 2/1, D DIP 2/1, S service
+    /// $4017: DDDD DD0. DIP switches 8-3
+    /// ```
+    pub fn read(&self, address: u16) -> Option<u8> {
+        match address {
+            0x4016 => Some(
+                (self.service_button as u8) << 2
+                    | (self.dip_switches & 0x03) << 3
+                    | ((self.coin_timers[0] > 0) as u8) << 5
+                    | ((self.coin_timers[1] > 0) as u8) << 6
+                    | (self.secondary as u8) << 7,
+            ),
+            0x4017 => Some(self.dip_switches & 0xFC),
+            _ => None,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, data: u8) {
+        match address {
+            0x4016 => self.chr_select = data & 0x04 != 0,
+            0x4020 => self.coin_counter = data & 0x01 != 0,
+            _ => {}
+        }
+    }
+
+    pub fn clock(&mut self) {
+        for timer in &mut self.coin_timers {
+            *timer = timer.saturating_sub(1);
+        }
+    }
+}